@@ -0,0 +1,119 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::{
+    hotkeys::letter_key_code,
+    player::{Owner, Players},
+    stats::Modifier,
+    units::UnitDefinitions,
+};
+
+const ABILITY_DEFINITIONS: &str = include_str!("../../assets/data/abilities.yaml");
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AbilityDefinition {
+    pub name: String,
+    pub targeting: TargetingMode,
+    /// Energy the caster spends on every cast
+    #[serde(default)]
+    pub energy: f32,
+    /// Seconds before the caster can use the ability again
+    pub cooldown: f32,
+    /// Distance from which the caster can reach its target
+    #[serde(default)]
+    pub range: f32,
+    /// Radius around the target point in which area abilities take effect
+    #[serde(default)]
+    pub radius: f32,
+    /// Units the effects apply to, in relation to the caster
+    #[serde(default)]
+    pub affects: Affects,
+    /// Letter that uses the ability while a unit that has it is selected
+    pub hotkey: Option<char>,
+    /// Applied in order to every affected unit
+    pub effects: Vec<AbilityEffect>,
+}
+
+impl AbilityDefinition {
+    pub fn hotkey(&self) -> Option<KeyCode> {
+        self.hotkey.and_then(letter_key_code)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetingMode {
+    /// Cast right away on the caster itself
+    None,
+    /// Cast on a point on the ground
+    Point,
+    /// Cast on a single unit or building
+    Unit,
+    /// Cast on every unit around a point on the ground
+    Area,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Affects {
+    Enemies,
+    Allies,
+    #[default]
+    All,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum AbilityEffect {
+    /// Takes health away, ignoring armor
+    Damage { amount: f32 },
+    /// Gives health back, up to the maximum
+    Heal { amount: f32 },
+    /// Changes a stat for the given number of seconds, or for good without one
+    Modify {
+        modifier: Modifier,
+        duration: Option<f32>,
+    },
+    /// Moves the caster to the target point
+    Teleport,
+}
+
+impl Affects {
+    pub fn includes(self, caster: Owner, owner: Owner, players: &Players) -> bool {
+        match self {
+            Affects::Enemies => !players.are_allies(caster, owner),
+            Affects::Allies => players.are_allies(caster, owner),
+            Affects::All => true,
+        }
+    }
+}
+
+/// Every ability units can have, read from `assets/data/abilities.yaml`
+#[derive(Resource, Debug)]
+pub struct AbilityDefinitions(HashMap<String, AbilityDefinition>);
+
+impl FromWorld for AbilityDefinitions {
+    fn from_world(world: &mut World) -> Self {
+        let definitions: HashMap<String, AbilityDefinition> =
+            serde_yaml::from_str(ABILITY_DEFINITIONS).expect("invalid ability definitions");
+        for (kind, unit) in world.resource::<UnitDefinitions>().iter() {
+            for ability in &unit.abilities {
+                assert!(
+                    definitions.contains_key(ability),
+                    "unit {:?} has unknown ability {}",
+                    kind,
+                    ability
+                );
+            }
+        }
+
+        Self(definitions)
+    }
+}
+
+impl AbilityDefinitions {
+    pub fn get(&self, id: &str) -> &AbilityDefinition {
+        &self.0[id]
+    }
+}
@@ -0,0 +1,433 @@
+mod definitions;
+mod targeting;
+
+use std::cmp::Ordering;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    combat::distance_to,
+    command::{CastTarget, PlayerCommand},
+    health::Health,
+    navigation::Footprint,
+    player::{LocalPlayer, Owner, Players},
+    research::{ResearchDefinitions, ResearchState},
+    selection::Selectable,
+    simulation::{SimulationEventApp, SimulationTime, StableId, StepSet},
+    snapshot::SnapshotApp,
+    stats::{ModifierSource, Stats},
+    units::{Unit, UnitKind, UnitState},
+    GameState,
+};
+
+use self::targeting::AbilityTargetingPlugin;
+
+pub use self::{
+    definitions::{AbilityDefinitions, AbilityEffect, TargetingMode},
+    targeting::AbilityTargeting,
+};
+
+pub struct AbilityPlugin;
+
+/// Using abilities from the command card, hotkeys and targeting
+pub struct AbilityInputPlugin;
+
+/// Energy every unit regains per second
+const ENERGY_REGENERATION: f32 = 0.75;
+
+/// Spent on abilities, refills over time
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct Energy {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Energy {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+}
+
+/// Abilities a unit knows and the seconds until each of them can be used again
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct Abilities {
+    pub known: Vec<String>,
+    cooldowns: HashMap<String, f32>,
+}
+
+impl Abilities {
+    pub fn new(known: Vec<String>) -> Self {
+        Self {
+            known,
+            cooldowns: HashMap::default(),
+        }
+    }
+
+    pub fn has(&self, ability: &str) -> bool {
+        self.known.iter().any(|known| known == ability)
+    }
+
+    pub fn is_ready(&self, ability: &str) -> bool {
+        !self.cooldowns.contains_key(ability)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, FromReflect)]
+pub enum AbilityTarget {
+    None,
+    /// Point on the ground, for point and area abilities
+    Point(Vec3),
+    Unit(Entity),
+}
+
+/// Uses an ability with the selected units of the local player
+pub struct ActivateAbility(pub String);
+
+/// A unit used an ability, its effects are applied right after
+pub struct AbilityCast {
+    pub caster: Entity,
+    pub ability: String,
+    pub target: AbilityTarget,
+}
+
+/// Abilities the selected units of the local player can use, in command card order
+#[derive(Resource, Debug, Default, PartialEq)]
+pub struct AvailableAbilities(pub Vec<String>);
+
+impl Plugin for AbilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<Energy>()
+            .add_snapshot_component::<Abilities>()
+            .register_type::<AbilityTarget>()
+            .register_type::<Vec<String>>()
+            .register_type::<HashMap<String, f32>>()
+            .init_resource::<AbilityDefinitions>()
+            .add_simulation_event::<AbilityCast>()
+            .add_systems(
+                (
+                    regenerate_energy,
+                    cool_down_abilities,
+                    cast_abilities,
+                    apply_ability_effects,
+                )
+                    .chain()
+                    .in_set(StepSet::Abilities)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+impl Plugin for AbilityInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AvailableAbilities>()
+            .add_event::<ActivateAbility>()
+            .add_systems(
+                (
+                    collect_available_abilities,
+                    activate_from_hotkeys,
+                    activate_abilities,
+                )
+                    .chain()
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_plugin(AbilityTargetingPlugin);
+    }
+}
+
+/// Units of the local player that are selected and may use the ability
+fn can_use(
+    (selectable, owner, kind, abilities): (&Selectable, &Owner, &UnitKind, &Abilities),
+    ability: &str,
+    local_player: &LocalPlayer,
+    research: &ResearchState,
+    research_definitions: &ResearchDefinitions,
+) -> bool {
+    selectable.is_selected
+        && owner.0 == local_player.0
+        && abilities.has(ability)
+        && research.is_ability_unlocked(research_definitions, *owner, *kind, ability)
+}
+
+fn regenerate_energy(mut units: Query<&mut Energy>, time: Res<SimulationTime>) {
+    for mut energy in &mut units {
+        if energy.current < energy.max {
+            energy.current =
+                (energy.current + ENERGY_REGENERATION * time.delta_seconds()).min(energy.max);
+        }
+    }
+}
+
+fn cool_down_abilities(mut units: Query<&mut Abilities>, time: Res<SimulationTime>) {
+    for mut abilities in &mut units {
+        if abilities.cooldowns.is_empty() {
+            continue;
+        }
+
+        let delta = time.delta_seconds();
+        abilities.cooldowns.retain(|_, remaining| {
+            *remaining -= delta;
+            *remaining > 0.0
+        });
+    }
+}
+
+fn collect_available_abilities(
+    units: Query<(&Selectable, &Owner, &UnitKind, &Abilities)>,
+    local_player: Res<LocalPlayer>,
+    research: Res<ResearchState>,
+    research_definitions: Res<ResearchDefinitions>,
+    mut available: ResMut<AvailableAbilities>,
+) {
+    let mut collected = AvailableAbilities::default();
+    for unit in &units {
+        for ability in &unit.3.known {
+            if !collected.0.contains(ability)
+                && can_use(
+                    unit,
+                    ability,
+                    &local_player,
+                    &research,
+                    &research_definitions,
+                )
+            {
+                collected.0.push(ability.clone());
+            }
+        }
+    }
+
+    // Only changed when needed, so that the command card is not rebuilt every frame
+    if *available != collected {
+        *available = collected;
+    }
+}
+
+fn activate_from_hotkeys(
+    available: Res<AvailableAbilities>,
+    definitions: Res<AbilityDefinitions>,
+    input: Res<Input<KeyCode>>,
+    mut writer: EventWriter<ActivateAbility>,
+) {
+    for ability in &available.0 {
+        if definitions
+            .get(ability)
+            .hotkey()
+            .map(|key| input.just_pressed(key))
+            .unwrap_or(false)
+        {
+            writer.send(ActivateAbility(ability.clone()));
+        }
+    }
+}
+
+/// Abilities without a target are cast by every selected unit, the others need a target first
+#[allow(clippy::too_many_arguments)]
+fn activate_abilities(
+    mut commands: Commands,
+    mut reader: EventReader<ActivateAbility>,
+    units: Query<(&StableId, &Selectable, &Owner, &UnitKind, &Abilities)>,
+    definitions: Res<AbilityDefinitions>,
+    local_player: Res<LocalPlayer>,
+    research: Res<ResearchState>,
+    research_definitions: Res<ResearchDefinitions>,
+    mut writer: EventWriter<PlayerCommand>,
+) {
+    for ActivateAbility(ability) in reader.iter() {
+        if definitions.get(ability).targeting != TargetingMode::None {
+            commands.insert_resource(AbilityTargeting {
+                ability: ability.clone(),
+            });
+            continue;
+        }
+
+        let casters: Vec<StableId> = units
+            .iter()
+            .filter(|(_, selectable, owner, kind, abilities)| {
+                can_use(
+                    (selectable, owner, kind, abilities),
+                    ability,
+                    &local_player,
+                    &research,
+                    &research_definitions,
+                )
+            })
+            .map(|(id, ..)| *id)
+            .collect();
+        if !casters.is_empty() {
+            writer.send(PlayerCommand::Cast {
+                units: casters,
+                ability: ability.clone(),
+                target: CastTarget::None,
+                queued: false,
+            });
+        }
+    }
+}
+
+/// Picks the selected unit that should cast a targeted ability, preferring ready ones with the most energy
+fn choose_caster<'a>(
+    candidates: impl Iterator<Item = (Entity, &'a Abilities, Option<&'a Energy>)>,
+    ability: &str,
+    cost: f32,
+) -> Option<Entity> {
+    candidates
+        .map(|(entity, abilities, energy)| {
+            let energy = energy.map_or(0.0, |energy| energy.current);
+            (
+                entity,
+                (abilities.is_ready(ability) && energy >= cost, energy),
+            )
+        })
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        .map(|(entity, _)| entity)
+}
+
+/// Walks casting units into range of their target and uses the ability once they are there
+#[allow(clippy::type_complexity)]
+fn cast_abilities(
+    mut casters: Query<(
+        &StableId,
+        Entity,
+        &mut Unit,
+        &Transform,
+        &mut Abilities,
+        Option<&mut Energy>,
+        &Owner,
+    )>,
+    targets: Query<(&Transform, Option<&Footprint>, With<Health>)>,
+    definitions: Res<AbilityDefinitions>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<AbilityCast>,
+) {
+    // Effects are applied in the order of the casts, which is kept by caster id
+    let mut casters: Vec<_> = casters.iter_mut().collect();
+    casters.sort_by_key(|(id, ..)| **id);
+
+    for (_, entity, mut unit, transform, mut abilities, energy, owner) in casters {
+        let UnitState::Casting {
+            ability, target, ..
+        } = &unit.state
+        else {
+            continue;
+        };
+
+        let (ability, target) = (ability.clone(), *target);
+        let definition = definitions.get(&ability);
+        let (target_position, distance) = match target {
+            AbilityTarget::None => (transform.translation, 0.0),
+            AbilityTarget::Point(point) => (point, distance_to(transform, point, None)),
+            AbilityTarget::Unit(target) => match targets.get(target) {
+                Ok((target_transform, footprint, _)) => (
+                    target_transform.translation,
+                    distance_to(transform, target_transform.translation, footprint),
+                ),
+                Err(_) => {
+                    // Dead
+                    unit.state = UnitState::Idle;
+                    continue;
+                }
+            },
+        };
+
+        if distance > definition.range {
+            unit.state = UnitState::Casting {
+                ability,
+                target,
+                position: target_position,
+            };
+            continue;
+        }
+
+        unit.state = UnitState::Idle;
+        let has_energy = energy.as_ref().map_or(definition.energy <= 0.0, |energy| {
+            energy.current >= definition.energy
+        });
+        if !abilities.is_ready(&ability) || !has_energy {
+            if owner.0 == local_player.0 {
+                info!("{} is not ready", definition.name);
+            }
+            continue;
+        }
+
+        if let Some(mut energy) = energy {
+            energy.current -= definition.energy;
+        }
+        abilities
+            .cooldowns
+            .insert(ability.clone(), definition.cooldown);
+        writer.send(AbilityCast {
+            caster: entity,
+            ability,
+            target,
+        });
+    }
+}
+
+fn apply_ability_effects(
+    mut reader: EventReader<AbilityCast>,
+    mut units: Query<(
+        Entity,
+        &mut Transform,
+        &Owner,
+        &mut Health,
+        Option<&mut Stats>,
+    )>,
+    definitions: Res<AbilityDefinitions>,
+    players: Res<Players>,
+) {
+    for event in reader.iter() {
+        let Ok((_, caster_transform, caster_owner, ..)) = units.get(event.caster) else {
+            continue;
+        };
+
+        let caster_owner = *caster_owner;
+        let definition = definitions.get(&event.ability);
+        let affected: Vec<Entity> = match event.target {
+            AbilityTarget::None => vec![event.caster],
+            AbilityTarget::Unit(target) => vec![target],
+            AbilityTarget::Point(point) => units
+                .iter()
+                .filter(|(_, transform, ..)| {
+                    definition.radius > 0.0
+                        && distance_to(transform, point, None) <= definition.radius
+                })
+                .map(|(entity, ..)| entity)
+                .collect(),
+        };
+        let caster_position = caster_transform.translation;
+
+        for effect in &definition.effects {
+            if let (AbilityEffect::Teleport, AbilityTarget::Point(point)) = (effect, event.target) {
+                if let Ok((_, mut transform, ..)) = units.get_mut(event.caster) {
+                    // The height is corrected by following the terrain
+                    transform.translation = Vec3::new(point.x, caster_position.y, point.z);
+                }
+                continue;
+            }
+
+            for entity in &affected {
+                let Ok((_, _, owner, mut health, stats)) = units.get_mut(*entity) else {
+                    continue;
+                };
+
+                if !definition.affects.includes(caster_owner, *owner, &players) {
+                    continue;
+                }
+
+                match effect {
+                    AbilityEffect::Damage { amount } => health.current -= amount,
+                    AbilityEffect::Heal { amount } => {
+                        health.current = (health.current + amount).min(health.max)
+                    }
+                    AbilityEffect::Modify { modifier, duration } => {
+                        if let Some(mut stats) = stats {
+                            stats.add_modifier(*modifier, ModifierSource::Ability, *duration);
+                        }
+                    }
+                    AbilityEffect::Teleport => {}
+                }
+            }
+        }
+    }
+}
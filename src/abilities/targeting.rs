@@ -0,0 +1,218 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_rapier3d::prelude::RapierContext;
+
+use crate::{
+    command::{CastTarget, PlayerCommand},
+    cursor::{get_entity_under_cursor, CursorPosition},
+    health::Health,
+    hud::is_cursor_over_hud,
+    order::is_queueing,
+    player::{LocalPlayer, Owner, Players},
+    research::{ResearchDefinitions, ResearchState},
+    selection::Selectable,
+    simulation::StableId,
+    units::UnitKind,
+    GameState,
+};
+
+use super::{can_use, choose_caster, Abilities, AbilityDefinitions, Energy, TargetingMode};
+
+/// Lifts the area preview above the ground so that it is not hidden by the terrain
+const PREVIEW_HEIGHT: f32 = 0.05;
+const PREVIEW_COLOR: Color = Color::rgba(0.3, 0.6, 1.0, 0.3);
+
+pub struct AbilityTargetingPlugin;
+
+/// Ability waiting for the player to pick its target with the cursor
+#[derive(Resource, Debug)]
+pub struct AbilityTargeting {
+    pub ability: String,
+}
+
+/// Circle on the ground under the cursor, showing where an area ability takes effect
+#[derive(Component)]
+struct AreaPreview;
+
+#[derive(Resource)]
+struct AreaPreviewAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for AreaPreviewAssets {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+
+        Self {
+            mesh: meshes.add(shape::Circle::new(1.0).into()),
+            material: materials.add(StandardMaterial {
+                base_color: PREVIEW_COLOR,
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+        }
+    }
+}
+
+impl Plugin for AbilityTargetingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AreaPreviewAssets>()
+            .add_systems(
+                (
+                    move_area_preview.run_if(resource_exists::<CursorPosition>()),
+                    confirm_target
+                        .run_if(resource_exists::<CursorPosition>())
+                        .run_if(not(is_cursor_over_hud)),
+                    cancel_targeting,
+                )
+                    .chain()
+                    .distributive_run_if(resource_exists::<AbilityTargeting>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(
+                despawn_area_preview
+                    .run_if(not(resource_exists::<AbilityTargeting>()))
+                    .run_if(any_with_component::<AreaPreview>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+fn move_area_preview(
+    mut commands: Commands,
+    mut previews: Query<(Entity, &mut Transform, With<AreaPreview>)>,
+    targeting: Res<AbilityTargeting>,
+    definitions: Res<AbilityDefinitions>,
+    assets: Res<AreaPreviewAssets>,
+    cursor: Res<CursorPosition>,
+) {
+    let definition = definitions.get(&targeting.ability);
+    if definition.targeting != TargetingMode::Area {
+        for (entity, ..) in &previews {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+
+    let transform = Transform::from_translation(cursor.0 + Vec3::Y * PREVIEW_HEIGHT)
+        .with_rotation(Quat::from_rotation_x(-FRAC_PI_2))
+        .with_scale(Vec3::splat(definition.radius));
+    match previews.get_single_mut() {
+        Ok((_, mut preview, _)) => *preview = transform,
+        Err(_) => {
+            commands.spawn((
+                PbrBundle {
+                    mesh: assets.mesh.clone(),
+                    material: assets.material.clone(),
+                    transform,
+                    ..default()
+                },
+                Name::from("Area Preview"),
+                AreaPreview,
+            ));
+        }
+    }
+}
+
+fn despawn_area_preview(mut commands: Commands, previews: Query<(Entity, With<AreaPreview>)>) {
+    for (entity, _) in &previews {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Orders the best suited selected unit to cast the ability on the target under the cursor
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn confirm_target(
+    mut commands: Commands,
+    window: Query<(&Window, With<PrimaryWindow>)>,
+    camera: Query<(&Camera, &GlobalTransform, With<Camera3d>)>,
+    casters: Query<(
+        Entity,
+        &StableId,
+        &Selectable,
+        &Owner,
+        &UnitKind,
+        &Abilities,
+        Option<&Energy>,
+    )>,
+    targets: Query<(&StableId, &Owner, &Visibility, With<Health>)>,
+    targeting: Res<AbilityTargeting>,
+    definitions: Res<AbilityDefinitions>,
+    cursor: Res<CursorPosition>,
+    rapier_context: Res<RapierContext>,
+    input: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+    players: Res<Players>,
+    research: Res<ResearchState>,
+    research_definitions: Res<ResearchDefinitions>,
+    mut writer: EventWriter<PlayerCommand>,
+) {
+    // Released rather than pressed, so that the click does not change the selection
+    if !input.just_released(MouseButton::Left) {
+        return;
+    }
+
+    let definition = definitions.get(&targeting.ability);
+    let target = match definition.targeting {
+        TargetingMode::Unit => {
+            let (window, _) = window.single();
+            let (camera, camera_transform, _) = camera.single();
+            let target = get_entity_under_cursor(window, camera, camera_transform, &rapier_context);
+            match target.and_then(|entity| targets.get(entity).ok()) {
+                // Units hidden by the fog can not be targeted
+                Some((id, owner, visibility, _))
+                    if visibility != Visibility::Hidden
+                        && definition
+                            .affects
+                            .includes(Owner(local_player.0), *owner, &players) =>
+                {
+                    CastTarget::Unit(*id)
+                }
+                // Keep targeting until a valid target is clicked
+                _ => return,
+            }
+        }
+        _ => CastTarget::Point(cursor.0),
+    };
+
+    let candidates = casters
+        .iter()
+        .filter(|(_, _, selectable, owner, kind, abilities, _)| {
+            can_use(
+                (selectable, owner, kind, abilities),
+                &targeting.ability,
+                &local_player,
+                &research,
+                &research_definitions,
+            )
+        })
+        .map(|(entity, _, _, _, _, abilities, energy)| (entity, abilities, energy));
+    let caster = choose_caster(candidates, &targeting.ability, definition.energy)
+        .and_then(|caster| casters.get(caster).ok());
+    if let Some((_, id, ..)) = caster {
+        writer.send(PlayerCommand::Cast {
+            units: vec![*id],
+            ability: targeting.ability.clone(),
+            target,
+            queued: is_queueing(&keys),
+        });
+    }
+
+    commands.remove_resource::<AbilityTargeting>();
+}
+
+fn cancel_targeting(
+    mut commands: Commands,
+    mouse_input: Res<Input<MouseButton>>,
+    key_input: Res<Input<KeyCode>>,
+) {
+    if mouse_input.just_pressed(MouseButton::Right) || key_input.just_pressed(KeyCode::Escape) {
+        commands.remove_resource::<AbilityTargeting>();
+    }
+}
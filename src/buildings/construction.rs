@@ -0,0 +1,162 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    command::PlayerCommand,
+    economy::Worker,
+    ground::Terrain,
+    health::Health,
+    navigation::Footprint,
+    player::{LocalPlayer, Owner},
+    selection::Selectable,
+    simulation::{SimulationEventApp, SimulationTime, StableId, StepSet},
+    snapshot::SnapshotApp,
+    units::{approach_point, has_arrived, Unit, UnitState},
+    GameState,
+};
+
+use super::{activate, Building, BuildingDefinitions, BuildingKind};
+
+/// Part of the full health a building site starts with
+pub const STARTING_HEALTH: f32 = 0.1;
+/// Height a building site is drawn with before any work was done, as part of the full height
+pub const STARTING_SCALE: f32 = 0.05;
+/// Part of the cost given back when a building site is cancelled
+pub const CANCEL_REFUND: f32 = 0.75;
+const CANCEL_KEY: KeyCode = KeyCode::Delete;
+
+pub struct ConstructionPlugin;
+
+pub struct ConstructionInputPlugin;
+
+/// Building that is not finished yet
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct Construction {
+    /// From 0 when the site is placed to 1 when the building is finished
+    pub progress: f32,
+}
+
+pub struct ConstructionComplete {
+    pub building: Entity,
+    pub kind: BuildingKind,
+}
+
+impl Plugin for ConstructionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<Construction>()
+            .add_simulation_event::<ConstructionComplete>()
+            .add_systems(
+                (
+                    construct_buildings.run_if(resource_exists::<Terrain>()),
+                    activate_completed_buildings,
+                )
+                    .chain()
+                    .in_set(StepSet::Construction)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+impl Plugin for ConstructionInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(cancel_construction.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// State that walks a worker to the building site and constructs it
+pub fn construct(site: Entity, site_position: Vec3, footprint: Footprint, from: Vec3) -> UnitState {
+    UnitState::Constructing {
+        site,
+        position: approach_point(site_position, footprint.size().max_element(), from),
+    }
+}
+
+/// Advances every site by the number of workers standing next to it
+fn construct_buildings(
+    mut commands: Commands,
+    mut sites: Query<(
+        Entity,
+        &Building,
+        &mut Construction,
+        &mut Transform,
+        &mut Health,
+    )>,
+    mut workers: Query<(&mut Unit, &Transform, With<Worker>, Without<Building>)>,
+    definitions: Res<BuildingDefinitions>,
+    terrain: Res<Terrain>,
+    time: Res<SimulationTime>,
+    mut writer: EventWriter<ConstructionComplete>,
+) {
+    let mut builders: HashMap<Entity, u32> = HashMap::default();
+    for (mut unit, transform, _, _) in &mut workers {
+        let UnitState::Constructing { site, position } = unit.state else {
+            continue;
+        };
+
+        if !sites.contains(site) {
+            // Finished or cancelled
+            unit.state = UnitState::Idle;
+        } else if has_arrived(transform, position) {
+            *builders.entry(site).or_default() += 1;
+        }
+    }
+
+    for (entity, building, mut construction, mut transform, mut health) in &mut sites {
+        let Some(count) = builders.get(&entity) else {
+            continue;
+        };
+
+        let definition = definitions.get(building.kind);
+        let step = (time.delta_seconds() * *count as f32 / definition.build_time)
+            .min(1.0 - construction.progress);
+        construction.progress += step;
+        health.current =
+            (health.current + health.max * (1.0 - STARTING_HEALTH) * step).min(health.max);
+
+        // The site grows out of the ground
+        let scale = construction.progress.max(STARTING_SCALE);
+        let ground = terrain.height_at(transform.translation.x, transform.translation.z);
+        transform.scale.y = scale;
+        transform.translation.y = ground + definition.height * scale / 2.0;
+
+        if construction.progress >= 1.0 {
+            commands.entity(entity).remove::<Construction>();
+            writer.send(ConstructionComplete {
+                building: entity,
+                kind: building.kind,
+            });
+        }
+    }
+}
+
+fn activate_completed_buildings(
+    mut commands: Commands,
+    mut reader: EventReader<ConstructionComplete>,
+    definitions: Res<BuildingDefinitions>,
+) {
+    for event in reader.iter() {
+        if let Some(mut building) = commands.get_entity(event.building) {
+            activate(&mut building, definitions.get(event.kind));
+        }
+    }
+}
+
+fn cancel_construction(
+    sites: Query<(&StableId, &Selectable, &Owner, With<Construction>)>,
+    input: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<PlayerCommand>,
+) {
+    if !input.just_pressed(CANCEL_KEY) {
+        return;
+    }
+
+    let sites: Vec<StableId> = sites
+        .iter()
+        .filter(|(_, selectable, owner, _)| selectable.is_selected && owner.0 == local_player.0)
+        .map(|(id, ..)| *id)
+        .collect();
+    if !sites.is_empty() {
+        writer.send(PlayerCommand::CancelConstruction { sites });
+    }
+}
@@ -0,0 +1,78 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::{economy::Cost, hotkeys::letter_key_code, navigation::Footprint, units::UnitKind};
+
+use super::BuildingKind;
+
+const BUILDING_DEFINITIONS: &str = include_str!("../../assets/data/buildings.yaml");
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildingDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub cost: Cost,
+    /// Width and depth in navigation cells
+    pub footprint: [u32; 2],
+    pub height: f32,
+    pub health: f32,
+    /// Subtracted from the damage of every hit the building takes
+    #[serde(default)]
+    pub armor: f32,
+    /// Seconds a single worker needs to construct the building
+    pub build_time: f32,
+    pub sight_range: f32,
+    /// Workers of the owner can bring resources here
+    #[serde(default)]
+    pub dropoff: bool,
+    /// Population limit the building adds for its owner once it is finished
+    #[serde(default)]
+    pub provides_supply: u32,
+    /// Units the building can train once it is finished
+    #[serde(default)]
+    pub trains: Vec<UnitKind>,
+    /// Letter that starts placing the building while a worker is selected
+    pub hotkey: Option<char>,
+}
+
+impl BuildingDefinition {
+    pub fn footprint(&self) -> Footprint {
+        let [width, depth] = self.footprint;
+        Footprint { width, depth }
+    }
+
+    pub fn hotkey(&self) -> Option<KeyCode> {
+        self.hotkey.and_then(letter_key_code)
+    }
+}
+
+/// Stats of every building kind, read from `assets/data/buildings.yaml`
+#[derive(Resource, Debug)]
+pub struct BuildingDefinitions(HashMap<BuildingKind, BuildingDefinition>);
+
+impl Default for BuildingDefinitions {
+    fn default() -> Self {
+        let definitions: HashMap<BuildingKind, BuildingDefinition> =
+            serde_yaml::from_str(BUILDING_DEFINITIONS).expect("invalid building definitions");
+        for kind in BuildingKind::ALL {
+            assert!(
+                definitions.contains_key(&kind),
+                "building {:?} has no definition",
+                kind
+            );
+        }
+
+        Self(definitions)
+    }
+}
+
+impl BuildingDefinitions {
+    pub fn get(&self, kind: BuildingKind) -> &BuildingDefinition {
+        &self.0[&kind]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BuildingKind, &BuildingDefinition)> {
+        self.0.iter().map(|(kind, definition)| (*kind, definition))
+    }
+}
@@ -0,0 +1,225 @@
+mod construction;
+mod definitions;
+mod placement;
+mod production;
+
+use bevy::{ecs::system::EntityCommands, prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::{Collider, RigidBody};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    economy::Dropoff,
+    fog::SightRange,
+    ground::PlaceOnTerrain,
+    health::Health,
+    order::{Order, Orders},
+    player::{Owner, Players},
+    selection::Selectable,
+    simulation::{SimulationEventApp, SpawnSet, StableIds},
+    snapshot::SnapshotApp,
+    stats::{Stat, Stats},
+};
+
+use self::{
+    construction::{ConstructionInputPlugin, ConstructionPlugin, STARTING_HEALTH, STARTING_SCALE},
+    placement::BuildingPlacementPlugin,
+    production::{ProductionInputPlugin, ProductionPlugin},
+};
+
+pub use self::{
+    construction::{construct, Construction, CANCEL_REFUND},
+    definitions::{BuildingDefinition, BuildingDefinitions},
+    placement::{PlacementCheck, PlacingBuilding, StartPlacement},
+    production::{CancelProduction, EnqueueUnit, ProductionQueue, RallyPoint},
+};
+
+pub struct BuildingPlugin;
+
+/// Building models in the colour of their owner
+pub struct BuildingViewPlugin;
+
+/// Placing buildings, cancelling construction and controlling production
+pub struct BuildingInputPlugin;
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect, FromReflect, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildingKind {
+    #[default]
+    Base,
+    Barracks,
+    House,
+}
+
+impl BuildingKind {
+    pub const ALL: [BuildingKind; 3] = [
+        BuildingKind::Base,
+        BuildingKind::Barracks,
+        BuildingKind::House,
+    ];
+}
+
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct Building {
+    pub kind: BuildingKind,
+}
+
+pub struct SpawnBuilding {
+    pub kind: BuildingKind,
+    pub owner: Owner,
+    pub position: Vec3,
+    /// Spawns a site that workers have to construct first
+    pub under_construction: bool,
+    /// Workers that are sent to construct the site
+    pub builders: Vec<Entity>,
+}
+
+#[derive(Resource)]
+struct BuildingMeshes(HashMap<BuildingKind, Handle<Mesh>>);
+
+impl FromWorld for BuildingMeshes {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+        let definitions = world.resource::<BuildingDefinitions>();
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+
+        Self(
+            definitions
+                .iter()
+                .map(|(kind, definition)| {
+                    let size = definition.footprint().size();
+                    let mesh = shape::Box::new(size.x, definition.height, size.y);
+                    (kind, meshes.add(mesh.into()))
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Plugin for BuildingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<Building>()
+            .register_type::<BuildingKind>()
+            .add_simulation_event::<SpawnBuilding>()
+            .init_resource::<BuildingDefinitions>()
+            .add_system(
+                spawn_buildings
+                    .in_set(SpawnSet::Buildings)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(add_building_bodies)
+            .add_plugin(ConstructionPlugin)
+            .add_plugin(ProductionPlugin);
+    }
+}
+
+impl Plugin for BuildingViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BuildingMeshes>()
+            .add_system(add_building_models);
+    }
+}
+
+impl Plugin for BuildingInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(BuildingPlacementPlugin)
+            .add_plugin(ConstructionInputPlugin)
+            .add_plugin(ProductionInputPlugin);
+    }
+}
+
+fn spawn_buildings(
+    mut commands: Commands,
+    mut reader: EventReader<SpawnBuilding>,
+    definitions: Res<BuildingDefinitions>,
+    mut orders: Query<&mut Orders>,
+    mut ids: ResMut<StableIds>,
+) {
+    for event in reader.iter() {
+        let definition = definitions.get(event.kind);
+        let scale = if event.under_construction {
+            STARTING_SCALE
+        } else {
+            1.0
+        };
+
+        let mut building = commands.spawn((
+            SpatialBundle::from_transform(
+                Transform::from_translation(event.position).with_scale(Vec3::new(1.0, scale, 1.0)),
+            ),
+            ids.next(),
+            PlaceOnTerrain(definition.height * scale / 2.0),
+            Name::from(definition.name.as_str()),
+            Building { kind: event.kind },
+            Selectable::default(),
+            definition.footprint(),
+            SightRange(definition.sight_range),
+            Stats::new([(Stat::Armor, definition.armor)]),
+            event.owner,
+        ));
+
+        if event.under_construction {
+            building.insert((
+                Construction::default(),
+                Health {
+                    current: definition.health * STARTING_HEALTH,
+                    max: definition.health,
+                },
+            ));
+        } else {
+            building.insert(Health::new(definition.health));
+            activate(&mut building, definition);
+        }
+
+        let site = building.id();
+        for builder in &event.builders {
+            if let Ok(mut orders) = orders.get_mut(*builder) {
+                orders.replace(Order::Build(site));
+            }
+        }
+    }
+}
+
+/// Collider sized by the definition, like the unit colliders it is not part of snapshots
+fn add_building_bodies(
+    mut commands: Commands,
+    buildings: Query<(Entity, &Building), Added<Building>>,
+    definitions: Res<BuildingDefinitions>,
+) {
+    for (entity, building) in &buildings {
+        let definition = definitions.get(building.kind);
+        let size = definition.footprint().size();
+        commands.entity(entity).insert((
+            Collider::cuboid(size.x / 2.0, definition.height / 2.0, size.y / 2.0),
+            RigidBody::Fixed,
+        ));
+    }
+}
+
+fn add_building_models(
+    mut commands: Commands,
+    buildings: Query<(Entity, &Building, &Owner), Added<Building>>,
+    meshes: Res<BuildingMeshes>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    players: Res<Players>,
+) {
+    for (entity, building, owner) in &buildings {
+        commands.entity(entity).insert((
+            meshes.0[&building.kind].clone(),
+            materials.add(players.color(*owner).into()),
+        ));
+    }
+}
+
+/// Adds what a building can do once it is finished
+fn activate(building: &mut EntityCommands, definition: &BuildingDefinition) {
+    if definition.dropoff {
+        building.insert(Dropoff);
+    }
+
+    if !definition.trains.is_empty() {
+        building.insert((ProductionQueue::default(), RallyPoint::default()));
+    }
+}
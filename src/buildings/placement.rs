@@ -0,0 +1,367 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    command::PlayerCommand,
+    cursor::CursorPosition,
+    economy::{ResourceNode, Stockpiles, Worker, RESOURCE_NODE_SIZE},
+    fog::{CellVisibility, VisibilityGrid},
+    ground::Terrain,
+    hud::is_cursor_over_hud,
+    map::Obstacle,
+    navigation::{Footprint, NavigationGrid, NAVIGATION_CELL_SIZE},
+    player::{LocalPlayer, Owner},
+    selection::Selectable,
+    simulation::StableId,
+    units::{Unit, UNIT_SIZE},
+    GameState,
+};
+
+use super::{BuildingDefinitions, BuildingKind, BuildingMeshes};
+
+const VALID_COLOR: Color = Color::rgba(0.2, 0.9, 0.3, 0.4);
+const INVALID_COLOR: Color = Color::rgba(0.9, 0.2, 0.2, 0.4);
+/// Shrinks the overlap test so that buildings can stand right next to each other
+const OVERLAP_MARGIN: f32 = 0.05;
+
+pub struct BuildingPlacementPlugin;
+
+/// Building the local player is choosing a place for
+#[derive(Resource)]
+pub struct PlacingBuilding {
+    pub kind: BuildingKind,
+    ghost: Entity,
+    is_valid: bool,
+}
+
+/// Enters build mode for the given building
+pub struct StartPlacement(pub BuildingKind);
+
+/// Whether a player may place a building somewhere: on free cells inside the map that they
+/// have explored, without overlapping anything. The placement ghost and the `Build` commands
+/// are checked the same way, so a site the ghost accepts is placed
+#[derive(SystemParam)]
+#[allow(clippy::type_complexity)]
+pub struct PlacementCheck<'w, 's> {
+    navigation: Option<Res<'w, NavigationGrid>>,
+    vision: Option<Res<'w, VisibilityGrid>>,
+    definitions: Res<'w, BuildingDefinitions>,
+    blockers: Query<
+        'w,
+        's,
+        (
+            &'static Transform,
+            Option<&'static Obstacle>,
+            Option<&'static Footprint>,
+            Option<&'static ResourceNode>,
+        ),
+        Or<(
+            With<Obstacle>,
+            With<Footprint>,
+            With<ResourceNode>,
+            With<Unit>,
+        )>,
+    >,
+}
+
+/// Rectangle on the ground, turned around the vertical axis
+struct GroundRect {
+    center: Vec2,
+    half_size: Vec2,
+    axes: [Vec2; 2],
+}
+
+/// Translucent preview of the building that follows the cursor
+#[derive(Component)]
+struct PlacementGhost;
+
+#[derive(Resource)]
+struct GhostMaterials {
+    valid: Handle<StandardMaterial>,
+    invalid: Handle<StandardMaterial>,
+}
+
+impl FromWorld for GhostMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let mut ghost_material = |color: Color| {
+            materials.add(StandardMaterial {
+                base_color: color,
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            })
+        };
+
+        Self {
+            valid: ghost_material(VALID_COLOR),
+            invalid: ghost_material(INVALID_COLOR),
+        }
+    }
+}
+
+impl Plugin for BuildingPlacementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StartPlacement>()
+            .init_resource::<GhostMaterials>()
+            .add_systems(
+                (
+                    start_placement_from_hotkeys,
+                    start_placement,
+                    move_ghost
+                        .run_if(resource_exists::<PlacingBuilding>())
+                        .run_if(resource_exists::<Terrain>()),
+                    confirm_placement
+                        .run_if(resource_exists::<PlacingBuilding>())
+                        .run_if(not(is_cursor_over_hud)),
+                    cancel_placement.run_if(resource_exists::<PlacingBuilding>()),
+                )
+                    .chain()
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+impl PlacementCheck<'_, '_> {
+    /// Sites placed earlier in the same tick are passed in, they are not spawned yet
+    pub fn can_place(
+        &self,
+        player: u8,
+        kind: BuildingKind,
+        center: Vec3,
+        placed: &[(Vec3, Footprint)],
+    ) -> bool {
+        let footprint = self.definitions.get(kind).footprint();
+        let is_free = self
+            .navigation
+            .as_ref()
+            .is_some_and(|navigation| navigation.is_free(center, footprint));
+        let is_explored = self.vision.as_ref().is_some_and(|vision| {
+            footprint_cell_centers(center, footprint)
+                .all(|point| vision.visibility(player, point) != CellVisibility::Unexplored)
+        });
+        if !is_free || !is_explored {
+            return false;
+        }
+
+        let site = GroundRect::new(
+            center,
+            footprint.size() / 2.0 - OVERLAP_MARGIN,
+            Quat::IDENTITY,
+        );
+        let overlaps_placed = placed.iter().any(|(position, footprint)| {
+            site.overlaps(&GroundRect::new(
+                *position,
+                footprint.size() / 2.0,
+                Quat::IDENTITY,
+            ))
+        });
+        let overlaps_blocker =
+            self.blockers
+                .iter()
+                .any(|(transform, obstacle, footprint, node)| {
+                    let half_size = match (obstacle, footprint, node) {
+                        (Some(obstacle), ..) => Vec2::new(obstacle.size.x, obstacle.size.z) / 2.0,
+                        (_, Some(footprint), _) => footprint.size() / 2.0,
+                        (.., Some(_)) => Vec2::splat(RESOURCE_NODE_SIZE / 2.0),
+                        _ => Vec2::splat(UNIT_SIZE / 2.0),
+                    };
+                    site.overlaps(&GroundRect::new(
+                        transform.translation,
+                        half_size,
+                        transform.rotation,
+                    ))
+                });
+
+        !overlaps_placed && !overlaps_blocker
+    }
+}
+
+impl GroundRect {
+    fn new(center: Vec3, half_size: Vec2, rotation: Quat) -> Self {
+        let axis = |direction: Vec3| {
+            let direction = rotation * direction;
+            Vec2::new(direction.x, direction.z).normalize_or_zero()
+        };
+
+        Self {
+            center: Vec2::new(center.x, center.z),
+            half_size,
+            axes: [axis(Vec3::X), axis(Vec3::Z)],
+        }
+    }
+
+    /// Half the length of the rectangle's shadow on the axis
+    fn extent_along(&self, axis: Vec2) -> f32 {
+        self.half_size.x * self.axes[0].dot(axis).abs()
+            + self.half_size.y * self.axes[1].dot(axis).abs()
+    }
+
+    /// Separating axis test, rectangles that only touch do not overlap
+    fn overlaps(&self, other: &GroundRect) -> bool {
+        let offset = other.center - self.center;
+        self.axes.iter().chain(&other.axes).all(|axis| {
+            offset.dot(*axis).abs() < self.extent_along(*axis) + other.extent_along(*axis)
+        })
+    }
+}
+
+fn start_placement_from_hotkeys(
+    workers: Query<(&Selectable, &Owner, With<Worker>)>,
+    definitions: Res<BuildingDefinitions>,
+    input: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<StartPlacement>,
+) {
+    let has_worker_selected = workers
+        .iter()
+        .any(|(selectable, owner, _)| selectable.is_selected && owner.0 == local_player.0);
+    if !has_worker_selected {
+        return;
+    }
+
+    for (kind, definition) in definitions.iter() {
+        if definition
+            .hotkey()
+            .map(|key| input.just_pressed(key))
+            .unwrap_or(false)
+        {
+            writer.send(StartPlacement(kind));
+        }
+    }
+}
+
+fn start_placement(
+    mut commands: Commands,
+    mut reader: EventReader<StartPlacement>,
+    placing: Option<Res<PlacingBuilding>>,
+    meshes: Res<BuildingMeshes>,
+    materials: Res<GhostMaterials>,
+) {
+    let Some(StartPlacement(kind)) = reader.iter().last() else {
+        return;
+    };
+
+    if let Some(placing) = placing {
+        commands.entity(placing.ghost).despawn_recursive();
+    }
+
+    let ghost = commands
+        .spawn((
+            PbrBundle {
+                mesh: meshes.0[kind].clone(),
+                material: materials.invalid.clone(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            Name::from("Placement Ghost"),
+            PlacementGhost,
+        ))
+        .id();
+
+    commands.insert_resource(PlacingBuilding {
+        kind: *kind,
+        ghost,
+        is_valid: false,
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn move_ghost(
+    mut placing: ResMut<PlacingBuilding>,
+    mut ghost: Query<(
+        &mut Transform,
+        &mut Visibility,
+        &mut Handle<StandardMaterial>,
+        With<PlacementGhost>,
+    )>,
+    cursor: Option<Res<CursorPosition>>,
+    navigation: Option<Res<NavigationGrid>>,
+    placement: PlacementCheck,
+    terrain: Res<Terrain>,
+    definitions: Res<BuildingDefinitions>,
+    materials: Res<GhostMaterials>,
+    stockpiles: Res<Stockpiles>,
+    local_player: Res<LocalPlayer>,
+) {
+    let Ok((mut transform, mut visibility, mut material, _)) = ghost.get_mut(placing.ghost) else {
+        return;
+    };
+
+    let (Some(cursor), Some(navigation)) = (cursor, navigation) else {
+        *visibility = Visibility::Hidden;
+        placing.is_valid = false;
+        return;
+    };
+
+    let definition = definitions.get(placing.kind);
+    let footprint = definition.footprint();
+    let mut center = navigation.snap(cursor.0, footprint);
+    center.y = terrain.height_at(center.x, center.z) + definition.height / 2.0;
+    transform.translation = center;
+    *visibility = Visibility::Inherited;
+
+    let is_affordable = stockpiles
+        .get(Owner(local_player.0))
+        .can_afford(&definition.cost);
+
+    placing.is_valid =
+        placement.can_place(local_player.0, placing.kind, center, &[]) && is_affordable;
+    *material = if placing.is_valid {
+        materials.valid.clone()
+    } else {
+        materials.invalid.clone()
+    };
+}
+
+/// Centers of the navigation cells covered by the footprint
+fn footprint_cell_centers(center: Vec3, footprint: Footprint) -> impl Iterator<Item = Vec3> {
+    let corner = center - Vec3::new(footprint.size().x, 0.0, footprint.size().y) / 2.0;
+    (0..footprint.depth).flat_map(move |row| {
+        (0..footprint.width).map(move |column| {
+            corner + Vec3::new(column as f32 + 0.5, 0.0, row as f32 + 0.5) * NAVIGATION_CELL_SIZE
+        })
+    })
+}
+
+fn confirm_placement(
+    mut commands: Commands,
+    placing: Res<PlacingBuilding>,
+    ghost: Query<(&Transform, With<PlacementGhost>)>,
+    workers: Query<(&StableId, &Selectable, &Owner, With<Worker>)>,
+    mut writer: EventWriter<PlayerCommand>,
+    input: Res<Input<MouseButton>>,
+    local_player: Res<LocalPlayer>,
+) {
+    // Released rather than pressed, so the click does not start a selection afterwards
+    if !input.just_released(MouseButton::Left) || !placing.is_valid {
+        return;
+    }
+
+    let Ok((transform, _)) = ghost.get(placing.ghost) else {
+        return;
+    };
+
+    writer.send(PlayerCommand::Build {
+        kind: placing.kind,
+        position: transform.translation,
+        builders: workers
+            .iter()
+            .filter(|(_, selectable, owner, _)| selectable.is_selected && owner.0 == local_player.0)
+            .map(|(id, ..)| *id)
+            .collect(),
+    });
+
+    commands.entity(placing.ghost).despawn_recursive();
+    commands.remove_resource::<PlacingBuilding>();
+}
+
+fn cancel_placement(
+    mut commands: Commands,
+    placing: Res<PlacingBuilding>,
+    input: Res<Input<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::Escape) {
+        commands.entity(placing.ghost).despawn_recursive();
+        commands.remove_resource::<PlacingBuilding>();
+    }
+}
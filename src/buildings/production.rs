@@ -0,0 +1,286 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::{
+    command::PlayerCommand,
+    cursor::CursorPosition,
+    economy::Stockpiles,
+    hud::is_cursor_over_hud,
+    navigation::Footprint,
+    overlays::{MarkerKind, OrderMarker},
+    player::{LocalPlayer, Owner},
+    research::Researching,
+    selection::Selectable,
+    simulation::{SimulationEventApp, SimulationRng, SimulationTime, StableId, StepSet},
+    snapshot::SnapshotApp,
+    supply::{Supplies, SupplyCapped},
+    units::{approach_point, SpawnUnit, UnitDefinitions, UnitKind},
+    GameState,
+};
+
+use super::{Building, BuildingDefinitions};
+
+const MAX_QUEUE_LENGTH: usize = 5;
+const CANCEL_KEY: KeyCode = KeyCode::Delete;
+
+pub struct ProductionPlugin;
+
+pub struct ProductionInputPlugin;
+
+/// Units a finished building is training, the first one is in progress
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct ProductionQueue {
+    pub queue: Vec<UnitKind>,
+    /// Seconds spent on the first unit of the queue
+    pub progress: f32,
+}
+
+/// Point that trained units walk to
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct RallyPoint(pub Option<Vec3>);
+
+/// Adds a unit to the end of the queue if the owner can pay for it and has supply left
+pub struct EnqueueUnit {
+    pub building: Entity,
+    pub kind: UnitKind,
+}
+
+/// Removes the last unit of the queue and refunds it
+pub struct CancelProduction {
+    pub building: Entity,
+}
+
+impl Plugin for ProductionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<ProductionQueue>()
+            .add_snapshot_component::<RallyPoint>()
+            .register_type::<Vec<UnitKind>>()
+            .register_type::<Option<Vec3>>()
+            .add_simulation_event::<EnqueueUnit>()
+            .add_simulation_event::<CancelProduction>()
+            .add_systems(
+                (enqueue_units, cancel_production, advance_production)
+                    .chain()
+                    .in_set(StepSet::Production)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+impl Plugin for ProductionInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (enqueue_from_hotkeys, cancel_from_hotkeys).in_set(OnUpdate(GameState::InGame)),
+        )
+        .add_system(
+            set_rally_point
+                .run_if(resource_exists::<CursorPosition>())
+                .run_if(not(is_cursor_over_hud))
+                .in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
+
+fn enqueue_from_hotkeys(
+    buildings: Query<(
+        &StableId,
+        &Building,
+        &Selectable,
+        &Owner,
+        With<ProductionQueue>,
+    )>,
+    building_definitions: Res<BuildingDefinitions>,
+    unit_definitions: Res<UnitDefinitions>,
+    input: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<PlayerCommand>,
+) {
+    for (id, building, selectable, owner, _) in &buildings {
+        if !selectable.is_selected || owner.0 != local_player.0 {
+            continue;
+        }
+
+        for kind in &building_definitions.get(building.kind).trains {
+            if unit_definitions
+                .get(*kind)
+                .hotkey()
+                .map(|key| input.just_pressed(key))
+                .unwrap_or(false)
+            {
+                writer.send(PlayerCommand::Train {
+                    building: *id,
+                    kind: *kind,
+                });
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn cancel_from_hotkeys(
+    // Research is cancelled first
+    buildings: Query<(
+        &StableId,
+        &Selectable,
+        &Owner,
+        With<ProductionQueue>,
+        Without<Researching>,
+    )>,
+    input: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<PlayerCommand>,
+) {
+    if !input.just_pressed(CANCEL_KEY) {
+        return;
+    }
+
+    for (id, selectable, owner, _, _) in &buildings {
+        if selectable.is_selected && owner.0 == local_player.0 {
+            writer.send(PlayerCommand::CancelTraining { building: *id });
+        }
+    }
+}
+
+fn enqueue_units(
+    mut reader: EventReader<EnqueueUnit>,
+    mut buildings: Query<(&Building, &Owner, &mut ProductionQueue)>,
+    building_definitions: Res<BuildingDefinitions>,
+    unit_definitions: Res<UnitDefinitions>,
+    mut stockpiles: ResMut<Stockpiles>,
+    mut supplies: ResMut<Supplies>,
+    mut supply_capped: EventWriter<SupplyCapped>,
+) {
+    for event in reader.iter() {
+        let Ok((building, owner, mut production)) = buildings.get_mut(event.building) else {
+            continue;
+        };
+
+        if !building_definitions
+            .get(building.kind)
+            .trains
+            .contains(&event.kind)
+            || production.queue.len() >= MAX_QUEUE_LENGTH
+        {
+            continue;
+        }
+
+        let definition = unit_definitions.get(event.kind);
+        let supply = supplies.get_mut(*owner);
+        if !supply.has_room_for(definition.supply) {
+            supply_capped.send(SupplyCapped { owner: *owner });
+            continue;
+        }
+
+        let stockpile = stockpiles.get_mut(*owner);
+        if !stockpile.can_afford(&definition.cost) {
+            continue;
+        }
+
+        stockpile.spend(&definition.cost);
+        // Counted right away, so that several units queued at once can not exceed the limit
+        supply.used += definition.supply;
+        production.queue.push(event.kind);
+    }
+}
+
+fn cancel_production(
+    mut reader: EventReader<CancelProduction>,
+    mut buildings: Query<(&Owner, &mut ProductionQueue)>,
+    definitions: Res<UnitDefinitions>,
+    mut stockpiles: ResMut<Stockpiles>,
+) {
+    for event in reader.iter() {
+        let Ok((owner, mut production)) = buildings.get_mut(event.building) else {
+            continue;
+        };
+
+        let Some(kind) = production.queue.pop() else {
+            continue;
+        };
+
+        if production.queue.is_empty() {
+            production.progress = 0.0;
+        }
+        stockpiles
+            .get_mut(*owner)
+            .refund(&definitions.get(kind).cost, 1.0);
+    }
+}
+
+fn advance_production(
+    mut buildings: Query<(
+        &StableId,
+        &mut ProductionQueue,
+        &RallyPoint,
+        &Transform,
+        &Footprint,
+        &Owner,
+    )>,
+    definitions: Res<UnitDefinitions>,
+    time: Res<SimulationTime>,
+    mut rng: ResMut<SimulationRng>,
+    mut writer: EventWriter<SpawnUnit>,
+) {
+    // Random numbers and the ids of the trained units are handed out by building id
+    let mut buildings: Vec<_> = buildings.iter_mut().collect();
+    buildings.sort_by_key(|(id, ..)| **id);
+
+    for (_, mut production, rally_point, transform, footprint, owner) in buildings {
+        let Some(kind) = production.queue.first().copied() else {
+            continue;
+        };
+
+        production.progress += time.delta_seconds();
+        if production.progress < definitions.get(kind).train_time {
+            continue;
+        }
+
+        production.progress = 0.0;
+        production.queue.remove(0);
+
+        // Units leave on the side of the rally point, or a random one so that they do not stack
+        let building_position = transform.translation;
+        let towards = rally_point.0.unwrap_or_else(|| {
+            let angle = rng.next_f32() * TAU;
+            building_position + Vec3::new(angle.cos(), 0.0, angle.sin())
+        });
+        writer.send(SpawnUnit {
+            kind,
+            owner: *owner,
+            position: approach_point(building_position, footprint.size().max_element(), towards),
+            rally_point: rally_point.0,
+        });
+    }
+}
+
+fn set_rally_point(
+    buildings: Query<(&StableId, &Selectable, &Owner, With<RallyPoint>)>,
+    cursor: Res<CursorPosition>,
+    input: Res<Input<MouseButton>>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<PlayerCommand>,
+    mut markers: EventWriter<OrderMarker>,
+) {
+    if !input.just_pressed(MouseButton::Right) {
+        return;
+    }
+
+    let buildings: Vec<StableId> = buildings
+        .iter()
+        .filter(|(_, selectable, owner, _)| selectable.is_selected && owner.0 == local_player.0)
+        .map(|(id, ..)| *id)
+        .collect();
+    if !buildings.is_empty() {
+        writer.send(PlayerCommand::SetRallyPoint {
+            buildings,
+            position: cursor.0,
+        });
+        markers.send(OrderMarker {
+            kind: MarkerKind::Rally,
+            position: cursor.0,
+        });
+    }
+}
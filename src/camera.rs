@@ -54,13 +54,10 @@ fn move_camera(
 
 fn restrict_camera(mut camera: Query<(&mut Transform, With<Camera3d>)>) {
     let (mut transform, _) = camera.single_mut();
-    if transform.translation.y <= CAMERA_MIN_HEIGHT {
-        transform.translation.y = CAMERA_MIN_HEIGHT
-    }
-
-    if transform.translation.y >= CAMERA_MAX_HEIGHT {
-        transform.translation.y = CAMERA_MAX_HEIGHT
-    }
+    transform.translation.y = transform
+        .translation
+        .y
+        .clamp(CAMERA_MIN_HEIGHT, CAMERA_MAX_HEIGHT);
 }
 
 fn spawn_camera(mut commands: Commands) {
@@ -125,3 +122,20 @@ fn zoom(
     }
 }
 
+/// Moves the camera horizontally so that it looks at the given point
+pub fn center_camera_on(transform: &mut Transform, target: Vec3) {
+    if let Some(focus) = intersect_ground_plane(transform.translation, transform.forward()) {
+        let offset = transform.translation - focus;
+        transform.translation.x = target.x + offset.x;
+        transform.translation.z = target.z + offset.z;
+    }
+}
+
+/// Point where a ray crosses the `y = 0` plane
+pub fn intersect_ground_plane(origin: Vec3, direction: Vec3) -> Option<Vec3> {
+    if direction.y >= 0.0 {
+        return None;
+    }
+
+    Some(origin + direction * (-origin.y / direction.y))
+}
@@ -0,0 +1,640 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Write},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    ecs::world::EntityRef,
+    prelude::*,
+    reflect::{ReflectRef, VariantType},
+    utils::HashMap,
+};
+
+use crate::{
+    abilities::{Abilities, Energy},
+    buildings::{Building, Construction, ProductionQueue, RallyPoint},
+    combat::Weapon,
+    command::Selections,
+    economy::{ResourceNode, Stockpiles, Worker},
+    fog::VisibilityGrid,
+    health::Health,
+    order::Orders,
+    player::{Owner, Players},
+    research::{ResearchState, Researching},
+    simulation::{Interpolated, SimulationRng, SimulationTime, StableId},
+    stats::Stats,
+    supply::Supplies,
+    units::{Unit, UnitKind},
+};
+
+/// States that went out of sync are dumped here, next to the game
+const DESYNC_FOLDER: &str = "desyncs";
+
+/// Readable state of the simulation, one entry per resource and entity.
+/// Entities are listed and referred to by their `StableId`, so two runs of the same game
+/// give the same entries no matter how their `Entity` ids were handed out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateSnapshot {
+    pub tick: u64,
+    pub entries: Vec<StateEntry>,
+}
+
+/// A resource or an entity of a `StateSnapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateEntry {
+    /// Name of the resource, or `#` and the stable id of the entity
+    pub key: String,
+    /// Components of an entity by name, a resource has a single value without a name
+    pub fields: Vec<(String, String)>,
+}
+
+/// Entries that differ between two snapshots, matched up by their keys.
+/// It prints like a unified diff, `-` for the first snapshot and `+` for the second
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateDiff {
+    /// Ticks of the first and the second snapshot
+    pub ticks: (u64, u64),
+    pub entries: Vec<EntryDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryDiff {
+    /// A resource or entity that only the first snapshot has
+    Removed(StateEntry),
+    /// A resource or entity that only the second snapshot has
+    Added(StateEntry),
+    Changed {
+        key: String,
+        fields: Vec<FieldDiff>,
+    },
+}
+
+/// Values of a field in the first and the second snapshot, `None` where it is missing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub name: String,
+    pub first: Option<String>,
+    pub second: Option<String>,
+}
+
+/// Hashes of the latest ticks, and the full state of every few of them, so that a tick
+/// found to be out of sync later on can still be looked at
+pub struct StateHistory {
+    /// Tick and hash, the oldest first
+    hashes: VecDeque<(u64, u64)>,
+    snapshots: VecDeque<StateSnapshot>,
+    length: usize,
+    snapshot_interval: u64,
+}
+
+impl StateEntry {
+    fn resource(key: impl Into<String>, value: String) -> Self {
+        Self {
+            key: key.into(),
+            fields: vec![(String::new(), value)],
+        }
+    }
+
+    /// The entry as one line of text, which is what the hash is taken over
+    pub fn line(&self) -> String {
+        let mut line = self.key.clone();
+        for (name, value) in &self.fields {
+            if name.is_empty() {
+                let _ = write!(line, " {}", value);
+            } else {
+                let _ = write!(line, " {}={}", name, value);
+            }
+        }
+        line
+    }
+
+    fn field(&self, name: &str) -> Option<&String> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+}
+
+impl StateSnapshot {
+    pub fn capture(world: &World) -> Self {
+        let mut entities: Vec<(StableId, EntityRef)> = world
+            .iter_entities()
+            .filter_map(|entity| Some((*entity.get::<StableId>()?, entity)))
+            .collect();
+        entities.sort_by_key(|(id, _)| *id);
+        let writer = StateWriter {
+            ids: entities
+                .iter()
+                .map(|(id, entity)| (entity.id(), *id))
+                .collect(),
+        };
+
+        let tick = world
+            .get_resource::<SimulationTime>()
+            .map_or(0, SimulationTime::tick);
+        let mut entries = vec![StateEntry::resource("tick", tick.to_string())];
+        if let Some(rng) = world.get_resource::<SimulationRng>() {
+            entries.push(StateEntry::resource("rng", format!("{:?}", rng)));
+        }
+        if let Some(stockpiles) = world.get_resource::<Stockpiles>() {
+            entries.push(StateEntry::resource("stockpiles", writer.value(stockpiles)));
+        }
+        if let Some(supplies) = world.get_resource::<Supplies>() {
+            entries.push(StateEntry::resource("supplies", writer.value(supplies)));
+        }
+        let players = world
+            .get_resource::<Players>()
+            .map_or(0, |players| players.len());
+        if let Some(grid) = world.get_resource::<VisibilityGrid>() {
+            for (player, cells) in grid.explored().into_iter().enumerate() {
+                entries.push(StateEntry::resource(format!("explored {}", player), cells));
+            }
+        }
+        for player in 0..players {
+            let owner = Owner(player as u8);
+            if let Some(research) = world.get_resource::<ResearchState>() {
+                entries.push(StateEntry::resource(
+                    format!("research {}", player),
+                    format!("{:?}", research.completed(owner)),
+                ));
+            }
+            if let Some(selections) = world.get_resource::<Selections>() {
+                let selected: Vec<String> = selections
+                    .get(owner)
+                    .iter()
+                    .map(|id| format!("#{}", id.0))
+                    .collect();
+                entries.push(StateEntry::resource(
+                    format!("selection {}", player),
+                    format!("[{}]", selected.join(", ")),
+                ));
+            }
+        }
+
+        for (id, entity) in &entities {
+            let mut entry = StateEntry {
+                key: format!("#{}", id.0),
+                fields: Vec::new(),
+            };
+            writer.component::<Owner>(&mut entry, entity, "owner");
+            writer.component::<UnitKind>(&mut entry, entity, "unit");
+            writer.component::<Building>(&mut entry, entity, "building");
+            let transform = entity
+                .get::<Interpolated>()
+                .map(Interpolated::simulated)
+                .or_else(|| entity.get::<Transform>().copied());
+            if let Some(transform) = transform {
+                writer.field(&mut entry, "transform", &transform);
+            }
+            writer.component::<Health>(&mut entry, entity, "health");
+            writer.component::<Unit>(&mut entry, entity, "state");
+            if let Some(orders) = entity.get::<Orders>() {
+                let orders: Vec<String> = orders
+                    .iter()
+                    .map(|order| writer.value(order.as_reflect()))
+                    .collect();
+                entry
+                    .fields
+                    .push(("orders".to_string(), format!("[{}]", orders.join(", "))));
+            }
+            writer.component::<Worker>(&mut entry, entity, "worker");
+            writer.component::<ResourceNode>(&mut entry, entity, "node");
+            writer.component::<Construction>(&mut entry, entity, "construction");
+            writer.component::<ProductionQueue>(&mut entry, entity, "production");
+            writer.component::<RallyPoint>(&mut entry, entity, "rally");
+            writer.component::<Researching>(&mut entry, entity, "researching");
+            writer.component::<Weapon>(&mut entry, entity, "weapon");
+            writer.component::<Energy>(&mut entry, entity, "energy");
+            writer.component::<Abilities>(&mut entry, entity, "abilities");
+            writer.component::<Stats>(&mut entry, entity, "stats");
+            entries.push(entry);
+        }
+
+        Self { tick, entries }
+    }
+
+    /// FNV-1a over the lines of the entries, the same on every platform and in every run
+    pub fn hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for entry in &self.entries {
+            for byte in entry.line().bytes().chain([b'\n']) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    /// What differs from another snapshot, `None` if they are the same
+    pub fn diff(&self, other: &StateSnapshot) -> Option<StateDiff> {
+        let first: HashMap<&str, &StateEntry> = self.keyed();
+        let second: HashMap<&str, &StateEntry> = other.keyed();
+
+        let mut entries = Vec::new();
+        for entry in &self.entries {
+            match second.get(entry.key.as_str()) {
+                None => entries.push(EntryDiff::Removed(entry.clone())),
+                Some(other) if *other != entry => entries.push(EntryDiff::Changed {
+                    key: entry.key.clone(),
+                    fields: entry.diff_fields(other),
+                }),
+                Some(_) => {}
+            }
+        }
+        for entry in &other.entries {
+            if !first.contains_key(entry.key.as_str()) {
+                entries.push(EntryDiff::Added(entry.clone()));
+            }
+        }
+
+        (!entries.is_empty()).then_some(StateDiff {
+            ticks: (self.tick, other.tick),
+            entries,
+        })
+    }
+
+    fn keyed(&self) -> HashMap<&str, &StateEntry> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.key.as_str(), entry))
+            .collect()
+    }
+
+    /// Writes the snapshot as YAML, each entity a mapping from its components to their values
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let text = self.to_yaml()?;
+        fs::write(path, text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// Reads a snapshot that `write` wrote
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        Self::from_yaml(&text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// The text `write` puts into the file
+    pub fn to_yaml(&self) -> Result<String, String> {
+        let mut entries = serde_yaml::Mapping::new();
+        for entry in &self.entries {
+            let value = match entry.fields.as_slice() {
+                [(name, value)] if name.is_empty() => value.as_str().into(),
+                fields => serde_yaml::Value::Mapping(
+                    fields
+                        .iter()
+                        .map(|(name, value)| (name.as_str().into(), value.as_str().into()))
+                        .collect(),
+                ),
+            };
+            entries.insert(entry.key.as_str().into(), value);
+        }
+        let mut file = serde_yaml::Mapping::new();
+        file.insert("tick".into(), self.tick.into());
+        file.insert("entries".into(), entries.into());
+
+        serde_yaml::to_string(&file).map_err(|error| error.to_string())
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self, String> {
+        let file: serde_yaml::Value =
+            serde_yaml::from_str(text).map_err(|error| error.to_string())?;
+        let invalid = || "not a state snapshot".to_string();
+
+        let tick = file["tick"].as_u64().ok_or_else(invalid)?;
+        let entries = file["entries"]
+            .as_mapping()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|(key, value)| {
+                let key = key.as_str().ok_or_else(invalid)?.to_string();
+                let fields = match value {
+                    serde_yaml::Value::String(value) => vec![(String::new(), value.clone())],
+                    serde_yaml::Value::Mapping(fields) => fields
+                        .iter()
+                        .map(|(name, value)| {
+                            Some((name.as_str()?.to_string(), value.as_str()?.to_string()))
+                        })
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?,
+                    _ => return Err(invalid()),
+                };
+                Ok(StateEntry { key, fields })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { tick, entries })
+    }
+}
+
+impl StateEntry {
+    fn diff_fields(&self, other: &StateEntry) -> Vec<FieldDiff> {
+        let mut names: Vec<&String> = self.fields.iter().map(|(name, _)| name).collect();
+        for (name, _) in &other.fields {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let first = self.field(name);
+                let second = other.field(name);
+                (first != second).then(|| FieldDiff {
+                    name: name.clone(),
+                    first: first.cloned(),
+                    second: second.cloned(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (first, second) = self.ticks;
+        if first == second {
+            write!(f, "state at tick {}: ", first)?;
+        } else {
+            write!(f, "states at ticks {} and {}: ", first, second)?;
+        }
+        match self.entries.len() {
+            1 => write!(f, "1 entry differs")?,
+            count => write!(f, "{} entries differ", count)?,
+        }
+
+        for entry in &self.entries {
+            match entry {
+                EntryDiff::Removed(entry) => write!(f, "\n- {}", entry.line())?,
+                EntryDiff::Added(entry) => write!(f, "\n+ {}", entry.line())?,
+                EntryDiff::Changed { key, fields } => {
+                    write!(f, "\n{}", key)?;
+                    for field in fields {
+                        let name = if field.name.is_empty() {
+                            String::new()
+                        } else {
+                            format!("{}=", field.name)
+                        };
+                        if let Some(value) = &field.first {
+                            write!(f, "\n  - {}{}", name, value)?;
+                        }
+                        if let Some(value) = &field.second {
+                            write!(f, "\n  + {}{}", name, value)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StateHistory {
+    /// Keeps the hashes of the given number of the latest ticks,
+    /// and the snapshots of the ticks among them that are a multiple of the interval
+    pub fn new(length: usize, snapshot_interval: u64) -> Self {
+        Self {
+            hashes: VecDeque::with_capacity(length + 1),
+            snapshots: VecDeque::new(),
+            length,
+            snapshot_interval,
+        }
+    }
+
+    /// Whether the whole state of the tick is kept, not only its hash
+    pub fn keeps_snapshot(&self, tick: u64) -> bool {
+        tick.is_multiple_of(self.snapshot_interval)
+    }
+
+    /// Adds the hash of the latest tick, the oldest one is dropped if there are too many
+    pub fn push_hash(&mut self, tick: u64, hash: u64) {
+        self.hashes.push_back((tick, hash));
+        while self.hashes.len() > self.length {
+            self.hashes.pop_front();
+        }
+        let oldest = self.hashes.front().map_or(tick, |(tick, _)| *tick);
+        self.snapshots.retain(|snapshot| snapshot.tick >= oldest);
+    }
+
+    /// Adds the snapshot of the latest tick along with its hash, which it returns
+    pub fn push(&mut self, snapshot: StateSnapshot) -> u64 {
+        let hash = snapshot.hash();
+        let tick = snapshot.tick;
+        self.snapshots.push_back(snapshot);
+        self.push_hash(tick, hash);
+        hash
+    }
+
+    pub fn get(&self, tick: u64) -> Option<&StateSnapshot> {
+        self.snapshots.iter().find(|snapshot| snapshot.tick == tick)
+    }
+
+    pub fn hash(&self, tick: u64) -> Option<u64> {
+        self.hashes
+            .iter()
+            .find(|(kept, _)| *kept == tick)
+            .map(|(_, hash)| *hash)
+    }
+
+    /// Tick and hash of the latest ticks, the latest first
+    pub fn latest_hashes(&self, count: usize) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.hashes.iter().rev().take(count).copied()
+    }
+}
+
+/// Hash of the current state of the simulation
+pub fn state_hash(world: &World) -> u64 {
+    StateSnapshot::capture(world).hash()
+}
+
+/// Writes a snapshot that went out of sync into the desync folder, where it can be compared
+/// with the snapshot of the other side
+pub fn dump_state(snapshot: &StateSnapshot, name: &str) -> Result<PathBuf, String> {
+    fs::create_dir_all(DESYNC_FOLDER).map_err(|error| format!("{}: {}", DESYNC_FOLDER, error))?;
+    let path = Path::new(DESYNC_FOLDER).join(format!("{}.yaml", name));
+    snapshot.write(&path)?;
+    Ok(path)
+}
+
+/// Writes reflected values as text, with entities replaced by their stable ids
+/// and maps sorted so that their random iteration order does not show
+struct StateWriter {
+    ids: HashMap<Entity, StableId>,
+}
+
+impl StateWriter {
+    fn component<T: Component + Reflect>(
+        &self,
+        entry: &mut StateEntry,
+        entity: &EntityRef,
+        name: &str,
+    ) {
+        if let Some(component) = entity.get::<T>() {
+            self.field(entry, name, component);
+        }
+    }
+
+    fn field(&self, entry: &mut StateEntry, name: &str, value: &dyn Reflect) {
+        entry.fields.push((name.to_string(), self.value(value)));
+    }
+
+    fn value(&self, value: &dyn Reflect) -> String {
+        let mut out = String::new();
+        self.write(&mut out, value);
+        out
+    }
+
+    fn write(&self, out: &mut String, value: &dyn Reflect) {
+        if let Some(entity) = value.downcast_ref::<Entity>() {
+            match self.ids.get(entity) {
+                Some(id) => {
+                    let _ = write!(out, "#{}", id.0);
+                }
+                // Despawned, or not part of the simulation
+                None => out.push_str("#?"),
+            }
+            return;
+        }
+
+        match value.reflect_ref() {
+            ReflectRef::Struct(value) => {
+                let fields = (0..value.field_len()).map(|index| {
+                    let name = value.name_at(index).unwrap_or_default();
+                    let field = value.field_at(index).map(|field| self.value(field));
+                    format!("{}: {}", name, field.unwrap_or_default())
+                });
+                self.write_list(out, "{", fields, "}");
+            }
+            ReflectRef::TupleStruct(value) => {
+                let fields = value.iter_fields().map(|field| self.value(field));
+                self.write_list(out, "(", fields, ")");
+            }
+            ReflectRef::Tuple(value) => {
+                let fields = value.iter_fields().map(|field| self.value(field));
+                self.write_list(out, "(", fields, ")");
+            }
+            ReflectRef::List(value) => {
+                let items = value.iter().map(|item| self.value(item));
+                self.write_list(out, "[", items, "]");
+            }
+            ReflectRef::Array(value) => {
+                let items = value.iter().map(|item| self.value(item));
+                self.write_list(out, "[", items, "]");
+            }
+            ReflectRef::Map(value) => {
+                let mut entries: Vec<String> = value
+                    .iter()
+                    .map(|(key, item)| format!("{}: {}", self.value(key), self.value(item)))
+                    .collect();
+                entries.sort_unstable();
+                self.write_list(out, "{", entries.into_iter(), "}");
+            }
+            ReflectRef::Enum(value) => {
+                out.push_str(value.variant_name());
+                let fields = value.iter_fields().map(|field| match field.name() {
+                    Some(name) => format!("{}: {}", name, self.value(field.value())),
+                    None => self.value(field.value()),
+                });
+                match value.variant_type() {
+                    VariantType::Struct => self.write_list(out, " {", fields, "}"),
+                    VariantType::Tuple => self.write_list(out, "(", fields, ")"),
+                    VariantType::Unit => {}
+                }
+            }
+            ReflectRef::Value(value) => {
+                let _ = write!(out, "{:?}", value);
+            }
+        }
+    }
+
+    fn write_list(
+        &self,
+        out: &mut String,
+        open: &str,
+        items: impl Iterator<Item = String>,
+        close: &str,
+    ) {
+        out.push_str(open);
+        out.push_str(&items.collect::<Vec<_>>().join(", "));
+        out.push_str(close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.spawn((StableId(1), Owner(0), Health::new(80.0)));
+        world.spawn((StableId(2), Owner(1), Health::new(50.0)));
+        world
+    }
+
+    #[test]
+    fn diff_names_the_changed_entity_and_component() {
+        let mut world = world();
+        let first = StateSnapshot::capture(&world);
+
+        let mut query = world.query::<(&StableId, &mut Health)>();
+        for (id, mut health) in query.iter_mut(&mut world) {
+            if *id == StableId(2) {
+                health.current = 42.0;
+            }
+        }
+        let second = StateSnapshot::capture(&world);
+
+        let diff = first.diff(&second).expect("the health changed");
+        assert_eq!(
+            diff.entries,
+            vec![EntryDiff::Changed {
+                key: "#2".to_string(),
+                fields: vec![FieldDiff {
+                    name: "health".to_string(),
+                    first: Some("{current: 50.0, max: 50.0}".to_string()),
+                    second: Some("{current: 42.0, max: 50.0}".to_string()),
+                }],
+            }]
+        );
+        assert_eq!(first.diff(&first), None);
+    }
+
+    #[test]
+    fn written_snapshot_reads_back_with_the_same_hash() {
+        let snapshot = StateSnapshot::capture(&world());
+        let path = std::env::temp_dir().join(format!("state-{}.yaml", std::process::id()));
+        snapshot.write(&path).unwrap();
+        let read = StateSnapshot::read(&path);
+        let _ = fs::remove_file(&path);
+
+        let read = read.unwrap();
+        assert_eq!(read, snapshot);
+        assert_eq!(read.hash(), snapshot.hash());
+    }
+
+    #[test]
+    fn hash_does_not_depend_on_map_order() {
+        let keys: Vec<String> = (0..32).map(|key| format!("key {}", key)).collect();
+        let mut first = HashMap::default();
+        for (value, key) in keys.iter().enumerate() {
+            first.insert(key.clone(), value as u32);
+        }
+        // Filled the other way round and with more room, as a map restored from a save would be
+        let mut second = HashMap::with_capacity(256);
+        for (value, key) in keys.iter().enumerate().rev() {
+            second.insert(key.clone(), value as u32);
+        }
+
+        let writer = StateWriter {
+            ids: HashMap::default(),
+        };
+        let snapshot = |map: &HashMap<String, u32>| StateSnapshot {
+            tick: 0,
+            entries: vec![StateEntry::resource("map", writer.value(map))],
+        };
+        assert_eq!(snapshot(&first).hash(), snapshot(&second).hash());
+    }
+}
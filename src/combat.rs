@@ -0,0 +1,101 @@
+use bevy::prelude::*;
+
+use crate::{
+    health::Health,
+    navigation::Footprint,
+    simulation::{SimulationTime, StableId, StepSet},
+    snapshot::SnapshotApp,
+    stats::{Stat, Stats},
+    units::{Unit, UnitState},
+};
+
+pub struct CombatPlugin;
+
+/// Damage a hit always deals, no matter how much armor the target has
+const MIN_DAMAGE: f32 = 1.0;
+
+/// Lets a unit attack, damage and range are read from its stats
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct Weapon {
+    /// Seconds between two hits
+    pub cooldown: f32,
+    /// Seconds until the weapon can hit again
+    pub ready_in: f32,
+}
+
+impl Weapon {
+    pub fn new(cooldown: f32) -> Self {
+        Self {
+            cooldown,
+            ready_in: 0.0,
+        }
+    }
+}
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<Weapon>().add_system(
+            attack_targets
+                .in_set(StepSet::Combat)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+/// State that walks a unit into weapon range of the target
+pub fn attack(target: Entity, target_position: Vec3) -> UnitState {
+    UnitState::Attacking {
+        target,
+        position: target_position,
+    }
+}
+
+/// Horizontal distance from the unit to the target, buildings are measured from their sides
+pub fn distance_to(transform: &Transform, target: Vec3, footprint: Option<&Footprint>) -> f32 {
+    let radius = footprint.map_or(0.0, |footprint| footprint.size().min_element() / 2.0);
+    let offset = target - transform.translation;
+    Vec2::new(offset.x, offset.z).length() - radius
+}
+
+/// Walks attacking units into range of their target and hits it whenever the weapon is ready
+fn attack_targets(
+    mut attackers: Query<(&StableId, &mut Unit, &mut Weapon, &Transform, &Stats)>,
+    mut targets: Query<(&mut Health, &Transform, Option<&Stats>, Option<&Footprint>)>,
+    time: Res<SimulationTime>,
+) {
+    // Hits on the same target are summed in a fixed order, floats round differently in another
+    let mut attackers: Vec<_> = attackers.iter_mut().collect();
+    attackers.sort_by_key(|(id, ..)| **id);
+
+    for (_, mut unit, mut weapon, transform, stats) in attackers {
+        weapon.ready_in = (weapon.ready_in - time.delta_seconds()).max(0.0);
+
+        let UnitState::Attacking { target, .. } = unit.state else {
+            continue;
+        };
+
+        let Ok((mut health, target_transform, target_stats, footprint)) = targets.get_mut(target)
+        else {
+            // Dead
+            unit.state = UnitState::Idle;
+            continue;
+        };
+
+        let distance = distance_to(transform, target_transform.translation, footprint);
+        if distance > stats.get(Stat::Range) {
+            unit.state = attack(target, target_transform.translation);
+            continue;
+        }
+
+        // Stand still while in range
+        unit.state = attack(target, transform.translation);
+        if weapon.ready_in > 0.0 {
+            continue;
+        }
+
+        let armor = target_stats.map_or(0.0, |stats| stats.get(Stat::Armor));
+        health.current -= (stats.get(Stat::Damage) - armor).max(MIN_DAMAGE);
+        weapon.ready_in = weapon.cooldown;
+    }
+}
@@ -0,0 +1,472 @@
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abilities::{Abilities, AbilityTarget},
+    buildings::{
+        Building, BuildingDefinitions, BuildingKind, CancelProduction, Construction, EnqueueUnit,
+        PlacementCheck, RallyPoint, SpawnBuilding, CANCEL_REFUND,
+    },
+    economy::{Stockpiles, Worker},
+    fog::VisibilityGrid,
+    network::Lockstep,
+    order::{Order, Orders},
+    player::{LocalPlayer, Owner, Players},
+    replay::ReplayPlayback,
+    research::{CancelResearch, ResearchDefinitions, ResearchState, StartResearch},
+    simulation::{SimulationTime, StableId, StepSet},
+    snapshot::SnapshotApp,
+    units::{Unit, UnitKind, UnitState},
+    GameState,
+};
+
+pub struct CommandPlugin;
+
+/// Something a player asked for. Input, AI, replays and the network all issue these,
+/// and only `apply_commands` turns them into changes of the simulation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PlayerCommand {
+    /// Replaces what the player has selected
+    Select {
+        units: Vec<StableId>,
+    },
+    Move {
+        units: Vec<StableId>,
+        position: Vec3,
+        queued: bool,
+    },
+    Attack {
+        units: Vec<StableId>,
+        target: StableId,
+        queued: bool,
+    },
+    Gather {
+        units: Vec<StableId>,
+        node: StableId,
+        queued: bool,
+    },
+    /// Sends workers to construct a building site that has already been placed
+    Construct {
+        units: Vec<StableId>,
+        site: StableId,
+        queued: bool,
+    },
+    Cast {
+        units: Vec<StableId>,
+        ability: String,
+        target: CastTarget,
+        queued: bool,
+    },
+    Stop {
+        units: Vec<StableId>,
+    },
+    /// Places a building site and sends the workers to construct it
+    Build {
+        kind: BuildingKind,
+        position: Vec3,
+        builders: Vec<StableId>,
+    },
+    CancelConstruction {
+        sites: Vec<StableId>,
+    },
+    Train {
+        building: StableId,
+        kind: UnitKind,
+    },
+    CancelTraining {
+        building: StableId,
+    },
+    Research {
+        building: StableId,
+        research: String,
+    },
+    CancelResearch {
+        building: StableId,
+    },
+    SetRallyPoint {
+        buildings: Vec<StableId>,
+        position: Vec3,
+    },
+}
+
+/// Target of a cast, `AbilityTarget` with the unit given by its stable id
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CastTarget {
+    None,
+    Point(Vec3),
+    Unit(StableId),
+}
+
+/// Command of a player that is applied at the start of the given tick
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IssuedCommand {
+    pub player: u8,
+    pub tick: u64,
+    pub command: PlayerCommand,
+}
+
+/// Commands that have not been applied yet
+#[derive(Resource, Debug, Default)]
+pub struct CommandQueue(Vec<IssuedCommand>);
+
+impl CommandQueue {
+    pub fn push(&mut self, command: IssuedCommand) {
+        self.0.push(command);
+    }
+
+    /// Takes the commands due at the tick, ordered by player and then by the order they came in
+    fn take_due(&mut self, tick: u64) -> Vec<IssuedCommand> {
+        let (mut due, later) = self
+            .0
+            .drain(..)
+            .partition(|command: &IssuedCommand| command.tick <= tick);
+        self.0 = later;
+        due.sort_by_key(|command: &IssuedCommand| (command.tick, command.player));
+        due
+    }
+}
+
+/// Commands applied in the last tick, stamped with that tick, for replays
+#[derive(Resource, Debug, Default)]
+pub struct AppliedCommands(pub Vec<IssuedCommand>);
+
+/// What every player has selected, indexed by player
+#[derive(Resource, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct Selections(Vec<Vec<StableId>>);
+
+impl Selections {
+    pub fn get(&self, owner: Owner) -> &[StableId] {
+        self.0
+            .get(owner.0 as usize)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn set(&mut self, owner: Owner, units: Vec<StableId>) {
+        let index = owner.0 as usize;
+        if self.0.len() <= index {
+            self.0.resize(index + 1, Vec::new());
+        }
+        self.0[index] = units;
+    }
+}
+
+impl Plugin for CommandPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlayerCommand>()
+            .add_snapshot_resource::<Selections>()
+            .register_type::<Vec<StableId>>()
+            .register_type::<Vec<Vec<StableId>>>()
+            .init_resource::<CommandQueue>()
+            .init_resource::<AppliedCommands>()
+            .init_resource::<Selections>()
+            .add_system(
+                queue_local_commands
+                    .run_if(not(resource_exists::<ReplayPlayback>()))
+                    .run_if(not(resource_exists::<Lockstep>()))
+                    .in_base_set(CoreSet::PostUpdate),
+            )
+            .add_system(
+                apply_commands
+                    .in_set(StepSet::Commands)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(clear_commands.in_schedule(OnEnter(GameState::MainMenu)));
+    }
+}
+
+/// Issues the commands of the local player for the next tick, a replay only watches
+/// and lockstep games send them to the other players first
+fn queue_local_commands(
+    mut reader: EventReader<PlayerCommand>,
+    mut queue: ResMut<CommandQueue>,
+    local_player: Res<LocalPlayer>,
+    time: Res<SimulationTime>,
+) {
+    for command in reader.iter() {
+        queue.push(IssuedCommand {
+            player: local_player.0,
+            tick: time.tick(),
+            command: command.clone(),
+        });
+    }
+}
+
+fn clear_commands(mut commands: Commands) {
+    commands.insert_resource(CommandQueue::default());
+    commands.insert_resource(AppliedCommands::default());
+    commands.insert_resource(Selections::default());
+}
+
+/// What `apply_commands` checks commands against besides their own units
+#[derive(SystemParam)]
+struct CommandChecks<'w, 's> {
+    players: Res<'w, Players>,
+    vision: Option<Res<'w, VisibilityGrid>>,
+    placement: PlacementCheck<'w, 's>,
+    positions: Query<'w, 's, &'static Transform>,
+}
+
+impl CommandChecks<'_, '_> {
+    /// Entities in the fog can not be targeted by the player
+    fn is_visible(&self, player: u8, entity: Entity) -> bool {
+        self.positions.get(entity).is_ok_and(|transform| {
+            self.vision
+                .as_ref()
+                .is_none_or(|vision| vision.is_visible(player, transform.translation))
+        })
+    }
+}
+
+/// Checks that the player may give every due command and applies it,
+/// commands for units or buildings of other players are dropped
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn apply_commands(
+    mut commands: Commands,
+    mut queue: ResMut<CommandQueue>,
+    mut applied: ResMut<AppliedCommands>,
+    ids: Query<(Entity, &StableId)>,
+    owners: Query<&Owner>,
+    mut units: Query<CommandedUnit>,
+    sites: Query<(&Building, With<Construction>)>,
+    mut rally_points: Query<&mut RallyPoint>,
+    building_definitions: Res<BuildingDefinitions>,
+    research_definitions: Res<ResearchDefinitions>,
+    research: Res<ResearchState>,
+    checks: CommandChecks,
+    mut stockpiles: ResMut<Stockpiles>,
+    mut selections: ResMut<Selections>,
+    time: Res<SimulationTime>,
+    mut writers: (
+        EventWriter<SpawnBuilding>,
+        EventWriter<EnqueueUnit>,
+        EventWriter<CancelProduction>,
+        EventWriter<StartResearch>,
+        EventWriter<CancelResearch>,
+    ),
+) {
+    let due = queue.take_due(time.tick());
+    applied.0 = due
+        .iter()
+        .map(|command| IssuedCommand {
+            tick: time.tick(),
+            ..command.clone()
+        })
+        .collect();
+    if due.is_empty() {
+        return;
+    }
+
+    let entities: HashMap<StableId, Entity> =
+        ids.iter().map(|(entity, id)| (*id, entity)).collect();
+    // Sites cancelled in this tick, which are only despawned after it
+    let mut cancelled = Vec::new();
+    // Sites placed in this tick, which are only spawned after it
+    let mut placed = Vec::new();
+
+    for IssuedCommand {
+        player, command, ..
+    } in due
+    {
+        let owner = Owner(player);
+        let entity = |id: StableId| entities.get(&id).copied();
+        let owned = |ids: &[StableId]| -> Vec<Entity> {
+            ids.iter()
+                .filter_map(|id| entity(*id))
+                .filter(|entity| owners.get(*entity).is_ok_and(|other| *other == owner))
+                .collect()
+        };
+
+        match command {
+            PlayerCommand::Select { units: selected } => {
+                let selected = selected.into_iter().filter(|id| entity(*id).is_some());
+                selections.set(owner, selected.collect());
+            }
+            PlayerCommand::Move {
+                units: commanded,
+                position,
+                queued,
+            } => issue(
+                &mut units,
+                &owned(&commanded),
+                Order::Move(position),
+                queued,
+            ),
+            PlayerCommand::Attack {
+                units: commanded,
+                target,
+                queued,
+            } => {
+                // Only enemies the player can see
+                let target = entity(target).filter(|target| {
+                    owners
+                        .get(*target)
+                        .is_ok_and(|other| !checks.players.are_allies(owner, *other))
+                        && checks.is_visible(player, *target)
+                });
+                if let Some(target) = target {
+                    issue(
+                        &mut units,
+                        &owned(&commanded),
+                        Order::Attack(target),
+                        queued,
+                    );
+                }
+            }
+            PlayerCommand::Gather {
+                units: commanded,
+                node,
+                queued,
+            } => {
+                let node = entity(node).filter(|node| checks.is_visible(player, *node));
+                if let Some(node) = node {
+                    issue(&mut units, &owned(&commanded), Order::Gather(node), queued);
+                }
+            }
+            PlayerCommand::Construct {
+                units: commanded,
+                site,
+                queued,
+            } => {
+                // Only the player's own sites can be built up
+                if let Some(&site) = owned(&[site]).first() {
+                    issue(&mut units, &owned(&commanded), Order::Build(site), queued);
+                }
+            }
+            PlayerCommand::Cast {
+                units: commanded,
+                ability,
+                target,
+                queued,
+            } => {
+                let target = match target {
+                    CastTarget::None => AbilityTarget::None,
+                    CastTarget::Point(point) => AbilityTarget::Point(point),
+                    CastTarget::Unit(id) => {
+                        match entity(id).filter(|target| checks.is_visible(player, *target)) {
+                            Some(target) => AbilityTarget::Unit(target),
+                            None => continue,
+                        }
+                    }
+                };
+
+                for unit in owned(&commanded) {
+                    let Ok((mut orders, _, kind, abilities, _)) = units.get_mut(unit) else {
+                        continue;
+                    };
+
+                    if abilities.is_some_and(|abilities| abilities.has(&ability))
+                        && research.is_ability_unlocked(
+                            &research_definitions,
+                            owner,
+                            *kind,
+                            &ability,
+                        )
+                    {
+                        let order = Order::Cast {
+                            ability: ability.clone(),
+                            target,
+                        };
+                        orders.issue(order, queued);
+                    }
+                }
+            }
+            PlayerCommand::Stop { units: commanded } => {
+                for unit in owned(&commanded) {
+                    if let Ok((mut orders, mut unit, ..)) = units.get_mut(unit) {
+                        orders.clear();
+                        unit.state = UnitState::Idle;
+                    }
+                }
+            }
+            PlayerCommand::Build {
+                kind,
+                position,
+                builders,
+            } => {
+                let definition = building_definitions.get(kind);
+                let can_place = checks.placement.can_place(player, kind, position, &placed);
+                let stockpile = stockpiles.get_mut(owner);
+                if !can_place || !stockpile.can_afford(&definition.cost) {
+                    continue;
+                }
+
+                stockpile.spend(&definition.cost);
+                placed.push((position, definition.footprint()));
+                writers.0.send(SpawnBuilding {
+                    kind,
+                    owner,
+                    position,
+                    under_construction: true,
+                    builders: owned(&builders)
+                        .into_iter()
+                        .filter(|builder| units.get(*builder).is_ok_and(|unit| unit.4.is_some()))
+                        .collect(),
+                });
+            }
+            PlayerCommand::CancelConstruction { sites: cancelling } => {
+                for site in owned(&cancelling) {
+                    let Ok((building, _)) = sites.get(site) else {
+                        continue;
+                    };
+                    if cancelled.contains(&site) {
+                        continue;
+                    }
+
+                    stockpiles
+                        .get_mut(owner)
+                        .refund(&building_definitions.get(building.kind).cost, CANCEL_REFUND);
+                    commands.entity(site).despawn_recursive();
+                    cancelled.push(site);
+                }
+            }
+            PlayerCommand::Train { building, kind } => {
+                if let Some(&building) = owned(&[building]).first() {
+                    writers.1.send(EnqueueUnit { building, kind });
+                }
+            }
+            PlayerCommand::CancelTraining { building } => {
+                if let Some(&building) = owned(&[building]).first() {
+                    writers.2.send(CancelProduction { building });
+                }
+            }
+            PlayerCommand::Research { building, research } => {
+                if let Some(&building) = owned(&[building]).first() {
+                    writers.3.send(StartResearch { building, research });
+                }
+            }
+            PlayerCommand::CancelResearch { building } => {
+                if let Some(&building) = owned(&[building]).first() {
+                    writers.4.send(CancelResearch { building });
+                }
+            }
+            PlayerCommand::SetRallyPoint {
+                buildings,
+                position,
+            } => {
+                for building in owned(&buildings) {
+                    if let Ok(mut rally_point) = rally_points.get_mut(building) {
+                        rally_point.0 = Some(position);
+                    }
+                }
+            }
+        }
+    }
+}
+
+type CommandedUnit = (
+    &'static mut Orders,
+    &'static mut Unit,
+    &'static UnitKind,
+    Option<&'static Abilities>,
+    Option<&'static Worker>,
+);
+
+fn issue(units: &mut Query<CommandedUnit>, commanded: &[Entity], order: Order, queued: bool) {
+    for unit in commanded {
+        if let Ok((mut orders, ..)) = units.get_mut(*unit) {
+            orders.issue(order.clone(), queued);
+        }
+    }
+}
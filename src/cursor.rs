@@ -1,54 +1,60 @@
 use bevy::{
     prelude::*,
+    ui::RelativeCursorPosition,
     window::{CursorGrabMode, PrimaryWindow},
 };
 use bevy_inspector_egui::{
     prelude::ReflectInspectorOptions, quick::ResourceInspectorPlugin, InspectorOptions,
 };
-use bevy_rapier3d::prelude::Collider;
-
-use crate::{ground::Ground, GameState};
+use bevy_rapier3d::prelude::{Collider, QueryFilter, RapierContext};
+
+use crate::{
+    abilities::AbilityTargeting,
+    economy::{ResourceNode, Worker},
+    fog::{CellVisibility, VisibilityGrid},
+    ground::Ground,
+    health::Health,
+    hud::{is_cursor_over_hud, HudNode},
+    player::{LocalPlayer, Owner, Players},
+    selection::Selectable,
+    units::Unit,
+    GameState,
+};
 
 pub struct CursorPlugin;
 
-#[derive(Component)]
-struct MoveMark;
-
 #[derive(Reflect, Resource, Default, InspectorOptions)]
 #[reflect(Resource, InspectorOptions)]
 /// Coursor postion in world coordinates
-struct CursorPosition(Vec3);
+pub struct CursorPosition(pub Vec3);
 
-#[derive(Component)]
-struct MoveSphereDissapearTimer(Timer);
+/// Unit, building or resource node under the cursor, enemies hidden by the fog are left out
+#[derive(Resource, Default)]
+pub struct HoveredEntity(pub Option<Entity>);
 
 enum CursorEvent {
     OverGround(Vec3),
     OutOfBounds,
 }
 
-#[derive(Component, Default, Reflect)]
-struct DespawnCounter(u8);
-
 impl Plugin for CursorPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(ResourceInspectorPlugin::<CursorPosition>::default())
             .register_type::<CursorPosition>()
-            .register_type::<DespawnCounter>()
             .init_resource::<CursorPosition>()
+            .init_resource::<HoveredEntity>()
             .add_event::<CursorEvent>()
             .add_startup_system(set_default_cursor_position)
             .add_system(set_cursor_as_confined.in_schedule(OnEnter(GameState::InGame)))
-            .add_system(release_cursor.in_schedule(OnEnter(GameState::Menu)))
+            .add_system(release_cursor.in_schedule(OnEnter(GameState::Paused)))
             .add_systems(
                 (
                     remove_cursor_position_resource.run_if(resource_exists::<CursorPosition>()),
                     update_cursor_position.run_if(resource_exists::<CursorPosition>()),
-                    spawn_move_mark.run_if(resource_exists::<CursorPosition>()),
                     add_cursor_position_resource.run_if(not(resource_exists::<CursorPosition>())),
-                    handle_cursor_over_ground,
-                    decrease_move_mark_scale,
-                    despawn_move_mark,
+                    handle_cursor_over_ground.run_if(any_with_component::<Ground>()),
+                    update_hovered_entity,
+                    update_cursor_icon.after(update_hovered_entity),
                 )
                     .in_set(OnUpdate(GameState::InGame)),
             );
@@ -112,7 +118,8 @@ fn set_cursor_as_confined(mut window: Query<(&mut Window, With<PrimaryWindow>)>)
 
 fn release_cursor(mut window: Query<(&mut Window, With<PrimaryWindow>)>) {
     let (mut window, _) = window.single_mut();
-    window.cursor.grab_mode = CursorGrabMode::None
+    window.cursor.grab_mode = CursorGrabMode::None;
+    window.cursor.icon = CursorIcon::Default
 }
 
 pub fn get_point_on_ground(
@@ -134,61 +141,103 @@ pub fn get_point_on_ground(
     None
 }
 
-fn spawn_move_mark(
-    mut commands: Commands,
-    input: Res<Input<MouseButton>>,
-    position: Res<CursorPosition>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    if input.just_pressed(MouseButton::Right) {
-        commands.spawn((
-            Name::from("Move Mark"),
-            PbrBundle {
-                mesh: meshes.add(
-                    shape::UVSphere {
-                        radius: 0.1,
-                        ..default()
-                    }
-                    .into(),
-                ),
-                material: materials.add(Color::YELLOW_GREEN.into()),
-                transform: Transform::from_translation(position.0),
-                ..default()
-            },
-            MoveSphereDissapearTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
-            MoveMark,
-            DespawnCounter::default(),
-        ));
-    }
+/// First solid collider under the cursor, which may also be the ground.
+/// Sensors such as the camera and the selection box are skipped.
+pub fn get_entity_under_cursor(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    rapier_context: &RapierContext,
+) -> Option<Entity> {
+    let cursor_position = window.cursor_position()?;
+    let ray = camera.viewport_to_world(camera_transform, cursor_position)?;
+    rapier_context
+        .cast_ray(
+            ray.origin,
+            ray.direction,
+            100.0,
+            true,
+            QueryFilter::default().exclude_sensors(),
+        )
+        .map(|(entity, _)| entity)
 }
 
-fn decrease_move_mark_scale(
-    mut marks: Query<(
-        &mut MoveSphereDissapearTimer,
-        &mut Transform,
-        &mut DespawnCounter,
-        With<MoveMark>,
-    )>,
-    time: Res<Time>,
+#[allow(clippy::type_complexity)]
+pub fn update_hovered_entity(
+    window: Query<(&Window, With<PrimaryWindow>)>,
+    camera: Query<(&Camera, &GlobalTransform, With<Camera3d>)>,
+    hoverables: Query<&Visibility, Or<(With<Selectable>, With<ResourceNode>)>>,
+    rapier_context: Res<RapierContext>,
+    mut hovered: ResMut<HoveredEntity>,
 ) {
-    for (mut timer, mut transform, mut despawn_counter, _) in &mut marks {
-        timer.0.tick(time.delta());
-        if timer.0.just_finished() {
-            despawn_counter.0 += 1;
-            let scale = transform.scale;
-            transform.scale = scale - 0.1;
-        }
+    let (window, _) = window.single();
+    let (camera, camera_transform, _) = camera.single();
+
+    let entity = get_entity_under_cursor(window, camera, camera_transform, &rapier_context).filter(
+        |entity| {
+            hoverables
+                .get(*entity)
+                .is_ok_and(|visibility| *visibility != Visibility::Hidden)
+        },
+    );
+    if hovered.0 != entity {
+        hovered.0 = entity;
     }
 }
 
-fn despawn_move_mark(
-    marks: Query<(&DespawnCounter, Entity, With<MoveMark>)>,
-    mut commands: Commands,
+/// Shows what a right click would do with the local player's selected units
+#[allow(clippy::too_many_arguments)]
+fn update_cursor_icon(
+    mut window: Query<(&mut Window, With<PrimaryWindow>)>,
+    hud_nodes: Query<(&RelativeCursorPosition, With<HudNode>)>,
+    units: Query<(&Selectable, &Owner, Option<&Worker>, With<Unit>)>,
+    targets: Query<(&Owner, With<Health>)>,
+    resource_nodes: Query<(), With<ResourceNode>>,
+    hovered: Res<HoveredEntity>,
+    position: Option<Res<CursorPosition>>,
+    visibility_grid: Option<Res<VisibilityGrid>>,
+    targeting: Option<Res<AbilityTargeting>>,
+    local_player: Res<LocalPlayer>,
+    players: Res<Players>,
 ) {
-    for (despawn_counter, entity, _) in &marks {
-        if despawn_counter.0 == 10 {
-            commands.entity(entity).despawn_recursive()
+    let (mut window, _) = window.single_mut();
+
+    let selected_workers: Vec<bool> = units
+        .iter()
+        .filter(|(selectable, owner, ..)| selectable.is_selected && owner.0 == local_player.0)
+        .map(|(_, _, worker, _)| worker.is_some())
+        .collect();
+    let has_units = !selected_workers.is_empty();
+    let has_workers = selected_workers.contains(&true);
+    let is_enemy = |entity: Entity| {
+        targets
+            .get(entity)
+            .is_ok_and(|(owner, _)| !players.are_allies(*owner, Owner(local_player.0)))
+    };
+    let is_unexplored = || {
+        position
+            .as_ref()
+            .zip(visibility_grid.as_ref())
+            .is_some_and(|(position, grid)| {
+                grid.visibility(local_player.0, position.0) == CellVisibility::Unexplored
+            })
+    };
+
+    let icon = if is_cursor_over_hud(hud_nodes) {
+        CursorIcon::Default
+    } else if targeting.is_some() {
+        CursorIcon::Crosshair
+    } else {
+        match hovered.0 {
+            Some(enemy) if has_units && is_enemy(enemy) => CursorIcon::Crosshair,
+            Some(node) if has_workers && resource_nodes.contains(node) => CursorIcon::Hand,
+            Some(_) => CursorIcon::Default,
+            None if has_units && is_unexplored() => CursorIcon::NotAllowed,
+            None => CursorIcon::Default,
         }
+    };
+
+    if window.cursor.icon != icon {
+        window.cursor.icon = icon;
     }
 }
@@ -0,0 +1,505 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::quick::ResourceInspectorPlugin;
+use bevy_rapier3d::prelude::{Collider, RigidBody};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ground::PlaceOnTerrain,
+    navigation::Footprint,
+    player::Owner,
+    simulation::{SimulationEventApp, SimulationTime, SpawnSet, StableId, StableIds, StepSet},
+    snapshot::SnapshotApp,
+    units::{approach_point, has_arrived, Unit, UnitState},
+};
+
+pub struct EconomyPlugin;
+
+/// Resource node models and the stockpile inspector
+pub struct EconomyViewPlugin;
+
+pub const RESOURCE_NODE_SIZE: f32 = 1.0;
+
+/// Resources every player gets at the start of a game, unless the skirmish setup picks others
+pub const STARTING_RESOURCES: Stockpile = Stockpile {
+    minerals: 300,
+    wood: 150,
+};
+
+const WORKER_CAPACITY: u32 = 10;
+/// Amount taken from a node every harvest
+const HARVEST_AMOUNT: u32 = 2;
+const HARVEST_INTERVAL: f32 = 0.5;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect, FromReflect, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceKind {
+    #[default]
+    Minerals,
+    Wood,
+}
+
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct ResourceNode {
+    pub kind: ResourceKind,
+    pub amount: u32,
+}
+
+pub struct SpawnResourceNode {
+    pub kind: ResourceKind,
+    pub amount: u32,
+    pub position: Vec3,
+}
+
+/// Unit that can harvest resource nodes and carry the resources to a drop-off
+#[derive(Component, Debug, Reflect)]
+#[reflect(Component)]
+pub struct Worker {
+    pub capacity: u32,
+    pub carrying: u32,
+    /// Kind of resource that is being carried or was gathered last
+    pub resource: Option<ResourceKind>,
+    /// Node to go back to after the cargo has been dropped off
+    last_node: Option<Entity>,
+    harvest_timer: Timer,
+}
+
+impl Default for Worker {
+    fn default() -> Self {
+        Self {
+            capacity: WORKER_CAPACITY,
+            carrying: 0,
+            resource: None,
+            last_node: None,
+            harvest_timer: Timer::from_seconds(HARVEST_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+impl Worker {
+    fn is_full(&self) -> bool {
+        self.carrying >= self.capacity
+    }
+}
+
+/// Building where workers of the owning player deliver their cargo
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct Dropoff;
+
+/// Price of a building, unit or research
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect, FromReflect, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cost {
+    pub minerals: u32,
+    pub wood: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, Reflect, FromReflect, Serialize, Deserialize)]
+pub struct Stockpile {
+    pub minerals: u32,
+    pub wood: u32,
+}
+
+impl Stockpile {
+    pub fn can_afford(&self, cost: &Cost) -> bool {
+        self.minerals >= cost.minerals && self.wood >= cost.wood
+    }
+
+    /// Takes the cost out of the stockpile, which has to be able to afford it
+    pub fn spend(&mut self, cost: &Cost) {
+        self.minerals -= cost.minerals;
+        self.wood -= cost.wood;
+    }
+
+    /// Gives back a fraction of a cost that was spent
+    pub fn refund(&mut self, cost: &Cost, fraction: f32) {
+        self.minerals += (cost.minerals as f32 * fraction) as u32;
+        self.wood += (cost.wood as f32 * fraction) as u32;
+    }
+
+    pub fn add(&mut self, kind: ResourceKind, amount: u32) {
+        match kind {
+            ResourceKind::Minerals => self.minerals += amount,
+            ResourceKind::Wood => self.wood += amount,
+        }
+    }
+}
+
+/// Resources collected by every player, indexed by player
+#[derive(Resource, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct Stockpiles(Vec<Stockpile>);
+
+impl Stockpiles {
+    /// The same starting resources for every player
+    pub fn new(players: usize, starting: Stockpile) -> Self {
+        Self(vec![starting; players])
+    }
+
+    pub fn get(&self, owner: Owner) -> Stockpile {
+        self.0.get(owner.0 as usize).copied().unwrap_or_default()
+    }
+
+    pub fn get_mut(&mut self, owner: Owner) -> &mut Stockpile {
+        let index = owner.0 as usize;
+        if self.0.len() <= index {
+            self.0.resize(index + 1, Stockpile::default());
+        }
+        &mut self.0[index]
+    }
+}
+
+#[derive(Resource)]
+struct ResourceNodeAssets {
+    minerals_mesh: Handle<Mesh>,
+    minerals_material: Handle<StandardMaterial>,
+    wood_mesh: Handle<Mesh>,
+    wood_material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for ResourceNodeAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let minerals_mesh = meshes.add(shape::Cube::new(RESOURCE_NODE_SIZE).into());
+        let wood_mesh = meshes.add(
+            shape::Cylinder {
+                radius: RESOURCE_NODE_SIZE / 2.0,
+                height: RESOURCE_NODE_SIZE * 2.0,
+                ..default()
+            }
+            .into(),
+        );
+
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        Self {
+            minerals_mesh,
+            minerals_material: materials.add(Color::rgb(0.3, 0.7, 0.95).into()),
+            wood_mesh,
+            wood_material: materials.add(Color::rgb(0.35, 0.25, 0.1).into()),
+        }
+    }
+}
+
+impl Plugin for EconomyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<ResourceNode>()
+            .add_snapshot_component::<Worker>()
+            .add_snapshot_component::<Dropoff>()
+            .add_snapshot_resource::<Stockpiles>()
+            .register_type::<ResourceKind>()
+            .register_type::<Option<ResourceKind>>()
+            .register_type::<Option<Entity>>()
+            .register_type::<Stockpile>()
+            .register_type::<Vec<Stockpile>>()
+            .add_simulation_event::<SpawnResourceNode>()
+            .init_resource::<Stockpiles>()
+            .add_systems(
+                (harvest_resources, deliver_cargo)
+                    .chain()
+                    .in_set(StepSet::Economy)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                spawn_resource_nodes
+                    .in_set(SpawnSet::ResourceNodes)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(add_resource_node_bodies);
+    }
+}
+
+impl Plugin for EconomyViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(ResourceInspectorPlugin::<Stockpiles>::default())
+            .init_resource::<ResourceNodeAssets>()
+            .add_system(add_resource_node_models);
+    }
+}
+
+/// State that walks a worker to the node and harvests it
+pub fn gather_from(node: Entity, node_position: Vec3, from: Vec3) -> UnitState {
+    UnitState::Gathering {
+        node,
+        position: approach_point(node_position, RESOURCE_NODE_SIZE, from),
+    }
+}
+
+fn return_to(dropoff: Entity, dropoff_position: Vec3, size: f32, from: Vec3) -> UnitState {
+    UnitState::ReturningCargo {
+        dropoff,
+        position: approach_point(dropoff_position, size, from),
+    }
+}
+
+/// Closest node of the kind that is not used up. Nodes as far away as each other go by
+/// stable id, the order of a query changes when a snapshot is restored
+fn nearest_node<'a>(
+    nodes: impl Iterator<Item = (Entity, &'a ResourceNode, &'a Transform, &'a StableId)>,
+    kind: ResourceKind,
+    from: Vec3,
+) -> Option<(Entity, Vec3)> {
+    nodes
+        .filter(|(_, node, ..)| node.kind == kind && node.amount > 0)
+        .map(|(entity, _, transform, id)| (entity, transform.translation, *id))
+        .min_by(|(_, a, a_id), (_, b, b_id)| {
+            a.distance(from)
+                .total_cmp(&b.distance(from))
+                .then(a_id.cmp(b_id))
+        })
+        .map(|(entity, position, _)| (entity, position))
+}
+
+/// Closest drop-off of the owner with the size of its footprint, ties go by stable id
+#[allow(clippy::type_complexity)]
+fn nearest_dropoff(
+    dropoffs: &Query<(
+        Entity,
+        &Transform,
+        &Owner,
+        &Footprint,
+        &StableId,
+        With<Dropoff>,
+    )>,
+    owner: Owner,
+    from: Vec3,
+) -> Option<(Entity, Vec3, f32)> {
+    dropoffs
+        .iter()
+        .filter(|(_, _, dropoff_owner, ..)| **dropoff_owner == owner)
+        .min_by(|(_, a, _, _, a_id, _), (_, b, _, _, b_id, _)| {
+            a.translation
+                .distance(from)
+                .total_cmp(&b.translation.distance(from))
+                .then(a_id.cmp(b_id))
+        })
+        .map(|(entity, transform, _, footprint, ..)| {
+            (
+                entity,
+                transform.translation,
+                footprint.size().max_element(),
+            )
+        })
+}
+
+fn spawn_resource_nodes(
+    mut commands: Commands,
+    mut reader: EventReader<SpawnResourceNode>,
+    mut ids: ResMut<StableIds>,
+) {
+    for event in reader.iter() {
+        let half_height = match event.kind {
+            ResourceKind::Minerals => RESOURCE_NODE_SIZE / 2.0,
+            ResourceKind::Wood => RESOURCE_NODE_SIZE,
+        };
+
+        commands.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(event.position)),
+            ids.next(),
+            PlaceOnTerrain(half_height),
+            Name::from("Resource Node"),
+            ResourceNode {
+                kind: event.kind,
+                amount: event.amount,
+            },
+        ));
+    }
+}
+
+/// Minerals are blocks and wood is a trunk
+fn add_resource_node_bodies(
+    mut commands: Commands,
+    nodes: Query<(Entity, &ResourceNode), Added<ResourceNode>>,
+) {
+    for (entity, node) in &nodes {
+        let collider = match node.kind {
+            ResourceKind::Minerals => Collider::cuboid(
+                RESOURCE_NODE_SIZE / 2.0,
+                RESOURCE_NODE_SIZE / 2.0,
+                RESOURCE_NODE_SIZE / 2.0,
+            ),
+            ResourceKind::Wood => Collider::cylinder(RESOURCE_NODE_SIZE, RESOURCE_NODE_SIZE / 2.0),
+        };
+        commands.entity(entity).insert((collider, RigidBody::Fixed));
+    }
+}
+
+fn add_resource_node_models(
+    mut commands: Commands,
+    nodes: Query<(Entity, &ResourceNode), Added<ResourceNode>>,
+    assets: Res<ResourceNodeAssets>,
+) {
+    for (entity, node) in &nodes {
+        let (mesh, material) = match node.kind {
+            ResourceKind::Minerals => (&assets.minerals_mesh, &assets.minerals_material),
+            ResourceKind::Wood => (&assets.wood_mesh, &assets.wood_material),
+        };
+        commands
+            .entity(entity)
+            .insert((mesh.clone(), material.clone()));
+    }
+}
+
+/// Fills workers standing at their node and sends them back once they are full.
+/// Workers whose node ran out move on to the nearest node of the same kind.
+#[allow(clippy::type_complexity)]
+fn harvest_resources(
+    mut commands: Commands,
+    mut workers: Query<(&StableId, &mut Unit, &mut Worker, &Transform, &Owner)>,
+    mut nodes: Query<(Entity, &mut ResourceNode, &Transform, &StableId)>,
+    dropoffs: Query<(
+        Entity,
+        &Transform,
+        &Owner,
+        &Footprint,
+        &StableId,
+        With<Dropoff>,
+    )>,
+    time: Res<SimulationTime>,
+) {
+    // Workers share nodes, the last resources of a node go to the worker with the lowest id
+    let mut workers: Vec<_> = workers.iter_mut().collect();
+    workers.sort_by_key(|(id, ..)| **id);
+
+    for (_, mut unit, mut worker, transform, owner) in workers {
+        let UnitState::Gathering { node, position } = unit.state else {
+            continue;
+        };
+
+        let from = transform.translation;
+        let Ok((_, mut resource_node, ..)) = nodes.get_mut(node) else {
+            // The node was used up by someone else
+            unit.state = match worker.resource {
+                Some(kind) => next_gathering_state(&worker, &nodes, &dropoffs, kind, *owner, from),
+                None => UnitState::Idle,
+            };
+            continue;
+        };
+
+        if resource_node.amount == 0 {
+            let kind = resource_node.kind;
+            unit.state = next_gathering_state(&worker, &nodes, &dropoffs, kind, *owner, from);
+            continue;
+        }
+
+        if !has_arrived(transform, position) {
+            continue;
+        }
+
+        if worker.resource != Some(resource_node.kind) {
+            // Cargo of another kind is dropped
+            worker.carrying = 0;
+            worker.resource = Some(resource_node.kind);
+        }
+        worker.last_node = Some(node);
+
+        worker.harvest_timer.tick(time.delta());
+        if !worker.harvest_timer.just_finished() {
+            continue;
+        }
+
+        let taken = HARVEST_AMOUNT
+            .min(resource_node.amount)
+            .min(worker.capacity - worker.carrying);
+        resource_node.amount -= taken;
+        worker.carrying += taken;
+
+        let kind = resource_node.kind;
+        if resource_node.amount == 0 {
+            commands.entity(node).despawn_recursive();
+        }
+
+        if worker.is_full() {
+            unit.state = match nearest_dropoff(&dropoffs, *owner, from) {
+                Some((dropoff, position, size)) => return_to(dropoff, position, size, from),
+                None => UnitState::Idle,
+            };
+        } else if resource_node.amount == 0 {
+            unit.state = next_gathering_state(&worker, &nodes, &dropoffs, kind, *owner, from);
+        }
+    }
+}
+
+/// Nearest node of the same kind, or a drop-off if there are none left and the worker carries something
+#[allow(clippy::type_complexity)]
+fn next_gathering_state(
+    worker: &Worker,
+    nodes: &Query<(Entity, &mut ResourceNode, &Transform, &StableId)>,
+    dropoffs: &Query<(
+        Entity,
+        &Transform,
+        &Owner,
+        &Footprint,
+        &StableId,
+        With<Dropoff>,
+    )>,
+    kind: ResourceKind,
+    owner: Owner,
+    from: Vec3,
+) -> UnitState {
+    if let Some((node, node_position)) = nearest_node(nodes.iter(), kind, from) {
+        return gather_from(node, node_position, from);
+    }
+
+    match nearest_dropoff(dropoffs, owner, from) {
+        Some((dropoff, position, size)) if worker.carrying > 0 => {
+            return_to(dropoff, position, size, from)
+        }
+        _ => UnitState::Idle,
+    }
+}
+
+/// Adds the cargo of workers that reached a drop-off to their owner's stockpile
+/// and sends them back to gather more
+#[allow(clippy::type_complexity)]
+fn deliver_cargo(
+    mut workers: Query<(&mut Unit, &mut Worker, &Transform, &Owner)>,
+    nodes: Query<(Entity, &ResourceNode, &Transform, &StableId)>,
+    dropoffs: Query<(
+        Entity,
+        &Transform,
+        &Owner,
+        &Footprint,
+        &StableId,
+        With<Dropoff>,
+    )>,
+    mut stockpiles: ResMut<Stockpiles>,
+) {
+    for (mut unit, mut worker, transform, owner) in &mut workers {
+        let UnitState::ReturningCargo { dropoff, position } = unit.state else {
+            continue;
+        };
+
+        let from = transform.translation;
+        if !dropoffs.contains(dropoff) {
+            unit.state = match nearest_dropoff(&dropoffs, *owner, from) {
+                Some((dropoff, position, size)) => return_to(dropoff, position, size, from),
+                None => UnitState::Idle,
+            };
+            continue;
+        }
+
+        if !has_arrived(transform, position) {
+            continue;
+        }
+
+        let Some(kind) = worker.resource else {
+            unit.state = UnitState::Idle;
+            continue;
+        };
+
+        stockpiles.get_mut(*owner).add(kind, worker.carrying);
+        worker.carrying = 0;
+
+        let last_node = worker
+            .last_node
+            .and_then(|node| nodes.get(node).ok())
+            .filter(|(_, node, ..)| node.amount > 0)
+            .map(|(entity, _, transform, _)| (entity, transform.translation));
+
+        unit.state = match last_node.or_else(|| nearest_node(nodes.iter(), kind, from)) {
+            Some((node, node_position)) => gather_from(node, node_position, from),
+            None => UnitState::Idle,
+        };
+    }
+}
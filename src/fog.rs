@@ -0,0 +1,472 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    utils::HashMap,
+};
+
+use crate::{
+    buildings::Building,
+    ground::{Ground, Terrain},
+    player::{LocalPlayer, Owner},
+    selection::Selectable,
+    simulation::StepSet,
+    units::Unit,
+    GameState,
+};
+
+/// What every player sees, updated on the tick so that commands can be checked against it
+pub struct VisionPlugin;
+
+/// Darkens the ground and hides the units the local player does not see
+pub struct FogPlugin;
+
+/// Width of a visibility cell in world units
+const FOG_CELL_SIZE: f32 = 1.0;
+/// Height of the eyes of a viewer above the terrain, used for line of sight
+const EYE_HEIGHT: f32 = 1.5;
+
+const VISIBLE_SHADE: u8 = 255;
+const EXPLORED_SHADE: u8 = 110;
+const UNEXPLORED_SHADE: u8 = 20;
+
+const EXPLORED_CELL: char = '#';
+const UNEXPLORED_CELL: char = '.';
+
+/// How far an entity can see, in world units
+#[derive(Component, Debug, Default, Clone, Copy, Reflect)]
+#[reflect(Component)]
+pub struct SightRange(pub f32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellVisibility {
+    Unexplored,
+    Explored,
+    Visible,
+}
+
+/// Per player visibility of the map, split into square cells.
+/// Viewers add to the cells they see and only restamp when they move to another cell.
+#[derive(Resource)]
+pub struct VisibilityGrid {
+    size: f32,
+    cells_per_side: usize,
+    players: Vec<PlayerVision>,
+    stamps: HashMap<Entity, Stamp>,
+}
+
+#[derive(Default, Clone)]
+struct PlayerVision {
+    /// Number of viewers that currently see each cell
+    viewers: Vec<u16>,
+    explored: Vec<bool>,
+    /// Cells whose visibility changed since the fog texture was last updated
+    dirty: Vec<usize>,
+}
+
+struct Stamp {
+    player: u8,
+    center: usize,
+    range: f32,
+    cells: Vec<usize>,
+}
+
+#[derive(Resource)]
+struct FogTexture(Handle<Image>);
+
+impl Plugin for VisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            update_viewers
+                .in_set(StepSet::Vision)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+impl Plugin for FogPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                create_fog_texture.run_if(resource_added::<VisibilityGrid>()),
+                apply_fog_to_ground,
+                hide_units_outside_vision,
+                update_fog_texture,
+            )
+                .chain()
+                .distributive_run_if(resource_exists::<VisibilityGrid>())
+                .in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
+
+impl PlayerVision {
+    /// Nothing takes the dirty cells in games without a fog texture, so they stop
+    /// being collected once there are as many as cells and the whole texture is redrawn
+    fn mark_dirty(&mut self, cell: usize) {
+        if self.dirty.len() < self.viewers.len() {
+            self.dirty.push(cell);
+        }
+    }
+}
+
+impl VisibilityGrid {
+    pub fn new(size: f32) -> Self {
+        let cells_per_side = (size / FOG_CELL_SIZE).ceil().max(1.0) as usize;
+        Self {
+            size,
+            cells_per_side,
+            players: Vec::new(),
+            stamps: HashMap::default(),
+        }
+    }
+
+    pub fn cells_per_side(&self) -> usize {
+        self.cells_per_side
+    }
+
+    pub fn visibility(&self, player: u8, position: Vec3) -> CellVisibility {
+        self.cell_index(position)
+            .map(|cell| self.cell_visibility(player, cell))
+            .unwrap_or(CellVisibility::Unexplored)
+    }
+
+    pub fn is_visible(&self, player: u8, position: Vec3) -> bool {
+        self.visibility(player, position) == CellVisibility::Visible
+    }
+
+    /// Cells each player has explored, one character per cell.
+    /// The rest of the grid is rebuilt from the viewers
+    pub fn explored(&self) -> Vec<String> {
+        self.players
+            .iter()
+            .map(|vision| {
+                vision
+                    .explored
+                    .iter()
+                    .map(|explored| {
+                        if *explored {
+                            EXPLORED_CELL
+                        } else {
+                            UNEXPLORED_CELL
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Puts back what a player had explored, grids of another size are ignored
+    pub fn set_explored(&mut self, player: u8, cells: &str) {
+        let cell_count = self.cells_per_side * self.cells_per_side;
+        let explored: Vec<bool> = cells.chars().map(|cell| cell == EXPLORED_CELL).collect();
+        if explored.len() != cell_count {
+            return;
+        }
+
+        let vision = self.vision_mut(player);
+        vision.explored = explored;
+        vision.dirty.extend(0..cell_count);
+    }
+
+    fn cell_visibility(&self, player: u8, cell: usize) -> CellVisibility {
+        let Some(vision) = self.players.get(player as usize) else {
+            return CellVisibility::Unexplored;
+        };
+
+        if vision.viewers[cell] > 0 {
+            CellVisibility::Visible
+        } else if vision.explored[cell] {
+            CellVisibility::Explored
+        } else {
+            CellVisibility::Unexplored
+        }
+    }
+
+    fn cell_index(&self, position: Vec3) -> Option<usize> {
+        let column = ((position.x + self.size / 2.0) / FOG_CELL_SIZE).floor();
+        let row = ((position.z + self.size / 2.0) / FOG_CELL_SIZE).floor();
+        let range = 0.0..self.cells_per_side as f32;
+        if !range.contains(&column) || !range.contains(&row) {
+            return None;
+        }
+
+        Some(row as usize * self.cells_per_side + column as usize)
+    }
+
+    fn cell_center(&self, cell: usize) -> Vec3 {
+        let column = (cell % self.cells_per_side) as f32;
+        let row = (cell / self.cells_per_side) as f32;
+        Vec3::new(
+            (column + 0.5) * FOG_CELL_SIZE - self.size / 2.0,
+            0.0,
+            (row + 0.5) * FOG_CELL_SIZE - self.size / 2.0,
+        )
+    }
+
+    fn vision_mut(&mut self, player: u8) -> &mut PlayerVision {
+        let cell_count = self.cells_per_side * self.cells_per_side;
+        if self.players.len() <= player as usize {
+            self.players
+                .resize(player as usize + 1, PlayerVision::default());
+        }
+
+        let vision = &mut self.players[player as usize];
+        if vision.viewers.is_empty() {
+            vision.viewers = vec![0; cell_count];
+            vision.explored = vec![false; cell_count];
+        }
+        vision
+    }
+
+    /// Stamps the viewer again if it moved to another cell or its sight changed
+    fn update_viewer(
+        &mut self,
+        entity: Entity,
+        player: u8,
+        position: Vec3,
+        range: f32,
+        terrain: &Terrain,
+    ) {
+        let Some(center) = self.cell_index(position) else {
+            self.remove_viewer(entity);
+            return;
+        };
+
+        if let Some(stamp) = self.stamps.get(&entity) {
+            if stamp.center == center && stamp.player == player && stamp.range == range {
+                return;
+            }
+        }
+
+        self.remove_viewer(entity);
+        let cells = self.cells_in_sight(center, range, terrain);
+        let vision = self.vision_mut(player);
+        for cell in &cells {
+            vision.viewers[*cell] += 1;
+            if vision.viewers[*cell] == 1 {
+                vision.explored[*cell] = true;
+                vision.mark_dirty(*cell);
+            }
+        }
+
+        self.stamps.insert(
+            entity,
+            Stamp {
+                player,
+                center,
+                range,
+                cells,
+            },
+        );
+    }
+
+    fn remove_viewer(&mut self, entity: Entity) {
+        let Some(stamp) = self.stamps.remove(&entity) else {
+            return;
+        };
+
+        let vision = self.vision_mut(stamp.player);
+        for cell in stamp.cells {
+            vision.viewers[cell] -= 1;
+            if vision.viewers[cell] == 0 {
+                vision.mark_dirty(cell);
+            }
+        }
+    }
+
+    /// Cells within range that are not hidden behind higher terrain
+    fn cells_in_sight(&self, center: usize, range: f32, terrain: &Terrain) -> Vec<usize> {
+        let origin = self.cell_center(center);
+        let eye = terrain.height_at(origin.x, origin.z) + EYE_HEIGHT;
+        let radius = (range / FOG_CELL_SIZE).ceil() as i64;
+        let center_column = (center % self.cells_per_side) as i64;
+        let center_row = (center / self.cells_per_side) as i64;
+        let side = self.cells_per_side as i64;
+
+        let mut cells = Vec::new();
+        for row in (center_row - radius).max(0)..=(center_row + radius).min(side - 1) {
+            for column in (center_column - radius).max(0)..=(center_column + radius).min(side - 1) {
+                let cell = (row * side + column) as usize;
+                let target = self.cell_center(cell);
+                if origin.distance(target) > range {
+                    continue;
+                }
+
+                let target_height = terrain.height_at(target.x, target.z);
+                if has_line_of_sight(terrain, origin, eye, target, target_height) {
+                    cells.push(cell);
+                }
+            }
+        }
+
+        cells
+    }
+}
+
+fn has_line_of_sight(
+    terrain: &Terrain,
+    origin: Vec3,
+    eye: f32,
+    target: Vec3,
+    target_height: f32,
+) -> bool {
+    let distance = origin.distance(target);
+    let steps = (distance / (FOG_CELL_SIZE / 2.0)).ceil() as usize;
+
+    (1..steps).all(|step| {
+        let t = step as f32 / steps as f32;
+        let point = origin.lerp(target, t);
+        let sight_height = eye + (target_height - eye) * t;
+        terrain.height_at(point.x, point.z) <= sight_height
+    })
+}
+
+fn create_fog_texture(
+    mut commands: Commands,
+    grid: Res<VisibilityGrid>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let side = grid.cells_per_side() as u32;
+
+    let image = Image::new_fill(
+        Extent3d {
+            width: side,
+            height: side,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[UNEXPLORED_SHADE, UNEXPLORED_SHADE, UNEXPLORED_SHADE, 255],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+
+    commands.insert_resource(FogTexture(images.add(image)));
+}
+
+fn apply_fog_to_ground(
+    ground: Query<(&Handle<StandardMaterial>, With<Ground>)>,
+    texture: Option<Res<FogTexture>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Some(texture) = texture else {
+        return;
+    };
+
+    for (handle, _) in &ground {
+        let has_fog = materials
+            .get(handle)
+            .map(|material| material.base_color_texture.as_ref() == Some(&texture.0))
+            .unwrap_or(true);
+
+        if !has_fog {
+            if let Some(material) = materials.get_mut(handle) {
+                material.base_color_texture = Some(texture.0.clone());
+            }
+        }
+    }
+}
+
+fn update_viewers(
+    grid: Option<ResMut<VisibilityGrid>>,
+    viewers: Query<(Entity, &Transform, &SightRange, &Owner)>,
+    terrain: Res<Terrain>,
+) {
+    let Some(mut grid) = grid else {
+        return;
+    };
+
+    // Looked up every tick instead of read from the removals, which only last for a frame
+    let removed: Vec<Entity> = grid
+        .stamps
+        .keys()
+        .copied()
+        .filter(|entity| !viewers.contains(*entity))
+        .collect();
+    for entity in removed {
+        grid.remove_viewer(entity);
+    }
+
+    for (entity, transform, sight, owner) in &viewers {
+        grid.update_viewer(entity, owner.0, transform.translation, sight.0, &terrain);
+    }
+}
+
+/// Buildings are hidden like units, so that the fog does not give away what is built in it
+#[allow(clippy::type_complexity)]
+fn hide_units_outside_vision(
+    grid: Option<Res<VisibilityGrid>>,
+    mut units: Query<(
+        &GlobalTransform,
+        &Owner,
+        &mut Visibility,
+        &mut Selectable,
+        Or<(With<Unit>, With<Building>)>,
+    )>,
+    local_player: Res<LocalPlayer>,
+) {
+    let Some(grid) = grid else {
+        return;
+    };
+
+    for (transform, owner, mut visibility, mut selectable, _) in &mut units {
+        if owner.0 == local_player.0 {
+            // They may have been hidden while a replay showed the vision of another player
+            if *visibility == Visibility::Hidden {
+                *visibility = Visibility::Inherited;
+            }
+            continue;
+        }
+
+        if grid.is_visible(local_player.0, transform.translation()) {
+            if *visibility == Visibility::Hidden {
+                *visibility = Visibility::Inherited;
+            }
+        } else if *visibility != Visibility::Hidden {
+            *visibility = Visibility::Hidden;
+            selectable.is_selected = false;
+        }
+    }
+}
+
+fn update_fog_texture(
+    grid: Option<ResMut<VisibilityGrid>>,
+    texture: Option<Res<FogTexture>>,
+    mut images: ResMut<Assets<Image>>,
+    local_player: Res<LocalPlayer>,
+) {
+    let (Some(mut grid), Some(texture)) = (grid, texture) else {
+        return;
+    };
+
+    let local = local_player.0 as usize;
+    let mut dirty = Vec::new();
+    for (player, vision) in grid.players.iter_mut().enumerate() {
+        if player == local {
+            dirty = std::mem::take(&mut vision.dirty);
+        } else {
+            vision.dirty.clear();
+        }
+    }
+
+    // Replays switch between the vision of the players
+    let cell_count = grid.cells_per_side * grid.cells_per_side;
+    if local_player.is_changed() || dirty.len() >= cell_count {
+        dirty = (0..cell_count).collect();
+    }
+
+    if dirty.is_empty() {
+        return;
+    }
+
+    let Some(image) = images.get_mut(&texture.0) else {
+        return;
+    };
+
+    for cell in dirty {
+        let shade = match grid.cell_visibility(local_player.0, cell) {
+            CellVisibility::Visible => VISIBLE_SHADE,
+            CellVisibility::Explored => EXPLORED_SHADE,
+            CellVisibility::Unexplored => UNEXPLORED_SHADE,
+        };
+        image.data[cell * 4..cell * 4 + 3].copy_from_slice(&[shade, shade, shade]);
+    }
+}
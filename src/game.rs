@@ -1,19 +1,70 @@
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::RapierConfiguration;
+use serde::{Deserialize, Serialize};
 
-use crate::GameState;
+use crate::{
+    abilities::AbilityTargeting,
+    buildings::PlacingBuilding,
+    economy::Stockpile,
+    map::LoadMap,
+    simulation::{GameSpeed, SimulationRng},
+    GameState,
+};
 
 pub struct GamePlugin;
 
+/// Pausing and game speed from the keyboard
+pub struct GameInputPlugin;
+
+/// Choices of the skirmish setup, inserted when a game starts and removed when it ends
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct GameSetup {
+    /// Path of the map inside the assets folder
+    pub map: String,
+    pub starting_resources: Stockpile,
+    /// Seeds the randomness of the simulation, the same setup and seed play out the same way
+    pub seed: u64,
+}
+
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(pause_game.run_if(in_state(GameState::InGame)))
-            .add_system(resume_game.run_if(in_state(GameState::Menu)));
+        app.add_system(
+            start_game
+                .run_if(resource_added::<GameSetup>())
+                .in_schedule(OnEnter(GameState::InGame)),
+        )
+        .add_system(end_game.in_schedule(OnEnter(GameState::MainMenu)));
+    }
+}
+
+impl Plugin for GameInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            pause_game
+                .run_if(in_state(GameState::InGame))
+                // Escape cancels the placement instead
+                .run_if(not(resource_exists::<PlacingBuilding>()))
+                .run_if(not(resource_exists::<AbilityTargeting>())),
+        )
+        .add_system(resume_game.run_if(in_state(GameState::Paused)))
+        .add_system(change_speed_from_hotkeys.in_set(OnUpdate(GameState::InGame)))
+        .add_system(freeze_simulation.in_schedule(OnEnter(GameState::Paused)))
+        .add_system(unfreeze_simulation.in_schedule(OnExit(GameState::Paused)));
     }
 }
 
+fn start_game(mut commands: Commands, setup: Res<GameSetup>, mut writer: EventWriter<LoadMap>) {
+    commands.insert_resource(SimulationRng::new(setup.seed));
+    writer.send(LoadMap(setup.map.clone()))
+}
+
+fn end_game(mut commands: Commands) {
+    commands.remove_resource::<GameSetup>();
+}
+
 fn pause_game(input: Res<Input<KeyCode>>, mut next_state: ResMut<NextState<GameState>>) {
     if input.just_pressed(KeyCode::Escape) {
-        next_state.set(GameState::Menu)
+        next_state.set(GameState::Paused)
     }
 }
 
@@ -22,3 +73,23 @@ fn resume_game(input: Res<Input<KeyCode>>, mut next_state: ResMut<NextState<Game
         next_state.set(GameState::InGame)
     }
 }
+
+fn change_speed_from_hotkeys(input: Res<Input<KeyCode>>, mut speed: ResMut<GameSpeed>) {
+    if input.any_just_pressed([KeyCode::Equals, KeyCode::NumpadAdd]) {
+        *speed = speed.faster();
+    }
+    if input.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        *speed = speed.slower();
+    }
+}
+
+/// Stops game time and physics, the interface keeps running
+fn freeze_simulation(mut time: ResMut<Time>, mut rapier: ResMut<RapierConfiguration>) {
+    time.pause();
+    rapier.physics_pipeline_active = false;
+}
+
+fn unfreeze_simulation(mut time: ResMut<Time>, mut rapier: ResMut<RapierConfiguration>) {
+    time.unpause();
+    rapier.physics_pipeline_active = true;
+}
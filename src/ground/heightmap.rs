@@ -0,0 +1,120 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// Fractal value noise, sampled over the whole map
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct NoiseSettings {
+    pub seed: u32,
+    pub octaves: u32,
+    /// Number of noise features along one side of the map for the first octave
+    pub frequency: f32,
+    /// Amplitude multiplier applied to each following octave
+    pub persistence: f32,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            octaves: 4,
+            frequency: 4.0,
+            persistence: 0.5,
+        }
+    }
+}
+
+/// Heights in `0.0..=1.0` for a `resolution` x `resolution` grid
+pub fn from_noise(settings: &NoiseSettings, resolution: usize) -> Vec<f32> {
+    let last = (resolution - 1) as f32;
+    let mut heights = Vec::with_capacity(resolution * resolution);
+
+    for row in 0..resolution {
+        for column in 0..resolution {
+            let x = column as f32 / last;
+            let y = row as f32 / last;
+            heights.push(fractal_noise(settings, x, y));
+        }
+    }
+
+    heights
+}
+
+/// Heights in `0.0..=1.0` read from the first channel of the image
+pub fn from_image(image: &Image, resolution: usize) -> Vec<f32> {
+    let width = image.texture_descriptor.size.width as usize;
+    let height = image.texture_descriptor.size.height as usize;
+    let bytes_per_pixel = (image.data.len() / (width * height)).max(1);
+    let pixel = |x: usize, y: usize| image.data[(y * width + x) * bytes_per_pixel] as f32 / 255.0;
+
+    let last = (resolution - 1) as f32;
+    let mut heights = Vec::with_capacity(resolution * resolution);
+
+    for row in 0..resolution {
+        for column in 0..resolution {
+            let x = column as f32 / last * (width - 1) as f32;
+            let y = row as f32 / last * (height - 1) as f32;
+            let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+            let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+            let (fx, fy) = (x.fract(), y.fract());
+
+            let top = lerp(pixel(x0, y0), pixel(x1, y0), fx);
+            let bottom = lerp(pixel(x0, y1), pixel(x1, y1), fx);
+            heights.push(lerp(top, bottom, fy));
+        }
+    }
+
+    heights
+}
+
+fn fractal_noise(settings: &NoiseSettings, x: f32, y: f32) -> f32 {
+    let mut frequency = settings.frequency;
+    let mut amplitude = 1.0;
+    let mut total = 0.0;
+    let mut max = 0.0;
+
+    for octave in 0..settings.octaves {
+        let seed = settings.seed.wrapping_add(octave);
+        total += value_noise(seed, x * frequency, y * frequency) * amplitude;
+        max += amplitude;
+        frequency *= 2.0;
+        amplitude *= settings.persistence;
+    }
+
+    if max > 0.0 {
+        total / max
+    } else {
+        0.0
+    }
+}
+
+fn value_noise(seed: u32, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (smoothstep(x - x0), smoothstep(y - y0));
+    let (x0, y0) = (x0 as i32, y0 as i32);
+
+    let top = lerp(hash(seed, x0, y0), hash(seed, x0 + 1, y0), fx);
+    let bottom = lerp(hash(seed, x0, y0 + 1), hash(seed, x0 + 1, y0 + 1), fx);
+    lerp(top, bottom, fy)
+}
+
+/// Deterministic pseudo random value in `0.0..=1.0` for a lattice point
+fn hash(seed: u32, x: i32, y: i32) -> f32 {
+    let mut hash = seed.wrapping_mul(0x9E37_79B9)
+        ^ (x as u32).wrapping_mul(0x85EB_CA6B)
+        ^ (y as u32).wrapping_mul(0xC2B2_AE35);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x7FEB_352D);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x846C_A68B);
+    hash ^= hash >> 16;
+    hash as f32 / u32::MAX as f32
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
@@ -0,0 +1,76 @@
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+
+use super::Terrain;
+
+const SAND_COLOR: Color = Color::rgb(0.76, 0.7, 0.5);
+const GRASS_COLOR: Color = Color::rgb(0.3, 0.5, 0.3);
+const ROCK_COLOR: Color = Color::rgb(0.45, 0.42, 0.4);
+const SNOW_COLOR: Color = Color::rgb(0.95, 0.95, 0.97);
+
+/// Blends the terrain colours by normalized height and by slope (`0.0` is flat)
+pub fn terrain_color(height: f32, slope: f32) -> Color {
+    let sand = Vec4::from(SAND_COLOR.as_linear_rgba_f32());
+    let grass = Vec4::from(GRASS_COLOR.as_linear_rgba_f32());
+    let rock = Vec4::from(ROCK_COLOR.as_linear_rgba_f32());
+    let snow = Vec4::from(SNOW_COLOR.as_linear_rgba_f32());
+
+    let color = sand.lerp(grass, smoothstep(0.05, 0.15, height));
+    let color = color.lerp(snow, smoothstep(0.75, 0.9, height));
+    let color = color.lerp(rock, smoothstep(0.1, 0.3, slope));
+
+    Color::rgba_linear(color.x, color.y, color.z, 1.0)
+}
+
+pub fn build_terrain_mesh(terrain: &Terrain) -> Mesh {
+    let resolution = terrain.resolution;
+    let last = (resolution - 1) as f32;
+    let vertex_count = resolution * resolution;
+
+    let mut positions = Vec::with_capacity(vertex_count);
+    let mut normals = Vec::with_capacity(vertex_count);
+    let mut uvs = Vec::with_capacity(vertex_count);
+    let mut colors = Vec::with_capacity(vertex_count);
+
+    for row in 0..resolution {
+        for column in 0..resolution {
+            let u = column as f32 / last;
+            let v = row as f32 / last;
+            let x = (u - 0.5) * terrain.size;
+            let z = (v - 0.5) * terrain.size;
+
+            positions.push([x, terrain.sample(column, row), z]);
+            normals.push(terrain.normal_at(x, z).to_array());
+            uvs.push([u, v]);
+            colors.push(terrain.color_at(x, z).as_linear_rgba_f32());
+        }
+    }
+
+    let mut indices = Vec::with_capacity((resolution - 1) * (resolution - 1) * 6);
+    for row in 0..resolution - 1 {
+        for column in 0..resolution - 1 {
+            let top_left = (row * resolution + column) as u32;
+            let top_right = top_left + 1;
+            let bottom_left = top_left + resolution as u32;
+            let bottom_right = bottom_left + 1;
+
+            indices.extend([top_left, bottom_left, top_right]);
+            indices.extend([bottom_left, bottom_right, top_right]);
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
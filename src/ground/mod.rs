@@ -0,0 +1,232 @@
+mod heightmap;
+mod mesh;
+
+use std::path::Path;
+
+use bevy::{
+    prelude::*,
+    render::texture::{CompressedImageFormats, ImageType},
+};
+use bevy_rapier3d::prelude::Collider;
+use serde::Deserialize;
+
+use crate::{map::assets_folder, simulation::StepSet, snapshot::SnapshotApp};
+
+pub use self::heightmap::NoiseSettings;
+
+pub struct GroundPlugin;
+
+/// Terrain mesh and collider for picking the ground
+pub struct GroundViewPlugin;
+
+/// Number of height samples along each side of the terrain
+const TERRAIN_RESOLUTION: usize = 129;
+
+#[derive(Component)]
+pub struct Ground;
+
+/// Keeps the entity on the terrain surface, raised by the given offset
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct PlaceOnTerrain(pub f32);
+
+/// Where terrain heights are taken from
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TerrainSource {
+    /// Path to a grayscale image in the assets folder, black is the lowest point
+    Heightmap(String),
+    Noise(NoiseSettings),
+}
+
+/// Terrain to generate, inserting or changing it rebuilds the ground
+#[derive(Resource, Debug, Clone)]
+pub struct TerrainSettings {
+    pub source: TerrainSource,
+    pub size: f32,
+    pub max_height: f32,
+}
+
+/// Height samples of the spawned terrain, laid out row by row along the z axis
+#[derive(Resource)]
+pub struct Terrain {
+    size: f32,
+    max_height: f32,
+    resolution: usize,
+    heights: Vec<f32>,
+}
+
+impl Plugin for GroundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<PlaceOnTerrain>()
+            .add_system(
+                generate_terrain.run_if(
+                    resource_exists::<TerrainSettings>()
+                        .and_then(resource_changed::<TerrainSettings>()),
+                ),
+            )
+            .add_system(
+                place_on_terrain
+                    .in_set(StepSet::Placement)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+impl Plugin for GroundViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            spawn_ground
+                .run_if(resource_exists::<Terrain>().and_then(resource_changed::<Terrain>())),
+        );
+    }
+}
+
+impl Terrain {
+    fn new(settings: &TerrainSettings, normalized_heights: Vec<f32>) -> Self {
+        Self {
+            size: settings.size,
+            max_height: settings.max_height,
+            resolution: TERRAIN_RESOLUTION,
+            heights: normalized_heights
+                .into_iter()
+                .map(|height| height * settings.max_height)
+                .collect(),
+        }
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Height of the surface at the given world position, following the collider triangles
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let last = (self.resolution - 1) as f32;
+        let column = ((x / self.size + 0.5) * last).clamp(0.0, last);
+        let row = ((z / self.size + 0.5) * last).clamp(0.0, last);
+
+        let column_index = (column.floor() as usize).min(self.resolution - 2);
+        let row_index = (row.floor() as usize).min(self.resolution - 2);
+        let fx = column - column_index as f32;
+        let fz = row - row_index as f32;
+
+        let h00 = self.sample(column_index, row_index);
+        let h10 = self.sample(column_index + 1, row_index);
+        let h01 = self.sample(column_index, row_index + 1);
+        let h11 = self.sample(column_index + 1, row_index + 1);
+
+        // Cells are split along the same diagonal as the Rapier heightfield
+        if fx + fz <= 1.0 {
+            h00 + fx * (h10 - h00) + fz * (h01 - h00)
+        } else {
+            h11 + (1.0 - fx) * (h01 - h11) + (1.0 - fz) * (h10 - h11)
+        }
+    }
+
+    pub fn normal_at(&self, x: f32, z: f32) -> Vec3 {
+        let step = self.cell_size();
+        let dx = self.height_at(x + step, z) - self.height_at(x - step, z);
+        let dz = self.height_at(x, z + step) - self.height_at(x, z - step);
+        Vec3::new(-dx, 2.0 * step, -dz).normalize()
+    }
+
+    /// Colour of the terrain material at the given world position
+    pub fn color_at(&self, x: f32, z: f32) -> Color {
+        mesh::terrain_color(
+            self.height_at(x, z) / self.max_height,
+            1.0 - self.normal_at(x, z).y,
+        )
+    }
+
+    fn cell_size(&self) -> f32 {
+        self.size / (self.resolution - 1) as f32
+    }
+
+    fn sample(&self, column: usize, row: usize) -> f32 {
+        self.heights[row * self.resolution + column]
+    }
+
+    fn collider(&self) -> Collider {
+        // Rapier expects a column-major matrix with rows along the z axis
+        let mut heights = Vec::with_capacity(self.heights.len());
+        for column in 0..self.resolution {
+            for row in 0..self.resolution {
+                heights.push(self.sample(column, row));
+            }
+        }
+
+        Collider::heightfield(
+            heights,
+            self.resolution,
+            self.resolution,
+            Vec3::new(self.size, 1.0, self.size),
+        )
+    }
+}
+
+/// Builds the terrain right away, heightmaps are read without the asset server
+fn generate_terrain(mut commands: Commands, settings: Res<TerrainSettings>) {
+    let heights = match &settings.source {
+        TerrainSource::Heightmap(path) => match load_heightmap(path) {
+            Ok(image) => heightmap::from_image(&image, TERRAIN_RESOLUTION),
+            Err(error) => {
+                error!("{}: {}", path, error);
+                return;
+            }
+        },
+        TerrainSource::Noise(noise) => heightmap::from_noise(noise, TERRAIN_RESOLUTION),
+    };
+    commands.insert_resource(Terrain::new(&settings, heights));
+}
+
+fn load_heightmap(path: &str) -> Result<Image, String> {
+    let bytes = std::fs::read(assets_folder().join(path)).map_err(|error| error.to_string())?;
+    let extension = Path::new(path)
+        .extension()
+        .map_or("", |extension| extension.to_str().unwrap_or_default());
+    Image::from_buffer(
+        &bytes,
+        ImageType::Extension(extension),
+        CompressedImageFormats::NONE,
+        true,
+    )
+    .map_err(|error| error.to_string())
+}
+
+fn spawn_ground(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    ground: Query<(Entity, With<Ground>)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, _) in &ground {
+        commands.entity(entity).despawn_recursive()
+    }
+
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(mesh::build_terrain_mesh(&terrain)),
+            material: materials.add(StandardMaterial {
+                perceptual_roughness: 1.0,
+                ..default()
+            }),
+            ..default()
+        },
+        terrain.collider(),
+        Name::from("Ground"),
+        Ground,
+    ));
+}
+
+fn place_on_terrain(
+    mut commands: Commands,
+    mut entities: Query<(Entity, &mut Transform, &PlaceOnTerrain)>,
+    terrain: Res<Terrain>,
+) {
+    for (entity, mut transform, place) in &mut entities {
+        transform.translation.y =
+            terrain.height_at(transform.translation.x, transform.translation.z) + place.0;
+        commands.entity(entity).remove::<PlaceOnTerrain>();
+    }
+}
@@ -0,0 +1,577 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{log::LogPlugin, prelude::*, time::TimePlugin};
+
+use crate::{
+    checksum::{dump_state, state_hash, StateSnapshot},
+    command::{CommandQueue, IssuedCommand},
+    economy::STARTING_RESOURCES,
+    game::GameSetup,
+    ground::Terrain,
+    map::{CurrentMap, MapFilePlugin, DEFAULT_MAP},
+    network::{self, Lockstep, LoopbackTransport, Transport, UdpTransport, DEFAULT_INPUT_DELAY},
+    player::{LocalPlayer, Players},
+    replay::Replay,
+    save::SaveFile,
+    simulation::{SimulationPlugins, SimulationTime},
+    GameState,
+};
+
+/// Frames the map gets to load in before the game is given up on
+const LOADING_FRAMES: u32 = 10;
+/// Lockstep games that make no progress for this long are given up on
+const LOCKSTEP_TIMEOUT: Duration = Duration::from_secs(10);
+/// A lockstep game that is done keeps answering a while, the others may still need its frames
+const LOCKSTEP_LINGER: Duration = Duration::from_secs(1);
+
+/// Game without a window, camera or input, ticked by hand instead of by the clock
+pub struct HeadlessGame {
+    pub app: App,
+}
+
+impl HeadlessGame {
+    /// Starts the game and loads its map, the first tick has not run yet
+    pub fn start(setup: GameSetup, players: Players) -> Result<Self, String> {
+        let mut app = App::new();
+        app.add_state::<GameState>()
+            .add_plugins(MinimalPlugins.build().disable::<TimePlugin>())
+            .add_plugin(LogPlugin::default())
+            .add_plugins(SimulationPlugins)
+            .add_plugin(MapFilePlugin);
+
+        // The main menu ends any game, so the setup is only inserted once it has been entered
+        app.update();
+        let map = setup.map.clone();
+        app.insert_resource(setup).insert_resource(players);
+        app.world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::InGame);
+
+        for _ in 0..LOADING_FRAMES {
+            app.update();
+            if app.world.contains_resource::<CurrentMap>()
+                && app.world.contains_resource::<Terrain>()
+            {
+                return Ok(Self { app });
+            }
+        }
+
+        Err(format!("{} could not be loaded", map))
+    }
+
+    pub fn tick(&mut self) {
+        self.app.world.run_schedule(CoreSchedule::FixedUpdate);
+        self.app.update();
+    }
+
+    /// Ticks simulated so far, a lockstep game does not advance while it waits for inputs
+    pub fn ticks(&self) -> u64 {
+        self.app.world.resource::<SimulationTime>().tick()
+    }
+
+    pub fn state(&self) -> StateSnapshot {
+        StateSnapshot::capture(&self.app.world)
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        state_hash(&self.app.world)
+    }
+}
+
+/// Runs a game for a number of ticks from the command line and prints the state hash,
+/// `--headless [--map <path>] [--seed <number>] [--ticks <number>] [--replay <path>]
+/// [--load <path>] [--save <path>]`. A replay brings its own setup and commands, and is
+/// checked against the state it recorded. A loaded game goes on for the given ticks
+/// from where it was saved.
+///
+/// `--loopback <players>` plays a lockstep game with every player in this process, and
+/// `--lockstep <player> --peers <address>,...` plays one over UDP with other processes.
+/// Both take `--delay <ticks>`, and `--input <replay>` in which case every player gives the
+/// commands its player gave in the replay.
+///
+/// `--diff <path> <path>` compares two states that were dumped when a game went out of sync
+pub fn run_from_args(args: &[String]) -> Result<(), String> {
+    let mut setup = GameSetup {
+        map: DEFAULT_MAP.to_string(),
+        starting_resources: STARTING_RESOURCES,
+        seed: 0,
+    };
+    let mut ticks = 600;
+    let mut replay = None;
+    let mut load = None;
+    let mut save = None;
+    let mut loopback = None;
+    let mut lockstep: Option<u8> = None;
+    let mut peers = None;
+    let mut input_delay = DEFAULT_INPUT_DELAY;
+    let mut input = None;
+    let mut diff = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--headless" => {}
+            "--map" => setup.map = value()?.clone(),
+            "--seed" => setup.seed = value()?.parse().map_err(|_| "invalid seed")?,
+            "--ticks" => ticks = value()?.parse().map_err(|_| "invalid tick count")?,
+            "--replay" => replay = Some(value()?.clone()),
+            "--load" => load = Some(value()?.clone()),
+            "--save" => save = Some(value()?.clone()),
+            "--loopback" => loopback = Some(value()?.parse().map_err(|_| "invalid player count")?),
+            "--lockstep" => lockstep = Some(value()?.parse().map_err(|_| "invalid player")?),
+            "--peers" => peers = Some(network::parse_addresses(value()?)?),
+            "--delay" => input_delay = value()?.parse().map_err(|_| "invalid input delay")?,
+            "--input" => input = Some(Replay::load(value()?.as_ref())?),
+            "--diff" => diff = Some((value()?.clone(), value()?.clone())),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    if let Some(path) = replay {
+        return verify_replay(Replay::load(path.as_ref())?);
+    }
+    if let Some((first, second)) = diff {
+        let first = StateSnapshot::read(first.as_ref())?;
+        let second = StateSnapshot::read(second.as_ref())?;
+        return match first.diff(&second) {
+            Some(diff) => Err(diff.to_string()),
+            None => {
+                println!("the states are the same");
+                Ok(())
+            }
+        };
+    }
+    if let Some(input) = &input {
+        setup = input.header.setup();
+    }
+    if let Some(players) = loopback {
+        let transports = LoopbackTransport::connect(players)
+            .into_iter()
+            .enumerate()
+            .map(|(player, transport)| (player as u8, transport))
+            .collect();
+        return run_lockstep(
+            setup,
+            players,
+            transports,
+            ticks,
+            input_delay,
+            input.as_ref(),
+        );
+    }
+    if let Some(player) = lockstep {
+        let addresses = peers.ok_or("--lockstep needs --peers")?;
+        if player == 0 || player as usize > addresses.len() {
+            return Err(format!("player {} has no address in --peers", player));
+        }
+        let players = addresses.len();
+        let transport = UdpTransport::bind(player - 1, addresses)?;
+        return run_lockstep(
+            setup,
+            players,
+            vec![(player - 1, transport)],
+            ticks,
+            input_delay,
+            input.as_ref(),
+        );
+    }
+
+    let mut game = match load {
+        Some(path) => {
+            let save = SaveFile::read(path.as_ref())?;
+            let mut game = HeadlessGame::start(save.setup.clone(), Players(save.players.clone()))?;
+            save.restore_state(&mut game.app.world)?;
+            game
+        }
+        None => HeadlessGame::start(setup, Players::default())?,
+    };
+    for _ in 0..ticks {
+        game.tick();
+    }
+    println!(
+        "tick {} state {:016x}",
+        game.app.world.resource::<SimulationTime>().tick(),
+        game.state_hash()
+    );
+
+    if let Some(path) = save {
+        SaveFile::capture(&game.app.world)?.write(path.as_ref())?;
+    }
+    Ok(())
+}
+
+/// Plays a replay to its end, failing at the first state hash that differs from the recording
+fn verify_replay(replay: Replay) -> Result<(), String> {
+    let mut game = HeadlessGame::start(replay.header.setup(), replay.header.players())?;
+    let mut queue = game.app.world.resource_mut::<CommandQueue>();
+    for command in replay.commands_from(0) {
+        queue.push(command);
+    }
+
+    for tick in 1..=replay.length {
+        game.tick();
+        let Some(expected) = replay.checksum(tick) else {
+            continue;
+        };
+        let state = game.state();
+        let actual = state.hash();
+        if actual != expected {
+            let dumped = match dump_state(&state, &format!("replay-tick-{}", tick)) {
+                Ok(path) => format!("the state is written to {}", path.display()),
+                Err(error) => format!("the state could not be written: {}", error),
+            };
+            return Err(format!(
+                "replay went out of sync at tick {}, state {:016x} instead of {:016x}, {}",
+                tick, actual, expected, dumped
+            ));
+        }
+    }
+    println!(
+        "replay played to tick {} in sync, state {:016x}",
+        replay.length,
+        game.state_hash()
+    );
+    Ok(())
+}
+
+/// Plays a lockstep game for the given local players until all of them reach the tick count,
+/// failing if their states went apart
+fn run_lockstep<T: Transport>(
+    setup: GameSetup,
+    players: usize,
+    transports: Vec<(u8, T)>,
+    ticks: u64,
+    input_delay: u64,
+    input: Option<&Replay>,
+) -> Result<(), String> {
+    let mut games = Vec::new();
+    let mut inputs = Vec::new();
+    for (player, transport) in transports {
+        let mut game = HeadlessGame::start(setup.clone(), Players::humans(players))?;
+        game.app
+            .insert_resource(LocalPlayer(player))
+            .insert_resource(Lockstep::new(transport, player, players, input_delay));
+        games.push(game);
+
+        // Latest first, so that the next command is popped off the end
+        let mut commands: Vec<IssuedCommand> = input.map_or_else(Vec::new, |replay| {
+            replay
+                .commands_from(0)
+                .filter(|command| command.player == player)
+                .collect()
+        });
+        commands.reverse();
+        inputs.push(commands);
+    }
+
+    let mut last_progress = Instant::now();
+    let mut desync_shown = false;
+    while games.iter().any(|game| game.ticks() < ticks) {
+        let mut progressed = false;
+        for (game, commands) in games.iter_mut().zip(&mut inputs) {
+            let tick = game.ticks();
+            let mut lockstep = game.app.world.resource_mut::<Lockstep>();
+            if tick >= ticks {
+                lockstep.poll();
+                continue;
+            }
+
+            // The frame this tick closes is applied `input_delay` ticks later
+            while let Some(command) = commands.pop() {
+                if command.tick > tick + input_delay {
+                    commands.push(command);
+                    break;
+                }
+                lockstep.issue(command.command);
+            }
+            game.tick();
+            progressed |= game.ticks() > tick;
+        }
+
+        // Shown once the players have sent each other the states they dumped
+        if !desync_shown {
+            desync_shown = print_desync(&games);
+        }
+
+        if progressed {
+            last_progress = Instant::now();
+        } else if last_progress.elapsed() > LOCKSTEP_TIMEOUT {
+            let tick = games.iter().map(HeadlessGame::ticks).min().unwrap_or(0);
+            return Err(format!("lockstep game got stuck at tick {}", tick));
+        } else {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    let finished = Instant::now();
+    while finished.elapsed() < LOCKSTEP_LINGER {
+        for game in &mut games {
+            game.app.world.resource_mut::<Lockstep>().poll();
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    for game in &games {
+        println!(
+            "player {} tick {} state {:016x}",
+            game.app.world.resource::<LocalPlayer>().0 + 1,
+            game.ticks(),
+            game.state_hash()
+        );
+    }
+    if let Some(tick) = desynced_at(&games) {
+        return Err(format!("players went out of sync at tick {}", tick));
+    }
+    if let Some((first, others)) = games.split_first() {
+        let state = first.state();
+        if let Some(diff) = others.iter().find_map(|other| state.diff(&other.state())) {
+            return Err(format!("players ended in different states, {}", diff));
+        }
+    }
+    Ok(())
+}
+
+fn desynced_at(games: &[HeadlessGame]) -> Option<u64> {
+    games
+        .iter()
+        .filter_map(|game| game.app.world.resource::<Lockstep>().desynced_at())
+        .min()
+}
+
+/// Prints how the states the players dumped after going out of sync differ, once one of
+/// them has the states of the others. Returns whether it did
+fn print_desync(games: &[HeadlessGame]) -> bool {
+    let Some(states) = games
+        .iter()
+        .map(|game| game.app.world.resource::<Lockstep>().desync_states())
+        .find(|states| states.len() == games.len())
+    else {
+        return false;
+    };
+
+    if let Some(((first_player, first), others)) = states.split_first() {
+        for (player, state) in others {
+            if let Some(diff) = first.diff(state) {
+                println!(
+                    "player {} against player {}: {}",
+                    first_player + 1,
+                    player + 1,
+                    diff
+                );
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::world::EntityRef;
+
+    use crate::{
+        command::PlayerCommand, economy::Stockpiles, health::Health, player::Owner,
+        selection::ControlGroups, simulation::StableId, units::UnitKind,
+    };
+
+    const TICKS: u64 = 400;
+    /// Ticks between two compared hashes
+    const CHECKPOINT: u64 = 25;
+    /// Tick the game is saved and loaded at, before the last commands
+    const SAVED_AT: u64 = 200;
+
+    /// Workers gather, both sides train, and the soldiers walk up to each other and fight
+    fn commands() -> Vec<IssuedCommand> {
+        let ids = |ids: &[u32]| ids.iter().copied().map(StableId).collect();
+        let command = |tick, player, command| IssuedCommand {
+            player,
+            tick,
+            command,
+        };
+        vec![
+            command(
+                1,
+                0,
+                PlayerCommand::Gather {
+                    units: ids(&[3, 4]),
+                    node: StableId(9),
+                    queued: false,
+                },
+            ),
+            command(
+                1,
+                1,
+                PlayerCommand::Gather {
+                    units: ids(&[6]),
+                    node: StableId(12),
+                    queued: false,
+                },
+            ),
+            command(
+                2,
+                1,
+                PlayerCommand::Train {
+                    building: StableId(2),
+                    kind: UnitKind::Worker,
+                },
+            ),
+            command(
+                2,
+                0,
+                PlayerCommand::Move {
+                    units: ids(&[5]),
+                    position: Vec3::new(10.0, 0.0, 10.0),
+                    queued: false,
+                },
+            ),
+            command(
+                40,
+                0,
+                PlayerCommand::Train {
+                    building: StableId(1),
+                    kind: UnitKind::Soldier,
+                },
+            ),
+            command(
+                40,
+                1,
+                PlayerCommand::Move {
+                    units: ids(&[8]),
+                    position: Vec3::new(8.0, 0.0, 8.0),
+                    queued: false,
+                },
+            ),
+            command(
+                300,
+                0,
+                PlayerCommand::Attack {
+                    units: ids(&[5]),
+                    target: StableId(8),
+                    queued: false,
+                },
+            ),
+            command(
+                300,
+                1,
+                PlayerCommand::Gather {
+                    units: ids(&[7]),
+                    node: StableId(14),
+                    queued: false,
+                },
+            ),
+        ]
+    }
+
+    fn start(setup: GameSetup, players: Players) -> HeadlessGame {
+        HeadlessGame::start(setup, players).expect("the default map loads")
+    }
+
+    fn queue(game: &mut HeadlessGame, after: u64) {
+        let mut queue = game.app.world.resource_mut::<CommandQueue>();
+        for command in commands()
+            .into_iter()
+            .filter(|command| command.tick > after)
+        {
+            queue.push(command);
+        }
+    }
+
+    /// Ticks the game up to the given tick, hashing the state at every checkpoint on the way
+    fn run_to(game: &mut HeadlessGame, tick: u64) -> Vec<(u64, u64)> {
+        let mut hashes = Vec::new();
+        while game.ticks() < tick {
+            game.tick();
+            if game.ticks().is_multiple_of(CHECKPOINT) {
+                hashes.push((game.ticks(), game.state_hash()));
+            }
+        }
+        hashes
+    }
+
+    fn entity(game: &mut HeadlessGame, id: u32) -> Option<EntityRef<'_>> {
+        game.app
+            .world
+            .iter_entities()
+            .find(|entity| entity.get::<StableId>() == Some(&StableId(id)))
+    }
+
+    /// The hashes would also agree if every command had been dropped
+    fn assert_commands_took_effect(game: &mut HeadlessGame) {
+        let stockpile = game.app.world.resource::<Stockpiles>().get(Owner(0));
+        assert!(
+            stockpile.minerals > STARTING_RESOURCES.minerals,
+            "the workers brought back more than the soldier cost"
+        );
+
+        let soldier = entity(game, 5).expect("the soldier of player 0 lives");
+        let position = soldier.get::<Transform>().unwrap().translation;
+        assert!(
+            Vec2::new(position.x, position.z).distance(Vec2::new(10.0, 10.0)) < 1.0,
+            "the soldier walked to where it was sent"
+        );
+
+        // The other soldier may also have died of it
+        let target = entity(game, 8).and_then(|target| target.get::<Health>().copied());
+        assert!(
+            target.is_none_or(|health| health.current < health.max),
+            "the attack at tick 300 landed"
+        );
+    }
+
+    fn setup() -> GameSetup {
+        GameSetup {
+            map: DEFAULT_MAP.to_string(),
+            starting_resources: STARTING_RESOURCES,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn same_seed_and_commands_give_the_same_states() {
+        let mut first = start(setup(), Players::humans(2));
+        let mut second = start(setup(), Players::humans(2));
+        queue(&mut first, 0);
+        queue(&mut second, 0);
+
+        let hashes = run_to(&mut first, TICKS);
+        assert_eq!(hashes.len() as u64, TICKS / CHECKPOINT);
+        assert_eq!(hashes, run_to(&mut second, TICKS));
+        assert_commands_took_effect(&mut first);
+    }
+
+    #[test]
+    fn loaded_game_goes_on_like_the_saved_one() {
+        let mut played = start(setup(), Players::humans(2));
+        queue(&mut played, 0);
+        let hashes = run_to(&mut played, TICKS);
+
+        let mut saved = start(setup(), Players::humans(2));
+        queue(&mut saved, 0);
+        run_to(&mut saved, SAVED_AT);
+        let groups = vec![vec![StableId(3), StableId(5)], vec![StableId(1)]];
+        saved
+            .app
+            .world
+            .insert_resource(ControlGroups(groups.clone()));
+        let save = SaveFile::capture(&saved.app.world).unwrap();
+
+        // Commands that are still queued are not part of a save
+        let mut loaded = start(save.setup.clone(), Players(save.players.clone()));
+        save.restore_state(&mut loaded.app.world).unwrap();
+        queue(&mut loaded, SAVED_AT);
+        assert_eq!(loaded.ticks(), SAVED_AT);
+        assert_eq!(loaded.state_hash(), saved.state_hash());
+        assert_eq!(loaded.app.world.resource::<ControlGroups>().0, groups);
+
+        let expected: Vec<_> = hashes
+            .into_iter()
+            .filter(|(tick, _)| *tick > SAVED_AT)
+            .collect();
+        assert_eq!(run_to(&mut loaded, TICKS), expected);
+        assert_commands_took_effect(&mut loaded);
+    }
+}
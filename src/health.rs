@@ -0,0 +1,36 @@
+use bevy::prelude::*;
+
+use crate::{simulation::StepSet, snapshot::SnapshotApp};
+
+pub struct HealthPlugin;
+
+#[derive(Component, Debug, Default, Clone, Copy, Reflect)]
+#[reflect(Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+}
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<Health>().add_system(
+            despawn_dead
+                .in_set(StepSet::Cleanup)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+fn despawn_dead(mut commands: Commands, entities: Query<(Entity, &Health)>) {
+    for (entity, health) in &entities {
+        if health.current <= 0.0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
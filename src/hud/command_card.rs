@@ -0,0 +1,275 @@
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+
+use crate::{
+    abilities::{AbilityDefinitions, ActivateAbility, AvailableAbilities},
+    buildings::{Building, BuildingDefinitions, BuildingKind, Construction, StartPlacement},
+    command::PlayerCommand,
+    economy::Worker,
+    order::{StopUnits, STOP_HOTKEY},
+    player::{LocalPlayer, Owner},
+    research::{ResearchDefinitions, ResearchState},
+    selection::Selectable,
+    simulation::StableId,
+    ui::UiFont,
+    units::{Unit, UnitDefinitions, UnitKind},
+    GameState,
+};
+
+use super::{text_style, HudNode, HUD_MARGIN, PANEL_COLOR};
+
+pub struct CommandCardPlugin;
+
+const COMMAND_CARD_COLUMNS: f32 = 4.0;
+const BUTTON_SIZE: f32 = 64.0;
+const BUTTON_SPACING: f32 = 4.0;
+const BUTTON_FONT_SIZE: f32 = 12.0;
+
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.2);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.35);
+
+/// Buttons for the orders, production and abilities of the selection in the bottom right corner
+#[derive(Component)]
+struct CommandCard;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Stop,
+    Build(BuildingKind),
+    Train(UnitKind),
+    Research(String),
+    Ability(String),
+}
+
+/// Does the same as the hotkey shown on it
+#[derive(Component)]
+struct CommandButton(Command);
+
+impl Plugin for CommandCardPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_command_card).add_systems(
+            (update_buttons, press_buttons)
+                .chain()
+                .in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
+
+fn spawn_command_card(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(HUD_MARGIN),
+                    bottom: Val::Px(HUD_MARGIN),
+                    ..default()
+                },
+                // Shrinks to nothing while there are no buttons, so that it does not catch clicks
+                max_size: Size::width(Val::Px(
+                    COMMAND_CARD_COLUMNS * (BUTTON_SIZE + 2.0 * BUTTON_SPACING),
+                )),
+                flex_wrap: FlexWrap::Wrap,
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        },
+        RelativeCursorPosition::default(),
+        Name::from("Command Card"),
+        HudNode,
+        CommandCard,
+    ));
+}
+
+/// Collects the commands of the local player's selection and rebuilds the buttons when they change
+#[allow(clippy::too_many_arguments)]
+fn update_buttons(
+    mut commands: Commands,
+    command_card: Query<(Entity, With<CommandCard>)>,
+    units: Query<(&Selectable, &Owner, Option<&Worker>, With<Unit>)>,
+    buildings: Query<(&Building, &Selectable, &Owner, Without<Construction>)>,
+    available_abilities: Res<AvailableAbilities>,
+    unit_definitions: Res<UnitDefinitions>,
+    building_definitions: Res<BuildingDefinitions>,
+    research_definitions: Res<ResearchDefinitions>,
+    ability_definitions: Res<AbilityDefinitions>,
+    research: Res<ResearchState>,
+    local_player: Res<LocalPlayer>,
+    font: Res<UiFont>,
+    mut shown: Local<Vec<Command>>,
+) {
+    let mut buttons = Vec::new();
+    let mut push = |command: Command| {
+        if !buttons.contains(&command) {
+            buttons.push(command);
+        }
+    };
+
+    for (selectable, owner, worker, _) in &units {
+        if !selectable.is_selected || owner.0 != local_player.0 {
+            continue;
+        }
+
+        push(Command::Stop);
+        if worker.is_some() {
+            BuildingKind::ALL
+                .into_iter()
+                .for_each(|kind| push(Command::Build(kind)));
+        }
+    }
+
+    for (building, selectable, owner, _) in &buildings {
+        if !selectable.is_selected || owner.0 != local_player.0 {
+            continue;
+        }
+
+        for kind in &building_definitions.get(building.kind).trains {
+            push(Command::Train(*kind));
+        }
+
+        let mut researches: Vec<(&String, &str)> = research_definitions
+            .iter()
+            .filter(|(id, definition)| {
+                definition.researched_at == building.kind && !research.is_completed(*owner, id)
+            })
+            .map(|(id, definition)| (id, definition.name.as_str()))
+            .collect();
+        researches.sort_by_key(|(_, name)| *name);
+        for (id, _) in researches {
+            push(Command::Research(id.clone()));
+        }
+    }
+
+    for ability in &available_abilities.0 {
+        push(Command::Ability(ability.clone()));
+    }
+
+    if *shown == buttons {
+        return;
+    }
+
+    let Ok((command_card, _)) = command_card.get_single() else {
+        return;
+    };
+
+    let mut command_card = commands.entity(command_card);
+    command_card.despawn_descendants();
+    command_card.with_children(|parent| {
+        for command in &buttons {
+            let (name, hotkey) = match command {
+                Command::Stop => ("Stop", Some(STOP_HOTKEY)),
+                Command::Build(kind) => {
+                    let definition = building_definitions.get(*kind);
+                    (definition.name.as_str(), definition.hotkey)
+                }
+                Command::Train(kind) => {
+                    let definition = unit_definitions.get(*kind);
+                    (definition.name.as_str(), definition.hotkey)
+                }
+                Command::Research(id) => match research_definitions.get(id) {
+                    Some(definition) => (definition.name.as_str(), definition.hotkey),
+                    None => continue,
+                },
+                Command::Ability(id) => {
+                    let definition = ability_definitions.get(id);
+                    (definition.name.as_str(), definition.hotkey)
+                }
+            };
+            let label = match hotkey {
+                Some(hotkey) => format!("{}\n({})", name, hotkey.to_ascii_uppercase()),
+                None => name.to_string(),
+            };
+
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            size: Size::all(Val::Px(BUTTON_SIZE)),
+                            margin: UiRect::all(Val::Px(BUTTON_SPACING)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: BUTTON_COLOR.into(),
+                        ..default()
+                    },
+                    CommandButton(command.clone()),
+                ))
+                .with_children(|button| {
+                    button.spawn(
+                        TextBundle::from_section(label, text_style(&font, BUTTON_FONT_SIZE))
+                            .with_text_alignment(TextAlignment::Center),
+                    );
+                });
+        }
+    });
+
+    *shown = buttons;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn press_buttons(
+    mut buttons: Query<(&Interaction, &CommandButton, &mut BackgroundColor), Changed<Interaction>>,
+    buildings: Query<(
+        &StableId,
+        &Building,
+        &Selectable,
+        &Owner,
+        Without<Construction>,
+    )>,
+    building_definitions: Res<BuildingDefinitions>,
+    research_definitions: Res<ResearchDefinitions>,
+    local_player: Res<LocalPlayer>,
+    mut stop: EventWriter<StopUnits>,
+    mut start_placement: EventWriter<StartPlacement>,
+    mut activate_ability: EventWriter<ActivateAbility>,
+    mut player_commands: EventWriter<PlayerCommand>,
+) {
+    for (interaction, button, mut color) in &mut buttons {
+        if *interaction != Interaction::Clicked {
+            *color = match interaction {
+                Interaction::Hovered => HOVERED_BUTTON_COLOR,
+                _ => BUTTON_COLOR,
+            }
+            .into();
+            continue;
+        }
+
+        let selected_buildings = buildings.iter().filter(|(_, _, selectable, owner, _)| {
+            selectable.is_selected && owner.0 == local_player.0
+        });
+        match &button.0 {
+            Command::Stop => stop.send(StopUnits),
+            Command::Build(kind) => start_placement.send(StartPlacement(*kind)),
+            Command::Train(kind) => {
+                for (id, building, ..) in selected_buildings {
+                    if building_definitions
+                        .get(building.kind)
+                        .trains
+                        .contains(kind)
+                    {
+                        player_commands.send(PlayerCommand::Train {
+                            building: *id,
+                            kind: *kind,
+                        });
+                    }
+                }
+            }
+            Command::Research(id) => {
+                let Some(definition) = research_definitions.get(id) else {
+                    continue;
+                };
+
+                for (building_id, building, ..) in selected_buildings {
+                    if building.kind == definition.researched_at {
+                        player_commands.send(PlayerCommand::Research {
+                            building: *building_id,
+                            research: id.clone(),
+                        });
+                    }
+                }
+            }
+            Command::Ability(id) => activate_ability.send(ActivateAbility(id.clone())),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+mod command_card;
+mod replay_bar;
+mod selection_panel;
+mod top_bar;
+
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+
+use crate::{ui::UiFont, GameState};
+
+use self::{
+    command_card::CommandCardPlugin, replay_bar::ReplayBarPlugin,
+    selection_panel::SelectionPanelPlugin, top_bar::TopBarPlugin,
+};
+
+pub struct HudPlugin;
+
+/// Space between the bottom panels and the edges of the window
+const HUD_MARGIN: f32 = 10.0;
+const FONT_SIZE: f32 = 14.0;
+
+const PANEL_COLOR: Color = Color::rgba(0.05, 0.05, 0.08, 0.85);
+const TEXT_COLOR: Color = Color::WHITE;
+
+/// Part of the interface that keeps clicks from reaching the world behind it
+#[derive(Component)]
+pub struct HudNode;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(TopBarPlugin)
+            .add_plugin(SelectionPanelPlugin)
+            .add_plugin(CommandCardPlugin)
+            .add_plugin(ReplayBarPlugin)
+            .add_system(show_hud.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(hide_hud.in_schedule(OnEnter(GameState::MainMenu)));
+    }
+}
+
+fn show_hud(mut nodes: Query<(&mut Visibility, With<HudNode>)>) {
+    for (mut visibility, _) in &mut nodes {
+        *visibility = Visibility::Inherited;
+    }
+}
+
+/// Keeps the HUD out of the menus, hidden nodes do not take clicks either
+fn hide_hud(mut nodes: Query<(&mut Visibility, With<HudNode>)>) {
+    for (mut visibility, _) in &mut nodes {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+/// Run condition that is true while the cursor hovers any part of the HUD, including the minimap
+pub fn is_cursor_over_hud(nodes: Query<(&RelativeCursorPosition, With<HudNode>)>) -> bool {
+    nodes.iter().any(|(position, _)| position.mouse_over())
+}
+
+fn text_style(font: &UiFont, font_size: f32) -> TextStyle {
+    TextStyle {
+        font: font.0.clone(),
+        font_size,
+        color: TEXT_COLOR,
+    }
+}
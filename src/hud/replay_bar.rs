@@ -0,0 +1,120 @@
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+
+use crate::{
+    player::LocalPlayer,
+    replay::ReplayPlayback,
+    simulation::{GameSpeed, SimulationTime, TICKS_PER_SECOND},
+    ui::UiFont,
+    GameState,
+};
+
+use super::{text_style, HudNode, FONT_SIZE, PANEL_COLOR};
+
+pub struct ReplayBarPlugin;
+
+const REPLAY_BAR_TOP: f32 = 36.0;
+const REPLAY_BAR_PADDING: f32 = 8.0;
+
+#[derive(Component)]
+struct ReplayBar;
+
+/// Position, speed and vision of the replay
+#[derive(Component)]
+struct ReplayBarText;
+
+impl Plugin for ReplayBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_replay_bar.run_if(resource_added::<ReplayPlayback>()))
+            .add_system(
+                update_replay_bar
+                    .run_if(resource_exists::<ReplayPlayback>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(despawn_replay_bar.in_schedule(OnEnter(GameState::MainMenu)));
+    }
+}
+
+fn spawn_replay_bar(mut commands: Commands, font: Res<UiFont>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(REPLAY_BAR_TOP),
+                        ..default()
+                    },
+                    size: Size::width(Val::Percent(100.0)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            Name::from("Replay Bar"),
+            ReplayBar,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            padding: UiRect::all(Val::Px(REPLAY_BAR_PADDING)),
+                            ..default()
+                        },
+                        background_color: PANEL_COLOR.into(),
+                        ..default()
+                    },
+                    RelativeCursorPosition::default(),
+                    HudNode,
+                ))
+                .with_children(|panel| {
+                    panel.spawn((
+                        TextBundle::from_section("", text_style(&font, FONT_SIZE)),
+                        ReplayBarText,
+                    ));
+                });
+        });
+}
+
+fn update_replay_bar(
+    mut text: Query<(&mut Text, With<ReplayBarText>)>,
+    playback: Res<ReplayPlayback>,
+    time: Res<SimulationTime>,
+    speed: Res<GameSpeed>,
+    local_player: Res<LocalPlayer>,
+) {
+    let Ok((mut text, _)) = text.get_single_mut() else {
+        return;
+    };
+
+    let mut value = format!(
+        "Replay {} / {}    {}    Vision of player {}",
+        clock(time.tick()),
+        clock(playback.length()),
+        if playback.is_paused() {
+            "Paused".to_string()
+        } else {
+            speed.name()
+        },
+        local_player.0 + 1
+    );
+    if let Some(tick) = playback.desynced_at() {
+        value += &format!("    Out of sync since {}", clock(tick));
+    }
+    value += "\nSpace pause    Left / Right seek    - / + speed    V vision";
+
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+fn clock(tick: u64) -> String {
+    let seconds = tick / TICKS_PER_SECOND as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn despawn_replay_bar(mut commands: Commands, bars: Query<(Entity, With<ReplayBar>)>) {
+    for (entity, _) in &bars {
+        commands.entity(entity).despawn_recursive();
+    }
+}
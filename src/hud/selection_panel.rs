@@ -0,0 +1,318 @@
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+
+use crate::{
+    abilities::Energy,
+    buildings::{Construction, ProductionQueue},
+    health::Health,
+    player::{Owner, Players},
+    research::{ResearchDefinitions, Researching},
+    selection::Selectable,
+    stats::{Stat, Stats},
+    ui::UiFont,
+    units::UnitDefinitions,
+    GameState,
+};
+
+use super::{text_style, HudNode, FONT_SIZE, HUD_MARGIN, PANEL_COLOR};
+
+pub struct SelectionPanelPlugin;
+
+/// Leaves room for the minimap on the left
+const PANEL_LEFT: f32 = 220.0;
+const PANEL_WIDTH: f32 = 420.0;
+const PANEL_HEIGHT: f32 = 150.0;
+const PANEL_PADDING: f32 = 8.0;
+const NAME_FONT_SIZE: f32 = 18.0;
+const ICON_SIZE: f32 = 40.0;
+const ICON_SPACING: f32 = 3.0;
+/// Icons that fit into the panel, the rest of a large selection is left out
+const MAX_ICONS: usize = 24;
+const HEALTH_BAR_HEIGHT: f32 = 5.0;
+
+const HEALTH_BAR_BACKGROUND: Color = Color::rgb(0.1, 0.1, 0.1);
+const HEALTHY_COLOR: Color = Color::rgb(0.2, 0.8, 0.2);
+const WOUNDED_COLOR: Color = Color::rgb(0.9, 0.8, 0.1);
+const CRITICAL_COLOR: Color = Color::rgb(0.9, 0.2, 0.1);
+
+/// Shows the selected unit, or an icon for every unit of a larger selection
+#[derive(Component)]
+struct SelectionPanel;
+
+/// Description of a single selected unit or building
+#[derive(Component)]
+struct SelectionDetails(Entity);
+
+/// Selects only this unit when clicked
+#[derive(Component)]
+struct SelectionIcon(Entity);
+
+/// Filled part of a health bar, as wide as the health left
+#[derive(Component)]
+struct HealthBar(Entity);
+
+impl Plugin for SelectionPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_selection_panel).add_systems(
+            (
+                rebuild_selection_panel,
+                update_details,
+                update_health_bars,
+                select_from_icons,
+            )
+                .chain()
+                .in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
+
+fn spawn_selection_panel(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(PANEL_LEFT),
+                    bottom: Val::Px(HUD_MARGIN),
+                    ..default()
+                },
+                size: Size::new(Val::Px(PANEL_WIDTH), Val::Px(PANEL_HEIGHT)),
+                padding: UiRect::all(Val::Px(PANEL_PADDING)),
+                flex_wrap: FlexWrap::Wrap,
+                align_content: AlignContent::FlexStart,
+                // Hidden until something is selected
+                display: Display::None,
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        },
+        RelativeCursorPosition::default(),
+        Name::from("Selection Panel"),
+        HudNode,
+        SelectionPanel,
+    ));
+}
+
+/// Fills the panel again whenever the selection changes
+#[allow(clippy::type_complexity)]
+fn rebuild_selection_panel(
+    mut commands: Commands,
+    mut panel: Query<(Entity, &mut Style, With<SelectionPanel>)>,
+    selectables: Query<(Entity, &Selectable, &Name, Option<&Owner>, With<Health>)>,
+    players: Res<Players>,
+    font: Res<UiFont>,
+    mut shown: Local<Vec<Entity>>,
+) {
+    let mut selected: Vec<Entity> = selectables
+        .iter()
+        .filter(|(_, selectable, ..)| selectable.is_selected)
+        .map(|(entity, ..)| entity)
+        .collect();
+    selected.sort();
+    if *shown == selected {
+        return;
+    }
+
+    let Ok((panel, mut style, _)) = panel.get_single_mut() else {
+        return;
+    };
+
+    style.display = if selected.is_empty() {
+        Display::None
+    } else {
+        Display::Flex
+    };
+
+    let mut panel = commands.entity(panel);
+    panel.despawn_descendants();
+    panel.with_children(|parent| {
+        if let [entity] = selected[..] {
+            parent.spawn((
+                TextBundle::from_section("", text_style(&font, FONT_SIZE)).with_style(Style {
+                    size: Size::width(Val::Percent(100.0)),
+                    ..default()
+                }),
+                SelectionDetails(entity),
+            ));
+            spawn_health_bar(parent, entity);
+            return;
+        }
+
+        for entity in selected.iter().take(MAX_ICONS) {
+            let Ok((_, _, name, owner, _)) = selectables.get(*entity) else {
+                continue;
+            };
+
+            let color = owner.map_or(Color::GRAY, |owner| players.color(*owner));
+            let initial: String = name.chars().take(1).collect();
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            size: Size::all(Val::Px(ICON_SIZE)),
+                            margin: UiRect::all(Val::Px(ICON_SPACING)),
+                            flex_direction: FlexDirection::Column,
+                            justify_content: JustifyContent::SpaceBetween,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: color.into(),
+                        ..default()
+                    },
+                    SelectionIcon(*entity),
+                ))
+                .with_children(|icon| {
+                    icon.spawn(TextBundle::from_section(
+                        initial,
+                        text_style(&font, NAME_FONT_SIZE),
+                    ));
+                    spawn_health_bar(icon, *entity);
+                });
+        }
+    });
+
+    *shown = selected;
+}
+
+fn spawn_health_bar(parent: &mut ChildBuilder, entity: Entity) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Px(HEALTH_BAR_HEIGHT)),
+                ..default()
+            },
+            background_color: HEALTH_BAR_BACKGROUND.into(),
+            ..default()
+        })
+        .with_children(|bar| {
+            bar.spawn((
+                NodeBundle {
+                    style: Style {
+                        size: Size::height(Val::Percent(100.0)),
+                        ..default()
+                    },
+                    background_color: HEALTHY_COLOR.into(),
+                    ..default()
+                },
+                HealthBar(entity),
+            ));
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn update_details(
+    mut details: Query<(&mut Text, &SelectionDetails)>,
+    entities: Query<(
+        &Name,
+        &Health,
+        Option<&Energy>,
+        Option<&Stats>,
+        Option<&Construction>,
+        Option<&ProductionQueue>,
+        Option<&Researching>,
+    )>,
+    unit_definitions: Res<UnitDefinitions>,
+    research_definitions: Res<ResearchDefinitions>,
+) {
+    for (mut text, details) in &mut details {
+        let Ok((name, health, energy, stats, construction, production, researching)) =
+            entities.get(details.0)
+        else {
+            continue;
+        };
+
+        let mut lines = vec![
+            name.to_string(),
+            format!("Health {:.0} / {:.0}", health.current.max(0.0), health.max),
+        ];
+        if let Some(energy) = energy {
+            lines.push(format!("Energy {:.0} / {:.0}", energy.current, energy.max));
+        }
+        if let Some(stats) = stats {
+            let mut values = Vec::new();
+            if stats.get(Stat::Damage) > 0.0 {
+                values.push(format!(
+                    "Damage {:.0}    Range {:.1}",
+                    stats.get(Stat::Damage),
+                    stats.get(Stat::Range)
+                ));
+            }
+            values.push(format!("Armor {:.0}", stats.get(Stat::Armor)));
+            lines.push(values.join("    "));
+        }
+        if let Some(construction) = construction {
+            lines.push(format!(
+                "Constructing {:.0}%",
+                construction.progress * 100.0
+            ));
+        }
+        if let Some(production) = production {
+            if let Some(kind) = production.queue.first() {
+                let definition = unit_definitions.get(*kind);
+                lines.push(format!(
+                    "Training {} {:.0}% ({} queued)",
+                    definition.name,
+                    production.progress / definition.train_time * 100.0,
+                    production.queue.len()
+                ));
+            }
+        }
+        if let Some(researching) = researching {
+            if let Some(definition) = research_definitions.get(&researching.research) {
+                lines.push(format!(
+                    "Researching {} {:.0}%",
+                    definition.name,
+                    researching.progress / definition.time * 100.0
+                ));
+            }
+        }
+
+        let value = lines.join("\n");
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+fn update_health_bars(
+    mut bars: Query<(&HealthBar, &mut Style, &mut BackgroundColor)>,
+    healths: Query<&Health>,
+) {
+    for (bar, mut style, mut color) in &mut bars {
+        let Ok(health) = healths.get(bar.0) else {
+            continue;
+        };
+
+        let fraction = (health.current / health.max).clamp(0.0, 1.0);
+        let width = Val::Percent(fraction * 100.0);
+        if style.size.width != width {
+            style.size.width = width;
+            *color = health_color(fraction).into();
+        }
+    }
+}
+
+fn health_color(fraction: f32) -> Color {
+    if fraction > 0.6 {
+        HEALTHY_COLOR
+    } else if fraction > 0.3 {
+        WOUNDED_COLOR
+    } else {
+        CRITICAL_COLOR
+    }
+}
+
+fn select_from_icons(
+    icons: Query<(&Interaction, &SelectionIcon), Changed<Interaction>>,
+    mut selectables: Query<(Entity, &mut Selectable)>,
+) {
+    for (interaction, icon) in &icons {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        for (entity, mut selectable) in &mut selectables {
+            selectable.is_selected = entity == icon.0;
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+
+use crate::{
+    economy::Stockpiles,
+    player::{LocalPlayer, Owner},
+    simulation::{GameSpeed, SimulationTime},
+    supply::Supplies,
+    ui::UiFont,
+    GameState,
+};
+
+use super::{text_style, HudNode, FONT_SIZE, PANEL_COLOR};
+
+pub struct TopBarPlugin;
+
+const TOP_BAR_HEIGHT: f32 = 28.0;
+const TOP_BAR_PADDING: f32 = 16.0;
+
+/// Resources and supply of the local player
+#[derive(Component)]
+struct TopBarText;
+
+impl Plugin for TopBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_top_bar).add_system(
+            update_top_bar
+                .run_if(resource_exists::<Stockpiles>())
+                .in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
+
+fn spawn_top_bar(mut commands: Commands, font: Res<UiFont>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(0.0),
+                        top: Val::Px(0.0),
+                        ..default()
+                    },
+                    size: Size::new(Val::Percent(100.0), Val::Px(TOP_BAR_HEIGHT)),
+                    padding: UiRect::horizontal(Val::Px(TOP_BAR_PADDING)),
+                    justify_content: JustifyContent::FlexEnd,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+            RelativeCursorPosition::default(),
+            Name::from("Top Bar"),
+            HudNode,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("", text_style(&font, FONT_SIZE)),
+                TopBarText,
+            ));
+        });
+}
+
+fn update_top_bar(
+    mut text: Query<(&mut Text, With<TopBarText>)>,
+    stockpiles: Res<Stockpiles>,
+    supplies: Res<Supplies>,
+    local_player: Res<LocalPlayer>,
+    speed: Res<GameSpeed>,
+    time: Res<SimulationTime>,
+) {
+    let Ok((mut text, _)) = text.get_single_mut() else {
+        return;
+    };
+
+    let owner = Owner(local_player.0);
+    let stockpile = stockpiles.get(owner);
+    let supply = supplies.get(owner);
+    let seconds = time.elapsed().as_secs();
+    let mut value = format!(
+        "Minerals {}    Wood {}    Supply {} / {}    {}:{:02}",
+        stockpile.minerals,
+        stockpile.wood,
+        supply.used,
+        supply.max,
+        seconds / 60,
+        seconds % 60
+    );
+    if !speed.is_normal() {
+        value += &format!("    Speed {}", speed.name());
+    }
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
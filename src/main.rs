@@ -1,17 +1,59 @@
+mod abilities;
+mod buildings;
 mod camera;
+mod checksum;
+mod combat;
+mod command;
 mod cursor;
+mod economy;
+mod fog;
 mod game;
 mod ground;
+mod headless;
+mod health;
+mod hotkeys;
+mod hud;
+mod map;
+mod menus;
+mod minimap;
+mod navigation;
+mod network;
 mod order;
+mod overlays;
+mod player;
+mod replay;
+mod research;
+mod save;
 mod selection;
+mod simulation;
+mod snapshot;
+mod stats;
+mod supply;
+mod ui;
 mod units;
 
+use abilities::AbilityInputPlugin;
+use buildings::{BuildingInputPlugin, BuildingViewPlugin};
 use camera::CameraPlugin;
 use cursor::CursorPlugin;
-use game::GamePlugin;
-use ground::GroundPlugin;
-use order::OrderPlugin;
+use economy::{EconomyViewPlugin, STARTING_RESOURCES};
+use fog::FogPlugin;
+use game::{GameInputPlugin, GameSetup};
+use ground::GroundViewPlugin;
+use hud::HudPlugin;
+use map::{MapAssetPlugin, MapViewPlugin, DEFAULT_MAP};
+use menus::MenuPlugin;
+use minimap::MinimapPlugin;
+use network::{JoinLockstep, LockstepInputPlugin};
+use order::OrderInputPlugin;
+use overlays::OverlayPlugin;
+use replay::{ReplayPlugin, WatchReplay};
+use research::ResearchInputPlugin;
+use save::{LoadGame, SavePlugin};
 use selection::SelectionPlugin;
+use simulation::SimulationPlugins;
+use supply::SupplyViewPlugin;
+use ui::UiPlugin;
 
 use bevy::{input::common_conditions::input_toggle_active, prelude::*, window::PresentMode};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
@@ -19,18 +61,31 @@ use bevy_rapier3d::{
     prelude::{NoUserData, RapierPhysicsPlugin},
     render::RapierDebugRenderPlugin,
 };
-use units::UnitPlugin;
+use units::UnitViewPlugin;
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub enum GameState {
-    Menu,
     #[default]
+    MainMenu,
+    /// Skirmish setup before a game
+    Lobby,
     InGame,
+    /// The simulation is frozen while the pause menu is open
+    Paused,
 }
 
 fn main() {
-    App::new()
-        .add_state::<GameState>()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--headless") {
+        if let Err(error) = headless::run_from_args(&args) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut app = App::new();
+    app.add_state::<GameState>()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "RTS".into(),
@@ -44,12 +99,80 @@ fn main() {
         )
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         .add_plugin(RapierDebugRenderPlugin::default())
-        .add_plugin(UnitPlugin)
+        .add_plugins(SimulationPlugins)
+        .add_plugin(GameInputPlugin)
+        .add_plugin(UnitViewPlugin)
         .add_plugin(CameraPlugin)
-        .add_plugin(GroundPlugin)
+        .add_plugin(GroundViewPlugin)
+        .add_plugin(EconomyViewPlugin)
+        .add_plugin(MapAssetPlugin)
+        .add_plugin(MapViewPlugin)
         .add_plugin(SelectionPlugin)
-        .add_plugin(OrderPlugin)
-        .add_plugin(GamePlugin)
+        .add_plugin(OrderInputPlugin)
         .add_plugin(CursorPlugin)
-        .run();
+        .add_plugin(MinimapPlugin)
+        .add_plugin(FogPlugin)
+        .add_plugin(BuildingViewPlugin)
+        .add_plugin(BuildingInputPlugin)
+        .add_plugin(SupplyViewPlugin)
+        .add_plugin(ResearchInputPlugin)
+        .add_plugin(UiPlugin)
+        .add_plugin(AbilityInputPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(OverlayPlugin)
+        .add_plugin(MenuPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(LockstepInputPlugin);
+
+    // `--replay <path>` and `--load <path>` start with the replay or save instead of the main menu
+    let value_of = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|index| args.get(index + 1))
+    };
+    if let Some(path) = value_of("--replay") {
+        app.world.send_event(WatchReplay(path.into()));
+    }
+    if let Some(path) = value_of("--load") {
+        app.world.send_event(LoadGame(path.into()));
+    }
+    // `--lockstep <player> --peers <address>,... [--map <path>] [--seed <number>] [--delay <ticks>]`
+    // joins a game over UDP, every player lists the same addresses in the same order
+    if let Some(player) = value_of("--lockstep") {
+        match join_lockstep_from_args(player, value_of) {
+            Ok(join) => app.world.send_event(join),
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+    app.run();
+}
+
+fn join_lockstep_from_args<'a>(
+    player: &str,
+    value_of: impl Fn(&str) -> Option<&'a String>,
+) -> Result<JoinLockstep, String> {
+    let player: u8 = player.parse().map_err(|_| "invalid player")?;
+    let addresses =
+        network::parse_addresses(value_of("--peers").ok_or("--lockstep needs --peers")?)?;
+    if player == 0 || player as usize > addresses.len() {
+        return Err(format!("player {} has no address in --peers", player));
+    }
+
+    Ok(JoinLockstep {
+        setup: GameSetup {
+            map: value_of("--map").map_or(DEFAULT_MAP.to_string(), Clone::clone),
+            starting_resources: STARTING_RESOURCES,
+            seed: value_of("--seed")
+                .map_or(Ok(0), |seed| seed.parse().map_err(|_| "invalid seed"))?,
+        },
+        local_player: player - 1,
+        addresses,
+        input_delay: value_of("--delay").map_or(Ok(network::DEFAULT_INPUT_DELAY), |delay| {
+            delay.parse().map_err(|_| "invalid input delay")
+        })?,
+    })
 }
@@ -0,0 +1,457 @@
+mod validation;
+
+use std::path::PathBuf;
+
+use bevy::{
+    asset::{AssetLoader, FileAssetIo, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_rapier3d::prelude::{Collider, RigidBody};
+use serde::Deserialize;
+
+use crate::{
+    buildings::{Building, BuildingKind, SpawnBuilding},
+    camera::center_camera_on,
+    economy::{ResourceKind, ResourceNode, SpawnResourceNode, Stockpiles, STARTING_RESOURCES},
+    fog::VisibilityGrid,
+    game::GameSetup,
+    ground::{Ground, PlaceOnTerrain, Terrain, TerrainSettings, TerrainSource},
+    player::{LocalPlayer, Owner, Players},
+    research::ResearchState,
+    units::{SpawnUnit, Unit, UnitKind},
+    GameState,
+};
+
+pub use self::validation::{parse_map, MapError};
+
+pub struct MapPlugin;
+
+/// Loads maps through the asset server, in the background
+pub struct MapAssetPlugin;
+
+/// Reads maps straight from the assets folder, for games that run without an asset server
+pub struct MapFilePlugin;
+
+/// Obstacle models and the camera at the start location
+pub struct MapViewPlugin;
+
+/// Folder of the asset server, inside `FileAssetIo::get_base_path`
+const ASSETS_FOLDER: &str = "assets";
+const MAPS_FOLDER: &str = "maps";
+const MAP_EXTENSION: &str = ".map.yaml";
+pub const DEFAULT_MAP: &str = "maps/default.map.yaml";
+
+const OBSTACLE_COLOR: Color = Color::rgb(0.4, 0.38, 0.36);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MapDefinition {
+    pub name: String,
+    /// Width and depth of the map in world units
+    pub size: f32,
+    pub terrain: TerrainDefinition,
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleDefinition>,
+    #[serde(default)]
+    pub resources: Vec<ResourceNodeDefinition>,
+    pub players: Vec<PlayerStart>,
+    #[serde(default)]
+    pub units: Vec<UnitPlacement>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TerrainDefinition {
+    pub source: TerrainSource,
+    pub max_height: f32,
+}
+
+/// Positions on the map are given as `[x, z]`, the height comes from the terrain
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObstacleDefinition {
+    pub position: [f32; 2],
+    /// Width, height and depth of the obstacle box
+    pub size: [f32; 3],
+    /// Rotation around the vertical axis in degrees
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceNodeDefinition {
+    pub kind: ResourceKind,
+    pub position: [f32; 2],
+    pub amount: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerStart {
+    pub start: [f32; 2],
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnitPlacement {
+    #[serde(default)]
+    pub kind: UnitKind,
+    pub player: u8,
+    pub position: [f32; 2],
+}
+
+/// Replaces the current map with the one at the given path inside the assets folder
+pub struct LoadMap(pub String);
+
+/// Map that can be picked for a game
+#[derive(Debug, Clone)]
+pub struct MapSummary {
+    /// Path inside the assets folder
+    pub path: String,
+    pub name: String,
+    /// Number of start locations
+    pub players: usize,
+}
+
+/// Text of a map file as the asset server loaded it. It is parsed once it has loaded,
+/// so that problems can be reported with their lines
+#[derive(TypeUuid)]
+#[uuid = "3345aebb-376d-4273-a7c9-54643b0a0a13"]
+struct MapSource(String);
+
+#[derive(Default)]
+struct MapLoader;
+
+#[derive(Resource)]
+struct LoadingMap {
+    path: String,
+    handle: Handle<MapSource>,
+}
+
+/// Map that has been read and checked, it is spawned next
+#[derive(Resource)]
+struct LoadedMap(MapDefinition);
+
+/// Map that is currently being played
+#[derive(Resource)]
+pub struct CurrentMap {
+    pub start_locations: Vec<Vec3>,
+}
+
+/// Box that buildings can not be placed on
+#[derive(Component)]
+pub struct Obstacle {
+    /// Width, height and depth
+    pub size: Vec3,
+}
+
+#[derive(Resource)]
+struct ObstacleMaterial(Handle<StandardMaterial>);
+
+impl FromWorld for ObstacleMaterial {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        Self(materials.add(OBSTACLE_COLOR.into()))
+    }
+}
+
+/// Everything that was spawned for the map and is removed with it
+type MapEntityFilter = Or<(
+    With<Unit>,
+    With<ResourceNode>,
+    With<Building>,
+    With<Obstacle>,
+    With<Ground>,
+)>;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LoadMap>()
+            .add_system(unload_map.in_schedule(OnEnter(GameState::MainMenu)))
+            .add_system(spawn_map.run_if(resource_exists::<LoadedMap>()));
+    }
+}
+
+impl Plugin for MapAssetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<MapSource>()
+            .init_asset_loader::<MapLoader>()
+            .add_systems((
+                start_loading_map,
+                finish_loading_map.run_if(resource_exists::<LoadingMap>()),
+            ));
+    }
+}
+
+impl Plugin for MapFilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(read_map_file);
+    }
+}
+
+impl Plugin for MapViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ObstacleMaterial>().add_systems((
+            add_obstacle_models,
+            center_camera_on_start
+                .run_if(resource_exists::<CurrentMap>().and_then(resource_added::<Terrain>())),
+        ));
+    }
+}
+
+impl AssetLoader for MapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let source = std::str::from_utf8(bytes)?.to_string();
+            load_context.set_default_asset(LoadedAsset::new(MapSource(source)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.yaml"]
+    }
+}
+
+/// Where the asset server reads from, for what is read without it
+pub fn assets_folder() -> PathBuf {
+    FileAssetIo::get_base_path().join(ASSETS_FOLDER)
+}
+
+/// Valid maps in the maps folder, sorted by name
+pub fn available_maps() -> Vec<MapSummary> {
+    let Ok(entries) = std::fs::read_dir(assets_folder().join(MAPS_FOLDER)) else {
+        return Vec::new();
+    };
+
+    let mut maps: Vec<MapSummary> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !file_name.ends_with(MAP_EXTENSION) {
+                return None;
+            }
+
+            let path = format!("{}/{}", MAPS_FOLDER, file_name);
+            let source = std::fs::read_to_string(entry.path()).ok()?;
+            match parse_map(&source) {
+                Ok(map) => Some(MapSummary {
+                    path,
+                    name: map.name,
+                    players: map.players.len(),
+                }),
+                Err(errors) => {
+                    for error in errors {
+                        warn!("{}: {}", path, error);
+                    }
+                    None
+                }
+            }
+        })
+        .collect();
+    maps.sort_by(|a, b| a.name.cmp(&b.name));
+    maps
+}
+
+fn start_loading_map(
+    mut commands: Commands,
+    mut reader: EventReader<LoadMap>,
+    asset_server: Res<AssetServer>,
+) {
+    let Some(LoadMap(path)) = reader.iter().last() else {
+        return;
+    };
+
+    commands.insert_resource(LoadingMap {
+        path: path.clone(),
+        handle: asset_server.load(path.as_str()),
+    });
+}
+
+fn finish_loading_map(
+    mut commands: Commands,
+    loading: Res<LoadingMap>,
+    mut sources: ResMut<Assets<MapSource>>,
+    asset_server: Res<AssetServer>,
+) {
+    let source = match asset_server.get_load_state(&loading.handle) {
+        LoadState::Loaded => sources.remove(&loading.handle),
+        LoadState::Failed => None,
+        _ => return,
+    };
+    commands.remove_resource::<LoadingMap>();
+
+    let map = source
+        .ok_or_else(|| vec![MapError::without_line("map could not be loaded")])
+        .and_then(|MapSource(source)| parse_map(&source));
+    match map {
+        Ok(map) => commands.insert_resource(LoadedMap(map)),
+        Err(errors) => {
+            for error in errors {
+                error!("{}: {}", loading.path, error);
+            }
+        }
+    }
+}
+
+/// Reads the map in the frame it is asked for
+fn read_map_file(mut commands: Commands, mut reader: EventReader<LoadMap>) {
+    let Some(LoadMap(path)) = reader.iter().last() else {
+        return;
+    };
+
+    let map = std::fs::read_to_string(assets_folder().join(path))
+        .map_err(|error| vec![MapError::without_line(error.to_string())])
+        .and_then(|source| parse_map(&source));
+    match map {
+        Ok(map) => commands.insert_resource(LoadedMap(map)),
+        Err(errors) => {
+            for error in errors {
+                error!("{}: {}", path, error);
+            }
+        }
+    }
+}
+
+/// Leaves an empty world behind when going back to the main menu
+fn unload_map(mut commands: Commands, map_entities: Query<Entity, MapEntityFilter>) {
+    despawn_map(&mut commands, &map_entities);
+    commands.remove_resource::<LoadingMap>();
+    commands.remove_resource::<LoadedMap>();
+    commands.remove_resource::<TerrainSettings>();
+}
+
+fn despawn_map(commands: &mut Commands, map_entities: &Query<Entity, MapEntityFilter>) {
+    for entity in map_entities {
+        commands.entity(entity).despawn_recursive()
+    }
+    commands.remove_resource::<CurrentMap>();
+    commands.remove_resource::<Terrain>();
+    commands.remove_resource::<VisibilityGrid>();
+}
+
+/// Replaces the map with the loaded one. However long loading took, the simulation
+/// only starts once it is spawned, so every game starts from the same state
+#[allow(clippy::too_many_arguments)]
+fn spawn_map(
+    mut commands: Commands,
+    loaded: Res<LoadedMap>,
+    map_entities: Query<Entity, MapEntityFilter>,
+    mut units: EventWriter<SpawnUnit>,
+    mut resource_nodes: EventWriter<SpawnResourceNode>,
+    mut buildings: EventWriter<SpawnBuilding>,
+    players: Res<Players>,
+    setup: Option<Res<GameSetup>>,
+) {
+    commands.remove_resource::<LoadedMap>();
+    despawn_map(&mut commands, &map_entities);
+
+    let LoadedMap(map) = &*loaded;
+    info!("loading map {}", map.name);
+    commands.insert_resource(TerrainSettings {
+        source: map.terrain.source.clone(),
+        size: map.size,
+        max_height: map.terrain.max_height,
+    });
+    // Made with the map so that the first tick already checks commands against it
+    commands.insert_resource(VisibilityGrid::new(map.size));
+
+    for obstacle in &map.obstacles {
+        let [width, height, depth] = obstacle.size;
+        commands.spawn((
+            SpatialBundle::from_transform(
+                Transform::from_translation(world_position(obstacle.position))
+                    .with_rotation(Quat::from_rotation_y(obstacle.rotation.to_radians())),
+            ),
+            Collider::cuboid(width / 2.0, height / 2.0, depth / 2.0),
+            RigidBody::Fixed,
+            PlaceOnTerrain(height / 2.0),
+            Name::from("Obstacle"),
+            Obstacle {
+                size: Vec3::from(obstacle.size),
+            },
+        ));
+    }
+
+    for resource in &map.resources {
+        resource_nodes.send(SpawnResourceNode {
+            kind: resource.kind,
+            amount: resource.amount,
+            position: world_position(resource.position),
+        });
+    }
+
+    // Start locations beyond the number of players stay empty
+    let player_count = players.len().min(map.players.len());
+    for unit in map
+        .units
+        .iter()
+        .filter(|unit| (unit.player as usize) < player_count)
+    {
+        units.send(SpawnUnit {
+            kind: unit.kind,
+            owner: Owner(unit.player),
+            position: world_position(unit.position),
+            rally_point: None,
+        });
+    }
+
+    // Every player starts with a base to bring resources to
+    for (index, player) in map.players.iter().take(player_count).enumerate() {
+        buildings.send(SpawnBuilding {
+            kind: BuildingKind::Base,
+            owner: Owner(index as u8),
+            position: world_position(player.start),
+            under_construction: false,
+            builders: Vec::new(),
+        });
+    }
+    let starting_resources = setup.map_or(STARTING_RESOURCES, |setup| setup.starting_resources);
+    commands.insert_resource(Stockpiles::new(player_count, starting_resources));
+    commands.insert_resource(ResearchState::default());
+
+    commands.insert_resource(CurrentMap {
+        start_locations: map
+            .players
+            .iter()
+            .map(|player| world_position(player.start))
+            .collect(),
+    });
+}
+
+fn add_obstacle_models(
+    mut commands: Commands,
+    obstacles: Query<(Entity, &Obstacle), Added<Obstacle>>,
+    material: Res<ObstacleMaterial>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (entity, obstacle) in &obstacles {
+        let size = obstacle.size;
+        commands.entity(entity).insert((
+            meshes.add(shape::Box::new(size.x, size.y, size.z).into()),
+            material.0.clone(),
+        ));
+    }
+}
+
+fn center_camera_on_start(
+    mut camera: Query<(&mut Transform, With<Camera3d>)>,
+    map: Res<CurrentMap>,
+    local_player: Res<LocalPlayer>,
+) {
+    if let Some(start) = map.start_locations.get(local_player.0 as usize) {
+        let (mut transform, _) = camera.single_mut();
+        center_camera_on(&mut transform, *start);
+    }
+}
+
+fn world_position([x, z]: [f32; 2]) -> Vec3 {
+    Vec3::new(x, 0.0, z)
+}
@@ -0,0 +1,484 @@
+use std::fmt;
+
+use crate::ground::TerrainSource;
+
+use super::MapDefinition;
+
+/// Problem found in a map file, `line` is 1-based
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Segment {
+    Key(&'static str),
+    Index(usize),
+}
+
+impl MapError {
+    pub fn without_line(message: impl Into<String>) -> Self {
+        Self {
+            line: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Parses and validates a map, collecting every problem found
+pub fn parse_map(source: &str) -> Result<MapDefinition, Vec<MapError>> {
+    let map: MapDefinition = serde_yaml::from_str(source).map_err(|error| {
+        // The line is reported separately, so drop the location serde_yaml appends
+        let message = error.to_string();
+        let message = match message.find(" at line ") {
+            Some(end) => message[..end].to_string(),
+            None => message,
+        };
+
+        vec![MapError {
+            line: error.location().map(|location| location.line()),
+            message,
+        }]
+    })?;
+
+    let errors = map.validate(source);
+    if errors.is_empty() {
+        Ok(map)
+    } else {
+        Err(errors)
+    }
+}
+
+impl MapDefinition {
+    /// Checks values that parse correctly but make no sense on the map
+    fn validate(&self, source: &str) -> Vec<MapError> {
+        let mut problems: Vec<(Vec<Segment>, String)> = Vec::new();
+        let half = self.size / 2.0;
+        let in_bounds = |[x, z]: [f32; 2]| x.abs() <= half && z.abs() <= half;
+
+        if self.size <= 0.0 {
+            problems.push((vec![Segment::Key("size")], "size must be positive".into()));
+        }
+
+        if self.terrain.max_height < 0.0 {
+            problems.push((
+                vec![Segment::Key("terrain"), Segment::Key("max_height")],
+                "max_height must not be negative".into(),
+            ));
+        }
+
+        match &self.terrain.source {
+            TerrainSource::Heightmap(path) if path.trim().is_empty() => problems.push((
+                vec![Segment::Key("terrain"), Segment::Key("source")],
+                "heightmap path is empty".into(),
+            )),
+            TerrainSource::Noise(noise) if noise.octaves == 0 || noise.frequency <= 0.0 => problems
+                .push((
+                    vec![Segment::Key("terrain"), Segment::Key("source")],
+                    "noise needs at least one octave and a positive frequency".into(),
+                )),
+            _ => {}
+        }
+
+        if self.players.is_empty() {
+            problems.push((
+                vec![Segment::Key("players")],
+                "map needs at least one player".into(),
+            ));
+        }
+
+        for (index, player) in self.players.iter().enumerate() {
+            if !in_bounds(player.start) {
+                problems.push((
+                    vec![Segment::Key("players"), Segment::Index(index)],
+                    format!("start location of player {} is outside the map", index),
+                ));
+            }
+        }
+
+        for (index, obstacle) in self.obstacles.iter().enumerate() {
+            if !in_bounds(obstacle.position) {
+                problems.push((
+                    vec![
+                        Segment::Key("obstacles"),
+                        Segment::Index(index),
+                        Segment::Key("position"),
+                    ],
+                    "obstacle is outside the map".into(),
+                ));
+            }
+
+            if obstacle.size.iter().any(|side| *side <= 0.0) {
+                problems.push((
+                    vec![
+                        Segment::Key("obstacles"),
+                        Segment::Index(index),
+                        Segment::Key("size"),
+                    ],
+                    "obstacle size must be positive".into(),
+                ));
+            }
+        }
+
+        for (index, resource) in self.resources.iter().enumerate() {
+            if !in_bounds(resource.position) {
+                problems.push((
+                    vec![
+                        Segment::Key("resources"),
+                        Segment::Index(index),
+                        Segment::Key("position"),
+                    ],
+                    "resource node is outside the map".into(),
+                ));
+            }
+
+            if resource.amount == 0 {
+                problems.push((
+                    vec![
+                        Segment::Key("resources"),
+                        Segment::Index(index),
+                        Segment::Key("amount"),
+                    ],
+                    "resource node is empty".into(),
+                ));
+            }
+        }
+
+        for (index, unit) in self.units.iter().enumerate() {
+            if unit.player as usize >= self.players.len() {
+                problems.push((
+                    vec![
+                        Segment::Key("units"),
+                        Segment::Index(index),
+                        Segment::Key("player"),
+                    ],
+                    format!("player {} is not defined", unit.player),
+                ));
+            }
+
+            if !in_bounds(unit.position) {
+                problems.push((
+                    vec![
+                        Segment::Key("units"),
+                        Segment::Index(index),
+                        Segment::Key("position"),
+                    ],
+                    "unit is outside the map".into(),
+                ));
+            }
+        }
+
+        problems
+            .into_iter()
+            .map(|(path, message)| MapError {
+                line: find_line(source, &path),
+                message,
+            })
+            .collect()
+    }
+}
+
+/// Finds the line of a value in the YAML source by following keys and list indices.
+/// Falls back to the deepest part of the path that could be found.
+fn find_line(source: &str, path: &[Segment]) -> Option<usize> {
+    let lines: Vec<&str> = source.lines().map(strip_comment).collect();
+    let mut range = 0..lines.len();
+    let mut found = None;
+
+    for segment in path {
+        let mut candidates = range
+            .clone()
+            .filter(|index| !lines[*index].trim().is_empty());
+        let Some(first) = candidates.next() else {
+            break;
+        };
+
+        let line = match segment {
+            Segment::Key(key) => {
+                // Inside a list item the keys are aligned with the text after the dash
+                let key_indent = key_indent(lines[first]);
+                range.clone().find(|index| {
+                    let (indent, content) = key_position(lines[*index]);
+                    indent == key_indent
+                        && content
+                            .strip_prefix(key)
+                            .map(|rest| rest.trim_start().starts_with(':'))
+                            .unwrap_or(false)
+                })
+            }
+            Segment::Index(position) => {
+                let dash_indent = indent(lines[first]);
+                range
+                    .clone()
+                    .filter(|index| {
+                        indent(lines[*index]) == dash_indent
+                            && lines[*index].trim_start().starts_with('-')
+                    })
+                    .nth(*position)
+            }
+        };
+
+        let Some(line) = line else {
+            break;
+        };
+        found = Some(line + 1);
+
+        let line_indent = indent(lines[line]);
+        let end = (line + 1..range.end)
+            .find(|index| {
+                let next = lines[*index];
+                let next_indent = indent(next);
+                // A list may start at the same indentation as the key that holds it
+                let continues_list = matches!(segment, Segment::Key(_))
+                    && next_indent == line_indent
+                    && next.trim_start().starts_with('-');
+                !next.trim().is_empty() && next_indent <= line_indent && !continues_list
+            })
+            .unwrap_or(range.end);
+
+        range = match segment {
+            // List items may keep their first key on the same line as the dash
+            Segment::Index(_) => line..end,
+            Segment::Key(_) => line + 1..end,
+        };
+    }
+
+    found
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn key_indent(line: &str) -> usize {
+    key_position(line).0
+}
+
+/// Indentation and text of a line, looking past a leading list dash
+fn key_position(line: &str) -> (usize, &str) {
+    let content = line.trim_start();
+    match content.strip_prefix("- ") {
+        Some(rest) => (
+            indent(line) + 2 + rest.len() - rest.trim_start().len(),
+            rest.trim_start(),
+        ),
+        None => (indent(line), content),
+    }
+}
+
+/// Cuts off a comment. It starts at a `#` that follows a space or begins the line,
+/// and is not inside a quoted value
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut previous = ' ';
+    for (index, character) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if character == '\\' => escaped = true,
+            Some(open) if character == open => quote = None,
+            Some(_) => {}
+            // Quotes only start a value, an apostrophe within plain text stays text
+            None if matches!(character, '"' | '\'')
+                && matches!(previous, ' ' | ':' | '-' | '[' | '{' | ',') =>
+            {
+                quote = Some(character)
+            }
+            None if character == '#' && previous.is_whitespace() => return &line[..index],
+            None => {}
+        }
+        previous = character;
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+name: Test
+size: 20.0
+terrain:
+  max_height: 2.0
+  source: !noise
+    seed: 1
+    octaves: 2
+    frequency: 1.0
+    persistence: 0.5
+players:
+  - start: [-5.0, -5.0]
+  - start: [5.0, 5.0]
+obstacles:
+  - position: [0.0, 0.0]
+    size: [1.0, 1.0, 1.0]
+resources:
+  # Next to the first player
+  - kind: minerals
+    position: [-7.0, -5.0]
+    amount: 100
+units:
+  - player: 0
+    position: [-4.0, -5.0]
+  - player: 1
+    kind: soldier
+    position: [4.0, 5.0]
+";
+
+    /// Lines and messages of the problems in the map after replacing a part of it
+    fn errors(from: &str, to: &str) -> Vec<(Option<usize>, String)> {
+        assert!(MAP.contains(from), "{} is not in the map", from);
+        match parse_map(&MAP.replacen(from, to, 1)) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| (error.line, error.message))
+                .collect(),
+        }
+    }
+
+    fn error(line: usize, message: &str) -> Vec<(Option<usize>, String)> {
+        vec![(Some(line), message.to_string())]
+    }
+
+    #[test]
+    fn valid_map_parses() {
+        assert!(parse_map(MAP).is_ok());
+    }
+
+    #[test]
+    fn syntax_error_keeps_the_line_of_the_parser() {
+        let errors = errors("size: 20.0", "size: big");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, Some(2));
+        assert!(!errors[0].1.contains(" at line "));
+    }
+
+    #[test]
+    fn size_must_be_positive() {
+        let errors = errors("size: 20.0", "size: 0.0");
+        assert_eq!(errors[0], (Some(2), "size must be positive".to_string()));
+    }
+
+    #[test]
+    fn max_height_must_not_be_negative() {
+        assert_eq!(
+            errors("max_height: 2.0", "max_height: -1.0"),
+            error(4, "max_height must not be negative")
+        );
+    }
+
+    #[test]
+    fn heightmap_needs_a_path() {
+        assert_eq!(
+            errors(
+                "!noise\n    seed: 1\n    octaves: 2\n    frequency: 1.0\n    persistence: 0.5",
+                "!heightmap ' '"
+            ),
+            error(5, "heightmap path is empty")
+        );
+    }
+
+    #[test]
+    fn noise_needs_octaves() {
+        assert_eq!(
+            errors("octaves: 2", "octaves: 0"),
+            error(
+                5,
+                "noise needs at least one octave and a positive frequency"
+            )
+        );
+    }
+
+    #[test]
+    fn map_needs_players() {
+        let errors = errors(
+            "players:\n  - start: [-5.0, -5.0]\n  - start: [5.0, 5.0]",
+            "players: []",
+        );
+        assert_eq!(
+            errors[0],
+            (Some(10), "map needs at least one player".to_string())
+        );
+    }
+
+    #[test]
+    fn start_must_be_on_the_map() {
+        assert_eq!(
+            errors("start: [5.0, 5.0]", "start: [50.0, 5.0]"),
+            error(12, "start location of player 1 is outside the map")
+        );
+    }
+
+    #[test]
+    fn obstacle_must_be_on_the_map() {
+        assert_eq!(
+            errors("position: [0.0, 0.0]", "position: [0.0, 30.0]"),
+            error(14, "obstacle is outside the map")
+        );
+    }
+
+    #[test]
+    fn obstacle_size_must_be_positive() {
+        assert_eq!(
+            errors("size: [1.0, 1.0, 1.0]", "size: [1.0, 0.0, 1.0]"),
+            error(15, "obstacle size must be positive")
+        );
+    }
+
+    #[test]
+    fn resource_node_must_be_on_the_map() {
+        assert_eq!(
+            errors("position: [-7.0, -5.0]", "position: [-17.0, -5.0]"),
+            error(19, "resource node is outside the map")
+        );
+    }
+
+    #[test]
+    fn resource_node_must_not_be_empty() {
+        assert_eq!(
+            errors("amount: 100", "amount: 0"),
+            error(20, "resource node is empty")
+        );
+    }
+
+    #[test]
+    fn unit_player_must_be_defined() {
+        assert_eq!(
+            errors("- player: 1", "- player: 2"),
+            error(24, "player 2 is not defined")
+        );
+    }
+
+    #[test]
+    fn unit_must_be_on_the_map() {
+        assert_eq!(
+            errors("position: [4.0, 5.0]", "position: [4.0, 15.0]"),
+            error(26, "unit is outside the map")
+        );
+    }
+
+    #[test]
+    fn comments_end_at_a_hash_outside_of_quotes() {
+        assert_eq!(strip_comment("# Next to the first player"), "");
+        assert_eq!(strip_comment("size: 20.0 # metres"), "size: 20.0 ");
+        assert_eq!(
+            strip_comment("name: \"Ridge #2\" # new"),
+            "name: \"Ridge #2\" "
+        );
+        assert_eq!(strip_comment("name: 'Ridge #2'"), "name: 'Ridge #2'");
+        assert_eq!(strip_comment("name: \"a \\\" #b\""), "name: \"a \\\" #b\"");
+        assert_eq!(strip_comment("name: Bob's #2"), "name: Bob's ");
+        assert_eq!(strip_comment("name: Ridge#2"), "name: Ridge#2");
+    }
+}
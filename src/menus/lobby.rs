@@ -0,0 +1,371 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+
+use crate::{
+    economy::{Stockpile, STARTING_RESOURCES},
+    game::GameSetup,
+    map::{available_maps, MapSummary, DEFAULT_MAP},
+    player::{Controller, LocalPlayer, PlayerInfo, Players, PLAYER_COLORS},
+    ui::UiFont,
+    GameState,
+};
+
+use super::{
+    menu_root, spawn_button, spawn_sized_button, spawn_title, text_style, Disabled,
+    BUTTON_FONT_SIZE, BUTTON_SPACING, TEXT_COLOR,
+};
+
+pub struct LobbyPlugin;
+
+const MIN_PLAYERS: usize = 2;
+const WIDE_BUTTON_WIDTH: f32 = 400.0;
+const SETTING_BUTTON_WIDTH: f32 = 197.0;
+const PLAYER_BUTTON_WIDTH: f32 = 120.0;
+const PLAYER_LABEL_WIDTH: f32 = 100.0;
+
+const STARTING_RESOURCE_PRESETS: [(&str, Stockpile); 3] = [
+    (
+        "Low",
+        Stockpile {
+            minerals: 100,
+            wood: 50,
+        },
+    ),
+    ("Standard", STARTING_RESOURCES),
+    (
+        "High",
+        Stockpile {
+            minerals: 1000,
+            wood: 500,
+        },
+    ),
+];
+const STANDARD_RESOURCES: usize = 1;
+
+#[derive(Component)]
+struct Lobby;
+
+/// Settings of the next game, only exists while the lobby is open
+#[derive(Resource)]
+struct SkirmishSetup {
+    maps: Vec<MapSummary>,
+    map: usize,
+    players: Vec<LobbyPlayer>,
+    /// Index into `STARTING_RESOURCE_PRESETS`
+    resources: usize,
+}
+
+struct LobbyPlayer {
+    /// Index into `PLAYER_COLORS`
+    color: usize,
+    team: u8,
+    controller: Controller,
+}
+
+#[derive(Component, Clone, Copy)]
+enum LobbyAction {
+    CycleMap,
+    CyclePlayerCount,
+    CycleResources,
+    CycleColor(usize),
+    CycleTeam(usize),
+    CycleController(usize),
+    Start,
+    Back,
+}
+
+impl SkirmishSetup {
+    fn new() -> Self {
+        let maps = available_maps();
+        let map = maps
+            .iter()
+            .position(|map| map.path == DEFAULT_MAP)
+            .unwrap_or_default();
+        let mut setup = Self {
+            maps,
+            map,
+            players: Vec::new(),
+            resources: STANDARD_RESOURCES,
+        };
+        setup.set_player_count(MIN_PLAYERS);
+        setup
+    }
+
+    fn max_players(&self) -> usize {
+        self.maps
+            .get(self.map)
+            .map_or(MIN_PLAYERS, |map| map.players.max(MIN_PLAYERS))
+    }
+
+    /// Adds computer opponents on their own team, or removes the last players
+    fn set_player_count(&mut self, count: usize) {
+        self.players.truncate(count);
+        while self.players.len() < count {
+            let index = self.players.len();
+            self.players.push(LobbyPlayer {
+                color: self.next_free_color(index),
+                team: index as u8,
+                controller: if index == 0 {
+                    Controller::Human
+                } else {
+                    Controller::Ai
+                },
+            });
+        }
+    }
+
+    /// First colour after the player's current one that nobody else has
+    fn next_free_color(&self, player: usize) -> usize {
+        let current = self
+            .players
+            .get(player)
+            .map_or(PLAYER_COLORS.len() - 1, |player| player.color);
+        (1..=PLAYER_COLORS.len())
+            .map(|offset| (current + offset) % PLAYER_COLORS.len())
+            .find(|color| {
+                self.players
+                    .iter()
+                    .enumerate()
+                    .all(|(index, other)| index == player || other.color != *color)
+            })
+            .unwrap_or(current)
+    }
+
+    fn can_start(&self) -> bool {
+        !self.maps.is_empty()
+            && self
+                .players
+                .iter()
+                .any(|player| player.controller == Controller::Human)
+    }
+}
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(open_lobby.in_schedule(OnEnter(GameState::Lobby)))
+            .add_system(close_lobby.in_schedule(OnExit(GameState::Lobby)))
+            .add_systems(
+                (press_buttons, rebuild_lobby)
+                    .chain()
+                    .in_set(OnUpdate(GameState::Lobby)),
+            );
+    }
+}
+
+fn open_lobby(mut commands: Commands) {
+    commands.insert_resource(SkirmishSetup::new());
+    commands.spawn((menu_root(), Name::from("Lobby"), Lobby));
+}
+
+fn close_lobby(mut commands: Commands, lobbies: Query<(Entity, With<Lobby>)>) {
+    for (entity, _) in &lobbies {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<SkirmishSetup>();
+}
+
+/// Fills the lobby again whenever a setting changes
+fn rebuild_lobby(
+    mut commands: Commands,
+    lobby: Query<(Entity, With<Lobby>)>,
+    setup: Res<SkirmishSetup>,
+    font: Res<UiFont>,
+) {
+    if !setup.is_changed() {
+        return;
+    }
+    let Ok((lobby, _)) = lobby.get_single() else {
+        return;
+    };
+
+    let mut lobby = commands.entity(lobby);
+    lobby.despawn_descendants();
+    lobby.with_children(|parent| {
+        spawn_title(parent, &font, "Skirmish");
+
+        let map = match setup.maps.get(setup.map) {
+            Some(map) => format!("Map: {} ({} players)", map.name, map.players),
+            None => "No maps found".to_string(),
+        };
+        spawn_sized_button(
+            parent,
+            &font,
+            &map,
+            LobbyAction::CycleMap,
+            WIDE_BUTTON_WIDTH,
+            TEXT_COLOR,
+            true,
+        );
+
+        parent.spawn(row()).with_children(|row| {
+            spawn_sized_button(
+                row,
+                &font,
+                &format!("Players: {}", setup.players.len()),
+                LobbyAction::CyclePlayerCount,
+                SETTING_BUTTON_WIDTH,
+                TEXT_COLOR,
+                true,
+            );
+            spawn_sized_button(
+                row,
+                &font,
+                &format!(
+                    "Resources: {}",
+                    STARTING_RESOURCE_PRESETS[setup.resources].0
+                ),
+                LobbyAction::CycleResources,
+                SETTING_BUTTON_WIDTH,
+                TEXT_COLOR,
+                true,
+            );
+        });
+
+        for (index, player) in setup.players.iter().enumerate() {
+            parent.spawn(row()).with_children(|row| {
+                row.spawn(
+                    TextBundle::from_section(
+                        format!("Player {}", index + 1),
+                        text_style(&font, BUTTON_FONT_SIZE, TEXT_COLOR),
+                    )
+                    .with_style(Style {
+                        size: Size::width(Val::Px(PLAYER_LABEL_WIDTH)),
+                        margin: UiRect::all(Val::Px(BUTTON_SPACING)),
+                        ..default()
+                    }),
+                );
+
+                let (color_name, color) = PLAYER_COLORS[player.color];
+                spawn_sized_button(
+                    row,
+                    &font,
+                    color_name,
+                    LobbyAction::CycleColor(index),
+                    PLAYER_BUTTON_WIDTH,
+                    color,
+                    true,
+                );
+                spawn_sized_button(
+                    row,
+                    &font,
+                    &format!("Team {}", player.team + 1),
+                    LobbyAction::CycleTeam(index),
+                    PLAYER_BUTTON_WIDTH,
+                    TEXT_COLOR,
+                    true,
+                );
+                let controller = match player.controller {
+                    Controller::Human => "Human",
+                    Controller::Ai => "AI",
+                };
+                spawn_sized_button(
+                    row,
+                    &font,
+                    controller,
+                    LobbyAction::CycleController(index),
+                    PLAYER_BUTTON_WIDTH,
+                    TEXT_COLOR,
+                    true,
+                );
+            });
+        }
+
+        parent.spawn(row()).with_children(|row| {
+            spawn_button(row, &font, "Back", LobbyAction::Back, true);
+            spawn_button(row, &font, "Start", LobbyAction::Start, setup.can_start());
+        });
+    });
+}
+
+fn row() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        ..default()
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn press_buttons(
+    mut commands: Commands,
+    buttons: Query<(&Interaction, &LobbyAction), (Changed<Interaction>, Without<Disabled>)>,
+    mut setup: ResMut<SkirmishSetup>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, action) in &buttons {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        match *action {
+            LobbyAction::CycleMap => {
+                if !setup.maps.is_empty() {
+                    setup.map = (setup.map + 1) % setup.maps.len();
+                    let count = setup.players.len().min(setup.max_players());
+                    setup.set_player_count(count);
+                }
+            }
+            LobbyAction::CyclePlayerCount => {
+                let count = setup.players.len() + 1;
+                let count = if count > setup.max_players() {
+                    MIN_PLAYERS
+                } else {
+                    count
+                };
+                setup.set_player_count(count);
+            }
+            LobbyAction::CycleResources => {
+                setup.resources = (setup.resources + 1) % STARTING_RESOURCE_PRESETS.len();
+            }
+            LobbyAction::CycleColor(player) => {
+                let color = setup.next_free_color(player);
+                setup.players[player].color = color;
+            }
+            LobbyAction::CycleTeam(player) => {
+                let teams = setup.players.len() as u8;
+                let team = &mut setup.players[player].team;
+                *team = (*team + 1) % teams;
+            }
+            LobbyAction::CycleController(player) => {
+                let controller = &mut setup.players[player].controller;
+                *controller = match controller {
+                    Controller::Human => Controller::Ai,
+                    Controller::Ai => Controller::Human,
+                };
+            }
+            LobbyAction::Back => next_state.set(GameState::MainMenu),
+            LobbyAction::Start => {
+                let players = Players(
+                    setup
+                        .players
+                        .iter()
+                        .map(|player| PlayerInfo {
+                            color: PLAYER_COLORS[player.color].1,
+                            team: player.team,
+                            controller: player.controller,
+                        })
+                        .collect(),
+                );
+                let (Some(map), Some(local_player)) =
+                    (setup.maps.get(setup.map), players.first_human())
+                else {
+                    continue;
+                };
+
+                commands.insert_resource(LocalPlayer(local_player.0));
+                commands.insert_resource(players);
+                commands.insert_resource(GameSetup {
+                    map: map.path.clone(),
+                    starting_resources: STARTING_RESOURCE_PRESETS[setup.resources].1,
+                    seed: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |time| time.as_nanos() as u64),
+                });
+                next_state.set(GameState::InGame);
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    replay::{latest_replay, WatchReplay},
+    save::{latest_save, LoadGame},
+    ui::UiFont,
+    GameState,
+};
+
+use super::{menu_root, spawn_button, spawn_title, Disabled};
+
+pub struct MainMenuPlugin;
+
+#[derive(Component)]
+struct MainMenu;
+
+#[derive(Component, Clone, Copy)]
+enum MainMenuAction {
+    Skirmish,
+    LoadGame,
+    WatchReplay,
+    Quit,
+}
+
+impl Plugin for MainMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_main_menu.in_schedule(OnEnter(GameState::MainMenu)))
+            .add_system(despawn_main_menu.in_schedule(OnExit(GameState::MainMenu)))
+            .add_system(press_buttons.in_set(OnUpdate(GameState::MainMenu)));
+    }
+}
+
+fn spawn_main_menu(mut commands: Commands, font: Res<UiFont>) {
+    commands
+        .spawn((menu_root(), Name::from("Main Menu"), MainMenu))
+        .with_children(|parent| {
+            spawn_title(parent, &font, "RTS");
+            spawn_button(parent, &font, "Skirmish", MainMenuAction::Skirmish, true);
+            spawn_button(
+                parent,
+                &font,
+                "Load Last Save",
+                MainMenuAction::LoadGame,
+                latest_save().is_some(),
+            );
+            spawn_button(
+                parent,
+                &font,
+                "Watch Last Replay",
+                MainMenuAction::WatchReplay,
+                latest_replay().is_some(),
+            );
+            spawn_button(parent, &font, "Quit", MainMenuAction::Quit, true);
+        });
+}
+
+fn despawn_main_menu(mut commands: Commands, menus: Query<(Entity, With<MainMenu>)>) {
+    for (entity, _) in &menus {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn press_buttons(
+    buttons: Query<(&Interaction, &MainMenuAction), (Changed<Interaction>, Without<Disabled>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut loads: EventWriter<LoadGame>,
+    mut replays: EventWriter<WatchReplay>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for (interaction, action) in &buttons {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        match action {
+            MainMenuAction::Skirmish => next_state.set(GameState::Lobby),
+            MainMenuAction::LoadGame => {
+                if let Some(path) = latest_save() {
+                    loads.send(LoadGame(path));
+                }
+            }
+            MainMenuAction::WatchReplay => {
+                if let Some(path) = latest_replay() {
+                    replays.send(WatchReplay(path));
+                }
+            }
+            MainMenuAction::Quit => exit.send(AppExit),
+        }
+    }
+}
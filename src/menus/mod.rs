@@ -0,0 +1,144 @@
+mod lobby;
+mod main_menu;
+mod pause;
+
+use bevy::prelude::*;
+
+use crate::ui::UiFont;
+
+use self::{lobby::LobbyPlugin, main_menu::MainMenuPlugin, pause::PauseMenuPlugin};
+
+pub struct MenuPlugin;
+
+const TITLE_FONT_SIZE: f32 = 36.0;
+const BUTTON_FONT_SIZE: f32 = 20.0;
+const BUTTON_WIDTH: f32 = 260.0;
+const BUTTON_HEIGHT: f32 = 44.0;
+const BUTTON_SPACING: f32 = 6.0;
+
+const BACKDROP_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+const TEXT_COLOR: Color = Color::WHITE;
+const DISABLED_TEXT_COLOR: Color = Color::rgb(0.45, 0.45, 0.45);
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.2);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.35);
+const PRESSED_BUTTON_COLOR: Color = Color::rgb(0.35, 0.35, 0.5);
+
+#[derive(Component)]
+struct MenuButton;
+
+/// Menu button that can not be pressed yet
+#[derive(Component)]
+struct Disabled;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(MainMenuPlugin)
+            .add_plugin(LobbyPlugin)
+            .add_plugin(PauseMenuPlugin)
+            .add_system(highlight_buttons);
+    }
+}
+
+/// Full window node that dims the game behind a centered column of buttons
+fn menu_root() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            size: Size::all(Val::Percent(100.0)),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: BACKDROP_COLOR.into(),
+        ..default()
+    }
+}
+
+fn spawn_title(parent: &mut ChildBuilder, font: &UiFont, title: &str) {
+    parent.spawn(
+        TextBundle::from_section(title, text_style(font, TITLE_FONT_SIZE, TEXT_COLOR)).with_style(
+            Style {
+                margin: UiRect::bottom(Val::Px(BUTTON_HEIGHT / 2.0)),
+                ..default()
+            },
+        ),
+    );
+}
+
+/// Button with a centered label, `action` tells the menu what it does
+fn spawn_button<T: Component>(
+    parent: &mut ChildBuilder,
+    font: &UiFont,
+    label: &str,
+    action: T,
+    enabled: bool,
+) {
+    let color = if enabled {
+        TEXT_COLOR
+    } else {
+        DISABLED_TEXT_COLOR
+    };
+    spawn_sized_button(parent, font, label, action, BUTTON_WIDTH, color, enabled);
+}
+
+fn spawn_sized_button<T: Component>(
+    parent: &mut ChildBuilder,
+    font: &UiFont,
+    label: &str,
+    action: T,
+    width: f32,
+    color: Color,
+    enabled: bool,
+) {
+    let mut button = parent.spawn((
+        ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(width), Val::Px(BUTTON_HEIGHT)),
+                margin: UiRect::all(Val::Px(BUTTON_SPACING)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BUTTON_COLOR.into(),
+            ..default()
+        },
+        MenuButton,
+        action,
+    ));
+    button.with_children(|button| {
+        button.spawn(TextBundle::from_section(
+            label,
+            text_style(font, BUTTON_FONT_SIZE, color),
+        ));
+    });
+
+    if !enabled {
+        button.insert(Disabled);
+    }
+}
+
+fn text_style(font: &UiFont, font_size: f32, color: Color) -> TextStyle {
+    TextStyle {
+        font: font.0.clone(),
+        font_size,
+        color,
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn highlight_buttons(
+    mut buttons: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MenuButton>, Without<Disabled>),
+    >,
+) {
+    for (interaction, mut color) in &mut buttons {
+        *color = match interaction {
+            Interaction::Clicked => PRESSED_BUTTON_COLOR,
+            Interaction::Hovered => HOVERED_BUTTON_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        }
+        .into();
+    }
+}
@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+
+use crate::{
+    overlays::OverlaySettings,
+    save::{latest_save, LoadGame, SaveGame},
+    simulation::GameSpeed,
+    ui::UiFont,
+    GameState,
+};
+
+use super::{menu_root, spawn_button, spawn_title, Disabled};
+
+pub struct PauseMenuPlugin;
+
+#[derive(Component)]
+struct PauseMenu;
+
+/// Page of the pause menu, only one is shown at a time
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum PausePage {
+    Main,
+    Settings,
+}
+
+#[derive(Component, Clone, Copy)]
+enum PauseAction {
+    Resume,
+    Settings,
+    Save,
+    Load,
+    Quit,
+    CycleBars,
+    CycleSpeed,
+    Back,
+}
+
+impl Plugin for PauseMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_pause_menu.in_schedule(OnEnter(GameState::Paused)))
+            .add_system(despawn_pause_menu.in_schedule(OnExit(GameState::Paused)))
+            .add_systems(
+                (press_buttons, update_setting_texts)
+                    .chain()
+                    .in_set(OnUpdate(GameState::Paused)),
+            );
+    }
+}
+
+fn spawn_pause_menu(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    settings: Res<OverlaySettings>,
+    speed: Res<GameSpeed>,
+) {
+    commands
+        .spawn((menu_root(), Name::from("Pause Menu"), PauseMenu))
+        .with_children(|parent| {
+            parent
+                .spawn((page(Display::Flex), PausePage::Main))
+                .with_children(|page| {
+                    spawn_title(page, &font, "Paused");
+                    spawn_button(page, &font, "Resume", PauseAction::Resume, true);
+                    spawn_button(page, &font, "Settings", PauseAction::Settings, true);
+                    spawn_button(page, &font, "Save", PauseAction::Save, true);
+                    spawn_button(
+                        page,
+                        &font,
+                        "Load Last Save",
+                        PauseAction::Load,
+                        latest_save().is_some(),
+                    );
+                    spawn_button(page, &font, "Quit to Main Menu", PauseAction::Quit, true);
+                });
+
+            parent
+                .spawn((page(Display::None), PausePage::Settings))
+                .with_children(|page| {
+                    spawn_title(page, &font, "Settings");
+                    spawn_button(
+                        page,
+                        &font,
+                        &bar_setting_label(&settings),
+                        PauseAction::CycleBars,
+                        true,
+                    );
+                    spawn_button(
+                        page,
+                        &font,
+                        &speed_setting_label(*speed),
+                        PauseAction::CycleSpeed,
+                        true,
+                    );
+                    spawn_button(page, &font, "Back", PauseAction::Back, true);
+                });
+        });
+}
+
+fn page(display: Display) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            display,
+            ..default()
+        },
+        ..default()
+    }
+}
+
+fn bar_setting_label(settings: &OverlaySettings) -> String {
+    format!("Health bars: {}", settings.bars.name())
+}
+
+fn speed_setting_label(speed: GameSpeed) -> String {
+    format!("Game speed: {}", speed.name())
+}
+
+fn despawn_pause_menu(mut commands: Commands, menus: Query<(Entity, With<PauseMenu>)>) {
+    for (entity, _) in &menus {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn press_buttons(
+    buttons: Query<(&Interaction, &PauseAction), (Changed<Interaction>, Without<Disabled>)>,
+    mut pages: Query<(&PausePage, &mut Style)>,
+    mut settings: ResMut<OverlaySettings>,
+    mut speed: ResMut<GameSpeed>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut saves: EventWriter<SaveGame>,
+    mut loads: EventWriter<LoadGame>,
+) {
+    for (interaction, action) in &buttons {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let mut show_page = |shown: PausePage| {
+            for (page, mut style) in &mut pages {
+                style.display = if *page == shown {
+                    Display::Flex
+                } else {
+                    Display::None
+                };
+            }
+        };
+
+        match action {
+            PauseAction::Resume => next_state.set(GameState::InGame),
+            PauseAction::Settings => show_page(PausePage::Settings),
+            PauseAction::Back => show_page(PausePage::Main),
+            PauseAction::CycleBars => settings.bars = settings.bars.next(),
+            PauseAction::CycleSpeed => *speed = speed.next(),
+            // Back into the game, which shows the save went through
+            PauseAction::Save => {
+                saves.send(SaveGame);
+                next_state.set(GameState::InGame);
+            }
+            // The running game ends first, the save starts from the main menu
+            PauseAction::Load => {
+                if let Some(path) = latest_save() {
+                    loads.send(LoadGame(path));
+                    next_state.set(GameState::MainMenu);
+                }
+            }
+            PauseAction::Quit => next_state.set(GameState::MainMenu),
+        }
+    }
+}
+
+fn update_setting_texts(
+    buttons: Query<(&PauseAction, &Children)>,
+    mut texts: Query<&mut Text>,
+    settings: Res<OverlaySettings>,
+    speed: Res<GameSpeed>,
+) {
+    for (action, children) in &buttons {
+        let label = match action {
+            PauseAction::CycleBars if settings.is_changed() => bar_setting_label(&settings),
+            PauseAction::CycleSpeed if speed.is_changed() => speed_setting_label(*speed),
+            _ => continue,
+        };
+
+        let mut texts = texts.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    ui::RelativeCursorPosition,
+};
+
+use crate::{
+    camera::{center_camera_on, intersect_ground_plane},
+    command::PlayerCommand,
+    fog::{CellVisibility, VisibilityGrid},
+    ground::Terrain,
+    hud::HudNode,
+    order::{is_queueing, Orders},
+    overlays::{MarkerKind, OrderMarker},
+    player::{LocalPlayer, Owner, Players},
+    selection::Selectable,
+    simulation::StableId,
+    units::Unit,
+    GameState,
+};
+
+pub struct MinimapPlugin;
+
+/// Width and height of the minimap texture in pixels
+const MINIMAP_RESOLUTION: u32 = 128;
+/// Width and height of the minimap node in logical pixels
+const MINIMAP_SIZE: f32 = 200.0;
+const MINIMAP_MARGIN: f32 = 10.0;
+const MINIMAP_REFRESH_SECONDS: f32 = 0.1;
+
+const BACKGROUND_COLOR: Color = Color::BLACK;
+const EXPLORED_SHADE: f32 = 0.5;
+const UNEXPLORED_SHADE: f32 = 0.1;
+const UNIT_DOT_RADIUS: i32 = 1;
+const FRUSTUM_COLOR: Color = Color::WHITE;
+
+#[derive(Component)]
+struct Minimap;
+
+#[derive(Resource)]
+struct MinimapImage {
+    handle: Handle<Image>,
+    /// Terrain pixels copied into the image before units are drawn
+    background: Vec<u8>,
+    refresh: Timer,
+}
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_minimap)
+            .add_system(draw_terrain_background.run_if(resource_added::<Terrain>()))
+            .add_systems(
+                (
+                    redraw_minimap,
+                    move_camera_to_click,
+                    send_move_order_from_minimap,
+                )
+                    .distributive_run_if(resource_exists::<Terrain>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+fn spawn_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let background = [color_to_rgba(BACKGROUND_COLOR)]
+        .repeat((MINIMAP_RESOLUTION * MINIMAP_RESOLUTION) as usize)
+        .concat();
+
+    let image = Image::new(
+        Extent3d {
+            width: MINIMAP_RESOLUTION,
+            height: MINIMAP_RESOLUTION,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        background.clone(),
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    let handle = images.add(image);
+
+    commands.spawn((
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(MINIMAP_MARGIN),
+                    bottom: Val::Px(MINIMAP_MARGIN),
+                    ..default()
+                },
+                size: Size::new(Val::Px(MINIMAP_SIZE), Val::Px(MINIMAP_SIZE)),
+                ..default()
+            },
+            image: UiImage::new(handle.clone()),
+            ..default()
+        },
+        RelativeCursorPosition::default(),
+        Name::from("Minimap"),
+        HudNode,
+        Minimap,
+    ));
+
+    commands.insert_resource(MinimapImage {
+        handle,
+        background,
+        refresh: Timer::from_seconds(MINIMAP_REFRESH_SECONDS, TimerMode::Repeating),
+    });
+}
+
+fn draw_terrain_background(mut minimap: ResMut<MinimapImage>, terrain: Res<Terrain>) {
+    let resolution = MINIMAP_RESOLUTION as usize;
+    let pixel_size = terrain.size() / MINIMAP_RESOLUTION as f32;
+
+    for y in 0..resolution {
+        for x in 0..resolution {
+            let world_x = (x as f32 + 0.5) * pixel_size - terrain.size() / 2.0;
+            let world_z = (y as f32 + 0.5) * pixel_size - terrain.size() / 2.0;
+            let index = (y * resolution + x) * 4;
+            minimap.background[index..index + 4]
+                .copy_from_slice(&color_to_rgba(terrain.color_at(world_x, world_z)));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn redraw_minimap(
+    mut minimap: ResMut<MinimapImage>,
+    mut images: ResMut<Assets<Image>>,
+    units: Query<(&GlobalTransform, &Owner, &Visibility, With<Unit>)>,
+    camera: Query<(&Camera, &GlobalTransform, With<Camera3d>)>,
+    players: Res<Players>,
+    local_player: Res<LocalPlayer>,
+    grid: Option<Res<VisibilityGrid>>,
+    terrain: Res<Terrain>,
+    time: Res<Time>,
+) {
+    if !minimap.refresh.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let Some(image) = images.get_mut(&minimap.handle) else {
+        return;
+    };
+    image.data.copy_from_slice(&minimap.background);
+
+    if let Some(grid) = grid {
+        draw_fog(&mut image.data, &grid, local_player.0, terrain.size());
+    }
+
+    for (transform, owner, visibility, _) in &units {
+        if visibility == Visibility::Hidden {
+            continue;
+        }
+
+        let (x, y) = world_to_pixel(transform.translation(), terrain.size());
+        let color = color_to_rgba(players.color(*owner));
+        for dy in -UNIT_DOT_RADIUS..=UNIT_DOT_RADIUS {
+            for dx in -UNIT_DOT_RADIUS..=UNIT_DOT_RADIUS {
+                put_pixel(&mut image.data, x + dx, y + dy, color);
+            }
+        }
+    }
+
+    let (camera, camera_transform, _) = camera.single();
+    if let Some(corners) = frustum_on_ground(camera, camera_transform, terrain.size()) {
+        let color = color_to_rgba(FRUSTUM_COLOR);
+        for i in 0..corners.len() {
+            let start = world_to_pixel(corners[i], terrain.size());
+            let end = world_to_pixel(corners[(i + 1) % corners.len()], terrain.size());
+            draw_line(&mut image.data, start, end, color);
+        }
+    }
+}
+
+/// Darkens pixels the local player cannot see, reusing the fog of war grid
+fn draw_fog(data: &mut [u8], grid: &VisibilityGrid, player: u8, map_size: f32) {
+    let resolution = MINIMAP_RESOLUTION as usize;
+    let pixel_size = map_size / MINIMAP_RESOLUTION as f32;
+
+    for y in 0..resolution {
+        for x in 0..resolution {
+            let position = Vec3::new(
+                (x as f32 + 0.5) * pixel_size - map_size / 2.0,
+                0.0,
+                (y as f32 + 0.5) * pixel_size - map_size / 2.0,
+            );
+            let shade = match grid.visibility(player, position) {
+                CellVisibility::Visible => continue,
+                CellVisibility::Explored => EXPLORED_SHADE,
+                CellVisibility::Unexplored => UNEXPLORED_SHADE,
+            };
+
+            let index = (y * resolution + x) * 4;
+            for channel in &mut data[index..index + 3] {
+                *channel = (*channel as f32 * shade) as u8;
+            }
+        }
+    }
+}
+
+fn move_camera_to_click(
+    minimap: Query<(&RelativeCursorPosition, With<Minimap>)>,
+    mut camera: Query<(&mut Transform, With<Camera3d>)>,
+    input: Res<Input<MouseButton>>,
+    terrain: Res<Terrain>,
+) {
+    if !input.pressed(MouseButton::Left) {
+        return;
+    }
+
+    let Some(target) = minimap
+        .iter()
+        .find_map(|(position, _)| clicked_world_point(position, &terrain))
+    else {
+        return;
+    };
+
+    let (mut transform, _) = camera.single_mut();
+    center_camera_on(&mut transform, target);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_move_order_from_minimap(
+    minimap: Query<(&RelativeCursorPosition, With<Minimap>)>,
+    units: Query<(&StableId, &Selectable, &Owner, With<Orders>)>,
+    input: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    terrain: Res<Terrain>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<PlayerCommand>,
+    mut markers: EventWriter<OrderMarker>,
+) {
+    if !input.just_pressed(MouseButton::Right) {
+        return;
+    }
+
+    let Some(target) = minimap
+        .iter()
+        .find_map(|(position, _)| clicked_world_point(position, &terrain))
+    else {
+        return;
+    };
+
+    let units: Vec<StableId> = units
+        .iter()
+        .filter(|(_, selectable, owner, _)| selectable.is_selected && owner.0 == local_player.0)
+        .map(|(id, ..)| *id)
+        .collect();
+    if !units.is_empty() {
+        writer.send(PlayerCommand::Move {
+            units,
+            position: target,
+            queued: is_queueing(&keys),
+        });
+        markers.send(OrderMarker {
+            kind: MarkerKind::Move,
+            position: target,
+        });
+    }
+}
+
+/// World point under the cursor when it hovers the minimap
+fn clicked_world_point(position: &RelativeCursorPosition, terrain: &Terrain) -> Option<Vec3> {
+    if !position.mouse_over() {
+        return None;
+    }
+
+    position.normalized.map(|normalized| {
+        let x = (normalized.x - 0.5) * terrain.size();
+        let z = (normalized.y - 0.5) * terrain.size();
+        Vec3::new(x, terrain.height_at(x, z), z)
+    })
+}
+
+/// Points where the corners of the camera view meet the ground, in clockwise order
+fn frustum_on_ground(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    map_size: f32,
+) -> Option<[Vec3; 4]> {
+    let size = camera.logical_viewport_size()?;
+    let corners = [
+        Vec2::new(0.0, 0.0),
+        Vec2::new(0.0, size.y),
+        Vec2::new(size.x, size.y),
+        Vec2::new(size.x, 0.0),
+    ];
+
+    let mut points = [Vec3::ZERO; 4];
+    for (point, corner) in points.iter_mut().zip(corners) {
+        let ray = camera.viewport_to_world(camera_transform, corner)?;
+        *point = intersect_ground_plane(ray.origin, ray.direction)
+            .unwrap_or(ray.origin + ray.direction * map_size);
+    }
+
+    Some(points)
+}
+
+fn world_to_pixel(position: Vec3, map_size: f32) -> (i32, i32) {
+    let resolution = MINIMAP_RESOLUTION as f32;
+    (
+        ((position.x / map_size + 0.5) * resolution).floor() as i32,
+        ((position.z / map_size + 0.5) * resolution).floor() as i32,
+    )
+}
+
+fn put_pixel(data: &mut [u8], x: i32, y: i32, color: [u8; 4]) {
+    let resolution = MINIMAP_RESOLUTION as i32;
+    if !(0..resolution).contains(&x) || !(0..resolution).contains(&y) {
+        return;
+    }
+
+    let index = ((y * resolution + x) * 4) as usize;
+    data[index..index + 4].copy_from_slice(&color);
+}
+
+/// Bresenham line, clipped to the image bounds
+fn draw_line(data: &mut [u8], start: (i32, i32), end: (i32, i32), color: [u8; 4]) {
+    let (mut x, mut y) = start;
+    let dx = (end.0 - x).abs();
+    let dy = -(end.1 - y).abs();
+    let step_x = if x < end.0 { 1 } else { -1 };
+    let step_y = if y < end.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    loop {
+        put_pixel(data, x, y, color);
+        if (x, y) == end {
+            break;
+        }
+
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+fn color_to_rgba(color: Color) -> [u8; 4] {
+    color.as_rgba_f32().map(|channel| (channel * 255.0) as u8)
+}
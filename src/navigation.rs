@@ -0,0 +1,152 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{ground::Terrain, simulation::StepSet, snapshot::SnapshotApp, GameState};
+
+pub struct NavigationPlugin;
+
+/// Width of a navigation cell in world units
+pub const NAVIGATION_CELL_SIZE: f32 = 1.0;
+
+/// Area an entity takes up on the navigation grid, in cells
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct Footprint {
+    pub width: u32,
+    pub depth: u32,
+}
+
+impl Footprint {
+    /// Size of the footprint in world units
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.depth as f32) * NAVIGATION_CELL_SIZE
+    }
+}
+
+/// Cells of the map that can not be walked through or built on
+#[derive(Resource)]
+pub struct NavigationGrid {
+    size: f32,
+    cells_per_side: usize,
+    reserved: Vec<bool>,
+    footprints: HashMap<Entity, Vec<usize>>,
+}
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<Footprint>()
+            .add_system(
+                create_navigation_grid
+                    .run_if(resource_added::<Terrain>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(
+                reserve_footprints
+                    .in_set(StepSet::Orders)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+impl NavigationGrid {
+    fn new(size: f32) -> Self {
+        let cells_per_side = (size / NAVIGATION_CELL_SIZE).floor().max(1.0) as usize;
+        Self {
+            size,
+            cells_per_side,
+            reserved: vec![false; cells_per_side * cells_per_side],
+            footprints: HashMap::default(),
+        }
+    }
+
+    /// Moves the position so that the footprint lines up with the cells
+    pub fn snap(&self, position: Vec3, footprint: Footprint) -> Vec3 {
+        let half = footprint.size() / 2.0;
+        let snap = |value: f32, half: f32| {
+            let corner = ((value - half + self.size / 2.0) / NAVIGATION_CELL_SIZE).round();
+            corner * NAVIGATION_CELL_SIZE + half - self.size / 2.0
+        };
+
+        Vec3::new(
+            snap(position.x, half.x),
+            position.y,
+            snap(position.z, half.y),
+        )
+    }
+
+    /// Whether the footprint centered at the position is inside the map and not reserved
+    pub fn is_free(&self, center: Vec3, footprint: Footprint) -> bool {
+        self.footprint_cells(center, footprint)
+            .map(|cells| cells.iter().all(|cell| !self.reserved[*cell]))
+            .unwrap_or(false)
+    }
+
+    /// Cells under the footprint, `None` if a part of it is outside of the map
+    fn footprint_cells(&self, center: Vec3, footprint: Footprint) -> Option<Vec<usize>> {
+        let half = footprint.size() / 2.0;
+        let first_column = ((center.x - half.x + self.size / 2.0) / NAVIGATION_CELL_SIZE).round();
+        let first_row = ((center.z - half.y + self.size / 2.0) / NAVIGATION_CELL_SIZE).round();
+        let side = self.cells_per_side as f32;
+        if first_column < 0.0
+            || first_row < 0.0
+            || first_column + footprint.width as f32 > side
+            || first_row + footprint.depth as f32 > side
+        {
+            return None;
+        }
+
+        let (first_column, first_row) = (first_column as usize, first_row as usize);
+        let mut cells = Vec::with_capacity((footprint.width * footprint.depth) as usize);
+        for row in first_row..first_row + footprint.depth as usize {
+            for column in first_column..first_column + footprint.width as usize {
+                cells.push(row * self.cells_per_side + column);
+            }
+        }
+
+        Some(cells)
+    }
+
+    fn reserve(&mut self, entity: Entity, center: Vec3, footprint: Footprint) {
+        self.release(entity);
+        let cells = self.footprint_cells(center, footprint).unwrap_or_default();
+        for cell in &cells {
+            self.reserved[*cell] = true;
+        }
+        self.footprints.insert(entity, cells);
+    }
+
+    fn release(&mut self, entity: Entity) {
+        for cell in self.footprints.remove(&entity).unwrap_or_default() {
+            self.reserved[cell] = false;
+        }
+    }
+}
+
+fn create_navigation_grid(mut commands: Commands, terrain: Res<Terrain>) {
+    commands.insert_resource(NavigationGrid::new(terrain.size()));
+}
+
+fn reserve_footprints(
+    grid: Option<ResMut<NavigationGrid>>,
+    footprints: Query<(Entity, &Transform, &Footprint)>,
+) {
+    let Some(mut grid) = grid else {
+        return;
+    };
+
+    // Looked up every tick instead of read from the removals, which only last for a frame
+    let released: Vec<Entity> = grid
+        .footprints
+        .keys()
+        .copied()
+        .filter(|entity| !footprints.contains(*entity))
+        .collect();
+    for entity in released {
+        grid.release(entity);
+    }
+
+    for (entity, transform, footprint) in &footprints {
+        if !grid.footprints.contains_key(&entity) {
+            grid.reserve(entity, transform.translation, *footprint);
+        }
+    }
+}
@@ -0,0 +1,771 @@
+mod transport;
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checksum::{dump_state, state_hash, StateHistory, StateSnapshot},
+    command::{CommandQueue, IssuedCommand, PlayerCommand},
+    game::GameSetup,
+    ground::Terrain,
+    map::CurrentMap,
+    player::{LocalPlayer, Players},
+    simulation::{SimulationTime, TickEndSet, TickStartSet, TICKS_PER_SECOND},
+    GameState,
+};
+
+pub use self::transport::{LoopbackTransport, Transport, UdpTransport};
+
+/// Lockstep games, where every player simulates every tick with the commands of all players
+pub struct LockstepPlugin;
+
+/// Sends the commands of the local player to the others and joins lockstep games
+pub struct LockstepInputPlugin;
+
+/// Ticks from giving a command to it being applied, the time it has to reach the other players
+pub const DEFAULT_INPUT_DELAY: u64 = 3;
+/// Ticks whose state hash is kept to be compared with the other players, who may be behind
+const KEPT_HASHES: usize = 2 * TICKS_PER_SECOND as usize;
+/// Ticks between two states that are kept whole. After a desync, every player dumps
+/// the first of them from the tick that went out of sync on
+const SNAPSHOT_INTERVAL: u64 = TICKS_PER_SECOND as u64;
+/// Largest piece of a dumped state in one packet, well within a datagram
+const STATE_PART_SIZE: usize = 16 * 1024;
+/// Latest state hashes in every packet, a few in case some packets are lost
+const SENT_HASHES: usize = 4;
+/// Time spent waiting for the other players between two warnings
+const WAITING_WARNING: Duration = Duration::from_secs(5);
+
+/// Starts a lockstep game over UDP. Every player passes the same addresses in the same order
+pub struct JoinLockstep {
+    pub setup: GameSetup,
+    pub local_player: u8,
+    /// Indexed by player, including the local one
+    pub addresses: Vec<SocketAddr>,
+    pub input_delay: u64,
+}
+
+/// Addresses of all players, including the local one, separated by commas
+pub fn parse_addresses(text: &str) -> Result<Vec<SocketAddr>, String> {
+    text.split(',')
+        .map(|address| {
+            address
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid address {}", address))
+        })
+        .collect()
+}
+
+/// Commands a player gives during one tick
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CommandFrame {
+    /// Tick the commands are applied in
+    tick: u64,
+    commands: Vec<PlayerCommand>,
+}
+
+/// What the players send each other every tick
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Packet {
+    player: u8,
+    /// Frames of the sender that the receiver has not confirmed yet
+    frames: Vec<CommandFrame>,
+    /// The sender has every frame of the receiver before this tick
+    received_until: u64,
+    /// Latest state hashes of the sender, by tick
+    hashes: Vec<(u64, u64)>,
+    /// Piece of the state the sender dumped after going out of sync
+    state: Option<StatePart>,
+    /// The sender has the whole state the receiver dumped
+    has_state: bool,
+}
+
+/// The dumped states are too large for one packet, they are sent a piece at a time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StatePart {
+    tick: u64,
+    index: usize,
+    count: usize,
+    text: String,
+}
+
+/// What is known about one of the other players
+#[derive(Debug, Default)]
+struct Peer {
+    /// Frames that arrived and have not been applied yet
+    frames: BTreeMap<u64, Vec<PlayerCommand>>,
+    /// Every frame before this tick has arrived
+    received_until: u64,
+    /// The peer has every local frame before this tick
+    confirmed_until: u64,
+    /// Hashes of ticks the local game has not reached yet
+    hashes: BTreeMap<u64, u64>,
+    /// Pieces of the state the peer dumped that have arrived, by index
+    state_parts: BTreeMap<usize, String>,
+    /// State the peer dumped after going out of sync, once all of it has arrived
+    state: Option<StateSnapshot>,
+    /// The peer has the whole state the local game dumped
+    has_local_state: bool,
+}
+
+/// Lockstep game in progress. Local commands are collected into a frame every tick,
+/// which is applied `input_delay` ticks later, and only once the frames of all players are there
+#[derive(Resource)]
+pub struct Lockstep {
+    transport: Box<dyn Transport>,
+    local_player: u8,
+    input_delay: u64,
+    /// Commands given since the last local frame was closed
+    pending: Vec<PlayerCommand>,
+    /// Local frames, kept until every peer has them and they have been applied
+    frames: BTreeMap<u64, Vec<PlayerCommand>>,
+    /// Indexed by player, there is none for the local player
+    peers: Vec<Option<Peer>>,
+    /// Local state hashes of the latest ticks, every tick is compared with the other players
+    history: StateHistory,
+    /// The inputs of the current tick are not all there
+    waiting: bool,
+    /// When to warn that the game is still waiting, real time that never reaches the simulation
+    next_warning: Option<Instant>,
+    /// First tick whose state hash differed between the players
+    desynced_at: Option<u64>,
+    /// Tick whose state is dumped and sent to the other players once the game is there
+    dump_at: Option<u64>,
+    /// Local state that was dumped, with the pieces it is sent in
+    dumped: Option<(StateSnapshot, Vec<String>)>,
+    /// The pieces go out in turn, one in every packet
+    next_part: usize,
+}
+
+impl Lockstep {
+    pub fn new(
+        transport: impl Transport,
+        local_player: u8,
+        players: usize,
+        input_delay: u64,
+    ) -> Self {
+        // Nobody gives commands for the first ticks, they all count as received
+        let peers = (0..players)
+            .map(|player| {
+                (player != local_player as usize).then(|| Peer {
+                    received_until: input_delay,
+                    confirmed_until: input_delay,
+                    ..default()
+                })
+            })
+            .collect();
+
+        Self {
+            transport: Box::new(transport),
+            local_player,
+            input_delay,
+            pending: Vec::new(),
+            frames: BTreeMap::new(),
+            peers,
+            history: StateHistory::new(KEPT_HASHES, SNAPSHOT_INTERVAL),
+            waiting: false,
+            next_warning: None,
+            desynced_at: None,
+            dump_at: None,
+            dumped: None,
+            next_part: 0,
+        }
+    }
+
+    /// Whether the game is held back until the other players' inputs arrive
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    pub fn desynced_at(&self) -> Option<u64> {
+        self.desynced_at
+    }
+
+    /// Command of the local player, it goes into the frame the next tick closes
+    pub fn issue(&mut self, command: PlayerCommand) {
+        self.pending.push(command);
+    }
+
+    /// Takes in what the other players sent and sends them what they have not confirmed.
+    /// Ticks do this on their own, a game that stopped ticking calls it to let the others finish
+    pub fn poll(&mut self) {
+        self.receive();
+        self.send();
+    }
+
+    fn receive(&mut self) {
+        for bytes in self.transport.receive() {
+            let packet: Packet = match serde_yaml::from_slice(&bytes) {
+                Ok(packet) => packet,
+                Err(error) => {
+                    warn!("unreadable lockstep packet: {}", error);
+                    continue;
+                }
+            };
+            let Some(Some(peer)) = self.peers.get_mut(packet.player as usize) else {
+                continue;
+            };
+
+            peer.confirmed_until = peer.confirmed_until.max(packet.received_until);
+            for frame in packet.frames {
+                if frame.tick >= peer.received_until {
+                    peer.frames.entry(frame.tick).or_insert(frame.commands);
+                }
+            }
+            while peer.frames.contains_key(&peer.received_until) {
+                peer.received_until += 1;
+            }
+
+            peer.has_local_state |= packet.has_state;
+            if let Some(part) = packet.state {
+                peer.receive_state(packet.player, part);
+            }
+
+            let latest = self
+                .history
+                .latest_hashes(1)
+                .next()
+                .map_or(0, |(tick, _)| tick);
+            let mut compared = Vec::new();
+            for (tick, hash) in packet.hashes {
+                match self.history.hash(tick) {
+                    Some(local) => compared.push((tick, local, hash)),
+                    None if tick > latest => {
+                        peer.hashes.insert(tick, hash);
+                    }
+                    // Older than any hash that is still kept
+                    None => {}
+                }
+            }
+            for (tick, local, remote) in compared {
+                self.compare_hashes(packet.player, tick, local, remote);
+            }
+        }
+    }
+
+    fn send(&mut self) {
+        let hashes: Vec<(u64, u64)> = self.history.latest_hashes(SENT_HASHES).collect();
+        let part = self.dumped.as_ref().map(|(state, parts)| {
+            let index = self.next_part % parts.len();
+            StatePart {
+                tick: state.tick,
+                index,
+                count: parts.len(),
+                text: parts[index].clone(),
+            }
+        });
+        self.next_part += 1;
+
+        for (player, peer) in self.peers.iter().enumerate() {
+            let Some(peer) = peer else {
+                continue;
+            };
+
+            let packet = Packet {
+                player: self.local_player,
+                frames: self
+                    .frames
+                    .range(peer.confirmed_until..)
+                    .map(|(tick, commands)| CommandFrame {
+                        tick: *tick,
+                        commands: commands.clone(),
+                    })
+                    .collect(),
+                received_until: peer.received_until,
+                hashes: hashes.clone(),
+                state: part.clone().filter(|_| !peer.has_local_state),
+                has_state: peer.state.is_some(),
+            };
+            match serde_yaml::to_string(&packet) {
+                Ok(text) => self.transport.send(player as u8, text.as_bytes()),
+                Err(error) => error!("lockstep packet could not be written: {}", error),
+            }
+        }
+    }
+
+    /// Whether every player's frame for the tick has arrived
+    fn has_frames(&self, tick: u64) -> bool {
+        tick < self.input_delay
+            || self
+                .peers
+                .iter()
+                .flatten()
+                .all(|peer| peer.received_until > tick)
+    }
+
+    /// Players whose frame for the tick is missing
+    fn missing_players(&self, tick: u64) -> Vec<u8> {
+        self.peers
+            .iter()
+            .enumerate()
+            .filter_map(|(player, peer)| {
+                peer.as_ref()
+                    .filter(|peer| peer.received_until <= tick)
+                    .map(|_| player as u8)
+            })
+            .collect()
+    }
+
+    /// Commands of every player for the tick, in the order of the players
+    fn take_frame(&mut self, tick: u64) -> Vec<IssuedCommand> {
+        let mut commands = Vec::new();
+        for player in 0..self.peers.len() {
+            let frame = match &mut self.peers[player] {
+                Some(peer) => peer.frames.remove(&tick),
+                // Kept until the others have confirmed it
+                None => self.frames.get(&tick).cloned(),
+            };
+            commands.extend(frame.into_iter().flatten().map(|command| IssuedCommand {
+                player: player as u8,
+                tick,
+                command,
+            }));
+        }
+        commands
+    }
+
+    /// Closes the local frame that is applied `input_delay` ticks after this one
+    fn close_frame(&mut self, tick: u64) {
+        let commands = std::mem::take(&mut self.pending);
+        self.frames.insert(tick + self.input_delay, commands);
+    }
+
+    /// Drops the local frames that have been applied and that every peer has
+    fn forget_frames(&mut self, tick: u64) {
+        let confirmed = self
+            .peers
+            .iter()
+            .flatten()
+            .map(|peer| peer.confirmed_until)
+            .min()
+            .unwrap_or(u64::MAX);
+        let kept_from = confirmed.min(tick);
+        self.frames.retain(|frame, _| *frame >= kept_from);
+    }
+
+    /// States dumped after the game went out of sync by player, the local one
+    /// and those of the other players that have arrived
+    pub fn desync_states(&self) -> Vec<(u8, &StateSnapshot)> {
+        let local = self
+            .dumped
+            .as_ref()
+            .map(|(state, _)| (self.local_player, state));
+        let peers = self.peers.iter().enumerate().filter_map(|(player, peer)| {
+            let state = peer.as_ref()?.state.as_ref()?;
+            Some((player as u8, state))
+        });
+        let mut states: Vec<(u8, &StateSnapshot)> = local.into_iter().chain(peers).collect();
+        states.sort_by_key(|(player, _)| *player);
+        states
+    }
+
+    /// Hashes are kept for every tick, whole states only for some of them
+    fn keeps_snapshot(&self, tick: u64) -> bool {
+        self.history.keeps_snapshot(tick)
+    }
+
+    fn record_snapshot(&mut self, snapshot: StateSnapshot) {
+        let tick = snapshot.tick;
+        let hash = self.history.push(snapshot);
+        self.compare_with_peers(tick, hash);
+    }
+
+    fn record_hash(&mut self, tick: u64, hash: u64) {
+        self.history.push_hash(tick, hash);
+        self.compare_with_peers(tick, hash);
+    }
+
+    fn compare_with_peers(&mut self, tick: u64, hash: u64) {
+        let mut compared = Vec::new();
+        for (player, peer) in self.peers.iter_mut().enumerate() {
+            let Some(peer) = peer else {
+                continue;
+            };
+            if let Some(remote) = peer.hashes.remove(&tick) {
+                compared.push((player as u8, remote));
+            }
+            peer.hashes.retain(|remote_tick, _| *remote_tick > tick);
+        }
+        for (player, remote) in compared {
+            self.compare_hashes(player, tick, hash, remote);
+        }
+        self.dump_state();
+    }
+
+    fn compare_hashes(&mut self, player: u8, tick: u64, local: u64, remote: u64) {
+        if local != remote && self.desynced_at.is_none() {
+            error!(
+                "out of sync with player {} at tick {}, state {:016x} against {:016x}",
+                player + 1,
+                tick,
+                local,
+                remote
+            );
+            self.desynced_at = Some(tick);
+            // The other players pick the same tick, they found the same one out of sync
+            self.dump_at = Some(tick.next_multiple_of(SNAPSHOT_INTERVAL));
+        }
+    }
+
+    /// Writes the local state of the tick picked after a desync and sends it to the other
+    /// players, who write it next to theirs. Waits until the game has reached the tick
+    fn dump_state(&mut self) {
+        let Some(tick) = self.dump_at else {
+            return;
+        };
+        let Some(state) = self.history.get(tick) else {
+            if self.history.hash(tick).is_some() {
+                error!("state of tick {} is no longer kept", tick);
+                self.dump_at = None;
+            }
+            return;
+        };
+        self.dump_at = None;
+
+        let name = format!("tick-{}-player-{}", tick, self.local_player + 1);
+        match dump_state(state, &name) {
+            Ok(path) => error!(
+                "state written to {}, `--headless --diff` compares it with the other player's",
+                path.display()
+            ),
+            Err(error) => error!("state could not be written: {}", error),
+        }
+        match state.to_yaml() {
+            Ok(text) => self.dumped = Some((state.clone(), split_text(&text, STATE_PART_SIZE))),
+            Err(error) => error!("state could not be sent: {}", error),
+        }
+    }
+}
+
+impl Peer {
+    /// Keeps a piece of the state the peer dumped, and writes the state next to the local one
+    /// once all of it is there
+    fn receive_state(&mut self, player: u8, part: StatePart) {
+        if self.state.is_some() {
+            return;
+        }
+        self.state_parts.insert(part.index, part.text);
+        if self.state_parts.len() < part.count {
+            return;
+        }
+
+        let text: String = std::mem::take(&mut self.state_parts)
+            .into_values()
+            .collect();
+        let state = match StateSnapshot::from_yaml(&text) {
+            Ok(state) => state,
+            Err(error) => {
+                warn!("state of player {} is unreadable: {}", player + 1, error);
+                return;
+            }
+        };
+        let name = format!("tick-{}-player-{}", part.tick, player + 1);
+        match dump_state(&state, &name) {
+            Ok(path) => error!(
+                "state of player {} written to {}",
+                player + 1,
+                path.display()
+            ),
+            Err(error) => error!("state could not be written: {}", error),
+        }
+        self.state = Some(state);
+    }
+}
+
+impl Plugin for LockstepPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            exchange_frames
+                .run_if(resource_exists::<Lockstep>())
+                .run_if(in_state(GameState::InGame))
+                .run_if(resource_exists::<CurrentMap>())
+                .run_if(resource_exists::<Terrain>())
+                .in_set(TickStartSet)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(
+            record_state
+                .run_if(resource_exists::<Lockstep>())
+                .in_set(TickEndSet)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(leave_lockstep.in_schedule(OnEnter(GameState::MainMenu)));
+    }
+}
+
+impl Plugin for LockstepInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<JoinLockstep>()
+            .add_system(join_lockstep.in_set(OnUpdate(GameState::MainMenu)))
+            .add_system(
+                issue_local_commands
+                    .run_if(resource_exists::<Lockstep>())
+                    .in_base_set(CoreSet::PostUpdate),
+            );
+    }
+}
+
+/// Lets the tick run only once every player's frame for it is there.
+/// A tick that runs queues the commands of all players and closes the next local frame
+fn exchange_frames(
+    mut lockstep: ResMut<Lockstep>,
+    mut queue: ResMut<CommandQueue>,
+    time: Res<SimulationTime>,
+) {
+    let tick = time.tick();
+    lockstep.receive();
+
+    lockstep.waiting = !lockstep.has_frames(tick);
+    if lockstep.waiting {
+        let now = Instant::now();
+        let next_warning = *lockstep.next_warning.get_or_insert(now + WAITING_WARNING);
+        if now >= next_warning {
+            lockstep.next_warning = Some(now + WAITING_WARNING);
+            let missing: Vec<String> = lockstep
+                .missing_players(tick)
+                .iter()
+                .map(|player| (player + 1).to_string())
+                .collect();
+            warn!("waiting for player {} at tick {}", missing.join(", "), tick);
+        }
+    } else {
+        lockstep.next_warning = None;
+        for command in lockstep.take_frame(tick) {
+            queue.push(command);
+        }
+        lockstep.close_frame(tick);
+    }
+
+    lockstep.send();
+    lockstep.forget_frames(tick);
+}
+
+/// Hashes the state of the tick that just ran, and keeps all of it every few ticks
+fn record_state(world: &mut World) {
+    let tick = world.resource::<SimulationTime>().tick();
+    if world.resource::<Lockstep>().keeps_snapshot(tick) {
+        let snapshot = StateSnapshot::capture(world);
+        world.resource_mut::<Lockstep>().record_snapshot(snapshot);
+    } else {
+        let hash = state_hash(world);
+        world.resource_mut::<Lockstep>().record_hash(tick, hash);
+    }
+}
+
+/// Pieces of at most the given number of bytes, cut between characters
+fn split_text(text: &str, size: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(size);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (part, next) = rest.split_at(end);
+        parts.push(part.to_string());
+        rest = next;
+    }
+    parts
+}
+
+fn issue_local_commands(mut reader: EventReader<PlayerCommand>, mut lockstep: ResMut<Lockstep>) {
+    for command in reader.iter() {
+        lockstep.issue(command.clone());
+    }
+}
+
+fn join_lockstep(
+    mut commands: Commands,
+    mut reader: EventReader<JoinLockstep>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(join) = reader.iter().last() else {
+        return;
+    };
+
+    let transport = match UdpTransport::bind(join.local_player, join.addresses.clone()) {
+        Ok(transport) => transport,
+        Err(error) => {
+            error!("lockstep game could not be joined: {}", error);
+            return;
+        }
+    };
+
+    let players = join.addresses.len();
+    info!(
+        "joined lockstep game as player {} of {}",
+        join.local_player + 1,
+        players
+    );
+    commands.insert_resource(join.setup.clone());
+    commands.insert_resource(Players::humans(players));
+    commands.insert_resource(LocalPlayer(join.local_player));
+    commands.insert_resource(Lockstep::new(
+        transport,
+        join.local_player,
+        players,
+        join.input_delay,
+    ));
+    next_state.set(GameState::InGame);
+}
+
+fn leave_lockstep(mut commands: Commands) {
+    commands.remove_resource::<Lockstep>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checksum::EntryDiff, economy::STARTING_RESOURCES, headless::HeadlessGame, map::DEFAULT_MAP,
+        simulation::StableId,
+    };
+
+    const INPUT_DELAY: u64 = 2;
+
+    /// Both players of a game over the loopback, each in its own world
+    fn start() -> Vec<HeadlessGame> {
+        let setup = GameSetup {
+            map: DEFAULT_MAP.to_string(),
+            starting_resources: STARTING_RESOURCES,
+            seed: 7,
+        };
+        LoopbackTransport::connect(2)
+            .into_iter()
+            .enumerate()
+            .map(|(player, transport)| {
+                let player = player as u8;
+                let mut game = HeadlessGame::start(setup.clone(), Players::humans(2))
+                    .expect("the default map loads");
+                game.app
+                    .insert_resource(LocalPlayer(player))
+                    .insert_resource(Lockstep::new(transport, player, 2, INPUT_DELAY));
+                game
+            })
+            .collect()
+    }
+
+    fn lockstep(game: &mut HeadlessGame) -> Mut<'_, Lockstep> {
+        game.app.world.resource_mut::<Lockstep>()
+    }
+
+    fn tick_all(games: &mut [HeadlessGame], ticks: u64) {
+        for _ in 0..ticks {
+            for game in games.iter_mut() {
+                game.tick();
+            }
+        }
+    }
+
+    fn move_soldier(player: u8) -> PlayerCommand {
+        PlayerCommand::Move {
+            units: vec![StableId(if player == 0 { 5 } else { 8 })],
+            position: Vec3::new(10.0, 0.0, 10.0),
+            queued: false,
+        }
+    }
+
+    #[test]
+    fn waits_for_the_frames_of_the_other_player() {
+        let mut games = start();
+
+        // The first ticks have no frames to wait for
+        for _ in 0..INPUT_DELAY + 3 {
+            games[0].tick();
+        }
+        assert_eq!(games[0].ticks(), INPUT_DELAY);
+        assert!(lockstep(&mut games[0]).is_waiting());
+
+        // The other player's first tick closes the frame that was missing
+        games[1].tick();
+        games[0].tick();
+        assert_eq!(games[0].ticks(), INPUT_DELAY + 1);
+        games[0].tick();
+        assert_eq!(games[0].ticks(), INPUT_DELAY + 1);
+    }
+
+    #[test]
+    fn players_reach_the_same_states() {
+        let mut games = start();
+        for (player, game) in games.iter_mut().enumerate() {
+            lockstep(game).issue(move_soldier(player as u8));
+        }
+        tick_all(&mut games, 100);
+
+        for game in &mut games {
+            assert_eq!(game.ticks(), 100);
+            assert_eq!(lockstep(game).desynced_at(), None);
+        }
+        assert_eq!(games[0].state_hash(), games[1].state_hash());
+    }
+
+    #[test]
+    fn command_only_one_player_applies_is_found() {
+        let mut games = start();
+        tick_all(&mut games, 20);
+
+        // Skips the lockstep, so the other player never sees it
+        let tick = games[0].ticks();
+        games[0]
+            .app
+            .world
+            .resource_mut::<CommandQueue>()
+            .push(IssuedCommand {
+                player: 0,
+                tick,
+                command: move_soldier(0),
+            });
+        tick_all(&mut games, 10);
+
+        for game in &mut games {
+            assert_eq!(lockstep(game).desynced_at(), Some(tick + 1));
+        }
+    }
+
+    #[test]
+    fn players_send_each_other_their_states_after_a_desync() {
+        let mut games = start();
+        tick_all(&mut games, 20);
+
+        let tick = games[0].ticks();
+        games[0]
+            .app
+            .world
+            .resource_mut::<CommandQueue>()
+            .push(IssuedCommand {
+                player: 0,
+                tick,
+                command: move_soldier(0),
+            });
+        tick_all(&mut games, 2 * SNAPSHOT_INTERVAL);
+
+        let dumped_at = (tick + 1).next_multiple_of(SNAPSHOT_INTERVAL);
+        for game in &mut games {
+            let lockstep = lockstep(game);
+            let states = lockstep.desync_states();
+            assert_eq!(states.len(), 2);
+            let (first, second) = (states[0].1, states[1].1);
+            assert_eq!((first.tick, second.tick), (dumped_at, dumped_at));
+            let diff = first
+                .diff(second)
+                .expect("the soldier moved on one side only");
+            assert!(diff.entries.iter().any(|entry| matches!(
+                entry,
+                EntryDiff::Changed { key, .. } if key == "#5"
+            )));
+        }
+    }
+
+    #[test]
+    fn text_is_split_between_characters() {
+        let parts = split_text("añb", 2);
+        assert_eq!(parts, ["a", "ñ", "b"]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+};
+
+/// Largest payload of a UDP datagram
+const MAX_PACKET_SIZE: usize = 65_507;
+
+/// Carries packets between the players of a lockstep game. Packets may be lost or
+/// arrive out of order, `Lockstep` repeats everything until it is confirmed
+pub trait Transport: Send + Sync + 'static {
+    fn send(&mut self, player: u8, packet: &[u8]);
+
+    /// Packets that arrived since the last call
+    fn receive(&mut self) -> Vec<Vec<u8>>;
+}
+
+/// Every player on their own UDP socket, on other machines or on this one
+pub struct UdpTransport {
+    socket: UdpSocket,
+    /// Indexed by player, including the local one
+    addresses: Vec<SocketAddr>,
+}
+
+/// Players in the same process, packets arrive in order and are never lost
+pub struct LoopbackTransport {
+    player: u8,
+    /// Packets waiting for each player
+    inboxes: Arc<Vec<Mutex<Vec<Vec<u8>>>>>,
+}
+
+impl UdpTransport {
+    /// Listens on the address of the local player
+    pub fn bind(local_player: u8, addresses: Vec<SocketAddr>) -> Result<Self, String> {
+        let address = addresses
+            .get(local_player as usize)
+            .ok_or_else(|| format!("there is no address for player {}", local_player + 1))?;
+        let socket = UdpSocket::bind(address)
+            .and_then(|socket| {
+                socket.set_nonblocking(true)?;
+                Ok(socket)
+            })
+            .map_err(|error| format!("{}: {}", address, error))?;
+
+        Ok(Self { socket, addresses })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, player: u8, packet: &[u8]) {
+        if let Some(address) = self.addresses.get(player as usize) {
+            // Fails while the other player is not listening yet, the packet is sent again anyway
+            let _ = self.socket.send_to(packet, address);
+        }
+    }
+
+    fn receive(&mut self) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, _)) => packets.push(buffer[..size].to_vec()),
+                // Some systems report packets that could not be delivered earlier here
+                Err(error) if error.kind() == ErrorKind::ConnectionReset => continue,
+                Err(_) => break,
+            }
+        }
+        packets
+    }
+}
+
+impl LoopbackTransport {
+    /// Transports for the given number of players, connected to each other
+    pub fn connect(players: usize) -> Vec<Self> {
+        let inboxes: Arc<Vec<_>> = Arc::new((0..players).map(|_| Mutex::default()).collect());
+        (0..players)
+            .map(|player| Self {
+                player: player as u8,
+                inboxes: inboxes.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn send(&mut self, player: u8, packet: &[u8]) {
+        if let Some(inbox) = self.inboxes.get(player as usize) {
+            inbox.lock().unwrap().push(packet.to_vec());
+        }
+    }
+
+    fn receive(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.inboxes[self.player as usize].lock().unwrap())
+    }
+}
@@ -1,58 +1,372 @@
 use std::collections::VecDeque;
 
 use bevy::{prelude::*, window::PrimaryWindow};
-use bevy_rapier3d::prelude::Collider;
+use bevy_rapier3d::prelude::{Collider, RapierContext};
 
 use crate::{
-    cursor::get_point_on_ground,
+    abilities::{Abilities, AbilityTarget, AbilityTargeting},
+    buildings::{construct, Construction},
+    combat::{attack, Weapon},
+    command::PlayerCommand,
+    cursor::{get_entity_under_cursor, get_point_on_ground},
+    economy::{gather_from, ResourceNode, Worker},
+    fog::VisibilityGrid,
     ground::Ground,
+    health::Health,
+    hotkeys::letter_key_code,
+    hud::is_cursor_over_hud,
+    navigation::Footprint,
+    overlays::{MarkerKind, OrderMarker},
+    player::{LocalPlayer, Owner, Players},
     selection::Selectable,
+    simulation::{StableId, StepSet},
+    snapshot::SnapshotApp,
     units::{Unit, UnitState},
     GameState,
 };
 
 pub struct OrderPlugin;
 
-#[derive(Debug, Reflect, FromReflect)]
+/// Move and stop orders from the mouse and keyboard
+pub struct OrderInputPlugin;
+
+/// Letter that stops the selected units
+pub const STOP_HOTKEY: char = 'x';
+
+#[derive(Debug, Clone, Reflect, FromReflect)]
 pub enum Order {
     Move(Vec3),
+    /// Harvest the resource node until it runs out, workers only
+    Gather(Entity),
+    /// Construct the building site until it is finished, workers only
+    Build(Entity),
+    /// Chase the target and attack it until it dies, units without a weapon only follow it
+    Attack(Entity),
+    /// Walk into range of the target and use the ability on it
+    Cast {
+        ability: String,
+        target: AbilityTarget,
+    },
+}
+
+/// Orders of a unit, carried out one after another whenever it becomes idle
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Orders {
+    queue: VecDeque<Order>,
+    /// The first order replaced what the unit was doing and starts right away
+    interrupt: bool,
 }
 
-#[derive(Component, Deref, DerefMut, Default)]
-pub struct Orders(VecDeque<Order>);
+impl Orders {
+    /// Drops the queued orders and starts `order` right away
+    pub fn replace(&mut self, order: Order) {
+        self.queue.clear();
+        self.queue.push_back(order);
+        self.interrupt = true;
+    }
+
+    /// Carries out `order` once the unit is done with the orders before it
+    pub fn queue(&mut self, order: Order) {
+        self.queue.push_back(order);
+    }
+
+    /// Queues the order while Shift is held, replaces the current ones otherwise
+    pub fn issue(&mut self, order: Order, queued: bool) {
+        if queued {
+            self.queue(order)
+        } else {
+            self.replace(order)
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.interrupt = false;
+    }
+
+    /// Orders that have not been started yet
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.queue.iter()
+    }
+
+    fn next(&mut self, is_idle: bool) -> Option<Order> {
+        if !is_idle && !self.interrupt {
+            return None;
+        }
+
+        self.interrupt = false;
+        self.queue.pop_front()
+    }
+}
+
+/// Whether new orders are added to the queue instead of replacing it
+pub fn is_queueing(input: &Input<KeyCode>) -> bool {
+    input.any_pressed([KeyCode::LShift, KeyCode::RShift])
+}
+
+/// Drops the orders of the local player's selected units and lets them stand still
+pub struct StopUnits;
 
 impl Plugin for OrderPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems((send_move_order, handle_orders).in_set(OnUpdate(GameState::InGame)));
+        app.add_snapshot_component::<Orders>()
+            .register_type::<Order>()
+            .register_type::<VecDeque<Order>>()
+            .register_type::<Option<Entity>>()
+            .register_type::<Option<Vec3>>()
+            .add_system(
+                handle_orders
+                    .in_set(StepSet::Orders)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+impl Plugin for OrderInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StopUnits>().add_systems(
+            (
+                stop_from_hotkeys,
+                stop_units,
+                send_move_order
+                    .run_if(any_with_component::<Ground>())
+                    .run_if(not(is_cursor_over_hud))
+                    // Right clicks cancel targeting instead
+                    .run_if(not(resource_exists::<AbilityTargeting>())),
+            )
+                .in_set(OnUpdate(GameState::InGame)),
+        );
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn send_move_order(
     window: Query<(&Window, With<PrimaryWindow>)>,
-    mut units: Query<(&Selectable, &mut Orders)>,
+    units: Query<(&StableId, &Selectable, &Owner, With<Orders>)>,
     camera: Query<(&Camera, &GlobalTransform, With<Camera3d>)>,
     ground: Query<(&Collider, With<Ground>)>,
+    resource_nodes: Query<(Entity, With<ResourceNode>)>,
+    sites: Query<(&Owner, With<Construction>)>,
+    positions: Query<(&StableId, &Transform)>,
+    targets: Query<(&Owner, &Transform, With<Health>)>,
+    rapier_context: Res<RapierContext>,
+    visibility_grid: Option<Res<VisibilityGrid>>,
     input: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+    players: Res<Players>,
+    mut writer: EventWriter<PlayerCommand>,
+    mut markers: EventWriter<OrderMarker>,
 ) {
+    if !input.just_pressed(MouseButton::Right) {
+        return;
+    }
+
     let (window, _) = window.single();
     let (camera, camera_transform, _) = camera.single();
     let (ground_collider, _) = ground.single();
-    for (selectable, mut orders) in &mut units {
-        if selectable.is_selected && input.just_pressed(MouseButton::Right) {
-            if let Some(target) =
-                get_point_on_ground(window, camera, camera_transform, ground_collider)
-            {
-                orders.push_back(Order::Move(target))
-            }
+
+    let target = get_entity_under_cursor(window, camera, camera_transform, &rapier_context);
+    let is_own_site = |entity: Entity| {
+        sites
+            .get(entity)
+            .map(|(owner, _)| owner.0 == local_player.0)
+            .unwrap_or(false)
+    };
+    // Enemies hidden by the fog can not be targeted
+    let is_visible_enemy = |entity: Entity| {
+        targets
+            .get(entity)
+            .map(|(owner, transform, _)| {
+                !players.are_allies(*owner, Owner(local_player.0))
+                    && visibility_grid
+                        .as_ref()
+                        .is_none_or(|grid| grid.is_visible(local_player.0, transform.translation))
+            })
+            .unwrap_or(false)
+    };
+
+    let is_visible_node = |entity: Entity| {
+        positions.get(entity).is_ok_and(|(_, transform)| {
+            visibility_grid
+                .as_ref()
+                .is_none_or(|grid| grid.is_visible(local_player.0, transform.translation))
+        })
+    };
+
+    let order = match target {
+        Some(node) if resource_nodes.contains(node) && is_visible_node(node) => Order::Gather(node),
+        Some(site) if is_own_site(site) => Order::Build(site),
+        Some(enemy) if is_visible_enemy(enemy) => Order::Attack(enemy),
+        _ => match get_point_on_ground(window, camera, camera_transform, ground_collider) {
+            Some(target) => Order::Move(target),
+            None => return,
+        },
+    };
+
+    let selected: Vec<StableId> = units
+        .iter()
+        .filter(|(_, selectable, owner, _)| selectable.is_selected && owner.0 == local_player.0)
+        .map(|(id, ..)| *id)
+        .collect();
+    if selected.is_empty() {
+        return;
+    }
+
+    let (units, queued) = (selected, is_queueing(&keys));
+    let command = match order {
+        Order::Move(position) => Some(PlayerCommand::Move {
+            units,
+            position,
+            queued,
+        }),
+        Order::Gather(node) => positions
+            .get(node)
+            .ok()
+            .map(|(node, _)| PlayerCommand::Gather {
+                units,
+                node: *node,
+                queued,
+            }),
+        Order::Build(site) => positions
+            .get(site)
+            .ok()
+            .map(|(site, _)| PlayerCommand::Construct {
+                units,
+                site: *site,
+                queued,
+            }),
+        Order::Attack(target) => {
+            positions
+                .get(target)
+                .ok()
+                .map(|(target, _)| PlayerCommand::Attack {
+                    units,
+                    target: *target,
+                    queued,
+                })
         }
+        Order::Cast { .. } => None,
+    };
+    let Some(command) = command else {
+        return;
+    };
+    writer.send(command);
+
+    let marker = match order {
+        Order::Move(position) => Some((MarkerKind::Move, position)),
+        Order::Attack(target) => targets
+            .get(target)
+            .ok()
+            .map(|(_, transform, _)| (MarkerKind::Attack, transform.translation)),
+        Order::Gather(target) | Order::Build(target) => positions
+            .get(target)
+            .ok()
+            .map(|(_, transform)| (MarkerKind::Gather, transform.translation)),
+        Order::Cast { .. } => None,
+    };
+    if let Some((kind, position)) = marker {
+        markers.send(OrderMarker { kind, position });
     }
 }
 
-fn handle_orders(mut orders: Query<(&mut Orders, &mut Unit)>) {
-    for (mut orders, mut unit) in &mut orders {
-        if let Some(order) = orders.pop_front() {
+fn stop_from_hotkeys(input: Res<Input<KeyCode>>, mut writer: EventWriter<StopUnits>) {
+    if letter_key_code(STOP_HOTKEY)
+        .map(|key| input.just_pressed(key))
+        .unwrap_or(false)
+    {
+        writer.send(StopUnits);
+    }
+}
+
+fn stop_units(
+    mut reader: EventReader<StopUnits>,
+    units: Query<(&StableId, &Selectable, &Owner, With<Orders>)>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<PlayerCommand>,
+) {
+    if reader.iter().count() == 0 {
+        return;
+    }
+
+    let units: Vec<StableId> = units
+        .iter()
+        .filter(|(_, selectable, owner, _)| selectable.is_selected && owner.0 == local_player.0)
+        .map(|(id, ..)| *id)
+        .collect();
+    if !units.is_empty() {
+        writer.send(PlayerCommand::Stop { units });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn handle_orders(
+    mut orders: Query<(
+        &mut Orders,
+        &mut Unit,
+        &Transform,
+        Option<&Worker>,
+        Option<&Weapon>,
+        Option<&Abilities>,
+    )>,
+    resource_nodes: Query<(&Transform, With<ResourceNode>)>,
+    sites: Query<(&Transform, &Footprint, With<Construction>)>,
+    targets: Query<(&Transform, With<Health>)>,
+) {
+    for (mut orders, mut unit, transform, worker, weapon, abilities) in &mut orders {
+        let is_idle = matches!(unit.state, UnitState::Idle);
+        if let Some(order) = orders.next(is_idle) {
             match order {
                 Order::Move(destination) => unit.state = UnitState::Moving(destination),
+                Order::Gather(node) => {
+                    let Ok((node_transform, _)) = resource_nodes.get(node) else {
+                        continue;
+                    };
+
+                    unit.state = match worker {
+                        Some(_) => {
+                            gather_from(node, node_transform.translation, transform.translation)
+                        }
+                        // Other units only walk up to the node
+                        None => UnitState::Moving(node_transform.translation),
+                    };
+                }
+                Order::Build(site) => {
+                    let (Some(_), Ok((site_transform, footprint, _))) = (worker, sites.get(site))
+                    else {
+                        continue;
+                    };
+
+                    unit.state = construct(
+                        site,
+                        site_transform.translation,
+                        *footprint,
+                        transform.translation,
+                    );
+                }
+                Order::Attack(target) => {
+                    let Ok((target_transform, _)) = targets.get(target) else {
+                        continue;
+                    };
+
+                    unit.state = match weapon {
+                        Some(_) => attack(target, target_transform.translation),
+                        None => UnitState::Moving(target_transform.translation),
+                    };
+                }
+                Order::Cast { ability, target } => {
+                    if !abilities.is_some_and(|abilities| abilities.has(&ability)) {
+                        continue;
+                    }
+
+                    // The unit walks into range once the target position is known
+                    unit.state = UnitState::Casting {
+                        ability,
+                        target,
+                        position: transform.translation,
+                    };
+                }
             }
         }
     }
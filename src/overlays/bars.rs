@@ -0,0 +1,313 @@
+use bevy::{prelude::*, render::primitives::Aabb};
+
+use crate::{
+    abilities::Energy, cursor::HoveredEntity, health::Health, selection::Selectable, GameState,
+};
+
+use super::{despawn_all, on_main_menu};
+
+pub struct StatusBarPlugin;
+
+const BAR_HEIGHT: f32 = 0.08;
+const BAR_SPACING: f32 = 0.03;
+/// Gap between the top of the unit and its health bar
+const BAR_MARGIN: f32 = 0.25;
+/// Keeps bars of small units readable
+const MIN_BAR_WIDTH: f32 = 0.6;
+/// Draws the fill just in front of its background
+const FILL_OFFSET: f32 = 0.001;
+
+const BACKGROUND_COLOR: Color = Color::rgb(0.1, 0.1, 0.1);
+const HEALTHY_COLOR: Color = Color::rgb(0.2, 0.8, 0.2);
+const WOUNDED_COLOR: Color = Color::rgb(0.9, 0.8, 0.1);
+const CRITICAL_COLOR: Color = Color::rgb(0.9, 0.2, 0.1);
+const ENERGY_COLOR: Color = Color::rgb(0.6, 0.3, 0.9);
+
+/// When health and energy bars are shown above units and buildings
+#[derive(Reflect, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BarVisibility {
+    Selected,
+    /// Selected or under the cursor
+    Hovered,
+    /// Selected, under the cursor or missing health
+    #[default]
+    Damaged,
+    Always,
+}
+
+impl BarVisibility {
+    pub fn name(self) -> &'static str {
+        match self {
+            BarVisibility::Selected => "Selected",
+            BarVisibility::Hovered => "Hovered",
+            BarVisibility::Damaged => "Damaged",
+            BarVisibility::Always => "Always",
+        }
+    }
+
+    /// Setting that shows bars for more units, wrapping around to the fewest
+    pub fn next(self) -> Self {
+        match self {
+            BarVisibility::Selected => BarVisibility::Hovered,
+            BarVisibility::Hovered => BarVisibility::Damaged,
+            BarVisibility::Damaged => BarVisibility::Always,
+            BarVisibility::Always => BarVisibility::Selected,
+        }
+    }
+}
+
+#[derive(Resource, Reflect, Default)]
+#[reflect(Resource)]
+pub struct OverlaySettings {
+    pub bars: BarVisibility,
+}
+
+/// Parent of the bars above `target`, turned to face the camera
+#[derive(Component)]
+struct StatusBars {
+    target: Entity,
+    health: Entity,
+    energy: Option<Entity>,
+}
+
+/// Part of a bar that shrinks towards its left end
+#[derive(Component)]
+struct BarFill;
+
+/// Shared by all bars, so that any number of them only needs a handful of assets
+#[derive(Resource)]
+struct BarAssets {
+    quad: Handle<Mesh>,
+    background: Handle<StandardMaterial>,
+    healthy: Handle<StandardMaterial>,
+    wounded: Handle<StandardMaterial>,
+    critical: Handle<StandardMaterial>,
+    energy: Handle<StandardMaterial>,
+}
+
+impl FromWorld for BarAssets {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let mut bar_material = |color: Color| {
+            materials.add(StandardMaterial {
+                base_color: color,
+                unlit: true,
+                ..default()
+            })
+        };
+
+        Self {
+            quad: meshes.add(shape::Quad::new(Vec2::ONE).into()),
+            background: bar_material(BACKGROUND_COLOR),
+            healthy: bar_material(HEALTHY_COLOR),
+            wounded: bar_material(WOUNDED_COLOR),
+            critical: bar_material(CRITICAL_COLOR),
+            energy: bar_material(ENERGY_COLOR),
+        }
+    }
+}
+
+impl BarAssets {
+    fn health_material(&self, fraction: f32) -> &Handle<StandardMaterial> {
+        if fraction > 0.6 {
+            &self.healthy
+        } else if fraction > 0.3 {
+            &self.wounded
+        } else {
+            &self.critical
+        }
+    }
+}
+
+impl Plugin for StatusBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<OverlaySettings>()
+            .init_resource::<OverlaySettings>()
+            .init_resource::<BarAssets>()
+            .add_system(despawn_all::<StatusBars>.in_schedule(on_main_menu()))
+            .add_systems(
+                (spawn_status_bars, place_status_bars, update_fills)
+                    .chain()
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+fn spawn_status_bars(
+    mut commands: Commands,
+    targets: Query<(Entity, Option<&Energy>), Added<Health>>,
+    assets: Res<BarAssets>,
+) {
+    for (target, energy) in &targets {
+        let (health_background, health) = spawn_bar(&mut commands, &assets, 0.0, &assets.healthy);
+        let mut children = vec![health_background, health];
+        let energy = energy.map(|_| {
+            let (background, fill) = spawn_bar(
+                &mut commands,
+                &assets,
+                -(BAR_HEIGHT + BAR_SPACING),
+                &assets.energy,
+            );
+            children.extend([background, fill]);
+            fill
+        });
+
+        commands
+            .spawn((
+                SpatialBundle {
+                    // Shown by `place_status_bars` once it is placed
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                Name::from("Status Bars"),
+                StatusBars {
+                    target,
+                    health,
+                    energy,
+                },
+            ))
+            .push_children(&children);
+    }
+}
+
+/// Spawns the background and the fill of a bar at height `y` within its parent
+fn spawn_bar(
+    commands: &mut Commands,
+    assets: &BarAssets,
+    y: f32,
+    fill: &Handle<StandardMaterial>,
+) -> (Entity, Entity) {
+    let scale = Vec3::new(1.0, BAR_HEIGHT, 1.0);
+    let background = commands
+        .spawn(PbrBundle {
+            mesh: assets.quad.clone(),
+            material: assets.background.clone(),
+            transform: Transform::from_xyz(0.0, y, 0.0).with_scale(scale),
+            ..default()
+        })
+        .id();
+    let fill = commands
+        .spawn((
+            PbrBundle {
+                mesh: assets.quad.clone(),
+                material: fill.clone(),
+                transform: Transform::from_xyz(0.0, y, FILL_OFFSET).with_scale(scale),
+                ..default()
+            },
+            BarFill,
+        ))
+        .id();
+
+    (background, fill)
+}
+
+/// Shows bars according to `OverlaySettings` and keeps them above their target, facing the camera
+#[allow(clippy::type_complexity)]
+fn place_status_bars(
+    mut commands: Commands,
+    mut bars: Query<(Entity, &StatusBars, &mut Transform, &mut Visibility)>,
+    targets: Query<
+        (
+            &Transform,
+            &Visibility,
+            &Health,
+            Option<&Selectable>,
+            Option<&Aabb>,
+        ),
+        Without<StatusBars>,
+    >,
+    camera: Query<(&Transform, With<Camera3d>), Without<StatusBars>>,
+    settings: Res<OverlaySettings>,
+    hovered: Res<HoveredEntity>,
+) {
+    let Ok((camera, _)) = camera.get_single() else {
+        return;
+    };
+
+    for (entity, bars, mut transform, mut visibility) in &mut bars {
+        let Ok((target, target_visibility, health, selectable, aabb)) = targets.get(bars.target)
+        else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        let selected = selectable.is_some_and(|selectable| selectable.is_selected);
+        let shown = *target_visibility != Visibility::Hidden
+            && match settings.bars {
+                BarVisibility::Always => true,
+                level => {
+                    selected
+                        || (level >= BarVisibility::Hovered && hovered.0 == Some(bars.target))
+                        || (level >= BarVisibility::Damaged && health.current < health.max)
+                }
+            };
+
+        let new_visibility = if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+        if !shown {
+            continue;
+        }
+
+        let (top, width) = match aabb {
+            Some(aabb) => (
+                (aabb.center.y + aabb.half_extents.y) * target.scale.y,
+                aabb.half_extents.x * 2.0 * target.scale.x,
+            ),
+            None => (0.0, 0.0),
+        };
+        transform.translation = target.translation + Vec3::Y * (top + BAR_MARGIN);
+        transform.rotation = camera.rotation;
+        transform.scale = Vec3::new(width.max(MIN_BAR_WIDTH), 1.0, 1.0);
+    }
+}
+
+fn update_fills(
+    bars: Query<(&StatusBars, &Visibility)>,
+    mut fills: Query<(&mut Transform, &mut Handle<StandardMaterial>), With<BarFill>>,
+    targets: Query<(&Health, Option<&Energy>)>,
+    assets: Res<BarAssets>,
+) {
+    for (bars, visibility) in &bars {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+        let Ok((health, energy)) = targets.get(bars.target) else {
+            continue;
+        };
+
+        let health_fraction = (health.current / health.max).clamp(0.0, 1.0);
+        if let Ok((mut transform, mut material)) = fills.get_mut(bars.health) {
+            set_fill(&mut transform, health_fraction);
+            let health_material = assets.health_material(health_fraction);
+            if *material != *health_material {
+                *material = health_material.clone();
+            }
+        }
+
+        if let (Some(fill), Some(energy)) = (bars.energy, energy) {
+            if let Ok((mut transform, _)) = fills.get_mut(fill) {
+                set_fill(
+                    &mut transform,
+                    (energy.current / energy.max).clamp(0.0, 1.0),
+                );
+            }
+        }
+    }
+}
+
+/// Shrinks a fill to `fraction` of the bar while keeping its left end in place
+fn set_fill(transform: &mut Transform, fraction: f32) {
+    let x = -(1.0 - fraction) / 2.0;
+    if transform.scale.x != fraction || transform.translation.x != x {
+        transform.scale.x = fraction;
+        transform.translation.x = x;
+    }
+}
@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+
+use crate::{ground::Terrain, GameState};
+
+pub struct OrderMarkerPlugin;
+
+/// How long a marker takes to shrink away
+const MARKER_LIFETIME: f32 = 0.5;
+const MARKER_RADIUS: f32 = 0.4;
+const MARKER_THICKNESS: f32 = 0.05;
+const RALLY_FLAG_HEIGHT: f32 = 1.2;
+/// Lifts markers above the terrain so that they are not hidden by it
+const MARKER_HEIGHT: f32 = 0.05;
+
+const MOVE_COLOR: Color = Color::rgb(0.3, 0.9, 0.3);
+const ATTACK_COLOR: Color = Color::rgb(0.95, 0.2, 0.15);
+const GATHER_COLOR: Color = Color::rgb(0.95, 0.8, 0.2);
+const RALLY_COLOR: Color = Color::rgb(0.3, 0.6, 1.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerKind {
+    Move,
+    Attack,
+    /// Gathering and constructing
+    Gather,
+    Rally,
+}
+
+/// Shows where an order that was just issued goes
+pub struct OrderMarker {
+    pub kind: MarkerKind,
+    pub position: Vec3,
+}
+
+/// Shrinks until it disappears
+#[derive(Component)]
+struct Marker(Timer);
+
+#[derive(Resource)]
+struct MarkerAssets {
+    ring: Handle<Mesh>,
+    flag: Handle<Mesh>,
+    move_material: Handle<StandardMaterial>,
+    attack: Handle<StandardMaterial>,
+    gather: Handle<StandardMaterial>,
+    rally: Handle<StandardMaterial>,
+}
+
+impl FromWorld for MarkerAssets {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let mut marker_material = |color: Color| {
+            materials.add(StandardMaterial {
+                base_color: color,
+                unlit: true,
+                ..default()
+            })
+        };
+
+        Self {
+            ring: meshes.add(
+                shape::Torus {
+                    radius: MARKER_RADIUS,
+                    ring_radius: MARKER_THICKNESS,
+                    subdivisions_segments: 24,
+                    subdivisions_sides: 4,
+                }
+                .into(),
+            ),
+            flag: meshes.add(
+                shape::Cylinder {
+                    radius: MARKER_THICKNESS,
+                    height: RALLY_FLAG_HEIGHT,
+                    ..default()
+                }
+                .into(),
+            ),
+            move_material: marker_material(MOVE_COLOR),
+            attack: marker_material(ATTACK_COLOR),
+            gather: marker_material(GATHER_COLOR),
+            rally: marker_material(RALLY_COLOR),
+        }
+    }
+}
+
+impl Plugin for OrderMarkerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<OrderMarker>()
+            .init_resource::<MarkerAssets>()
+            .add_systems(
+                (spawn_markers, shrink_markers)
+                    .chain()
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+fn spawn_markers(
+    mut commands: Commands,
+    mut reader: EventReader<OrderMarker>,
+    assets: Res<MarkerAssets>,
+    terrain: Option<Res<Terrain>>,
+) {
+    for marker in reader.iter() {
+        let position = marker.position;
+        let ground = terrain.as_ref().map_or(position.y, |terrain| {
+            terrain.height_at(position.x, position.z)
+        });
+
+        let (mesh, material, height) = match marker.kind {
+            MarkerKind::Move => (&assets.ring, &assets.move_material, 0.0),
+            MarkerKind::Attack => (&assets.ring, &assets.attack, 0.0),
+            MarkerKind::Gather => (&assets.ring, &assets.gather, 0.0),
+            // Stands up from the ground, so that it is told apart from unit orders
+            MarkerKind::Rally => (&assets.flag, &assets.rally, RALLY_FLAG_HEIGHT / 2.0),
+        };
+
+        commands.spawn((
+            PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_xyz(
+                    position.x,
+                    ground + height + MARKER_HEIGHT,
+                    position.z,
+                ),
+                ..default()
+            },
+            Name::from("Order Marker"),
+            Marker(Timer::from_seconds(MARKER_LIFETIME, TimerMode::Once)),
+        ));
+    }
+}
+
+fn shrink_markers(
+    mut commands: Commands,
+    mut markers: Query<(Entity, &mut Marker, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (entity, mut marker, mut transform) in &mut markers {
+        marker.0.tick(time.delta());
+        if marker.0.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        transform.scale = Vec3::splat(marker.0.percent_left());
+    }
+}
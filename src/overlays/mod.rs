@@ -0,0 +1,40 @@
+mod bars;
+mod markers;
+mod rings;
+mod waypoints;
+
+use bevy::prelude::*;
+
+use crate::GameState;
+
+use self::{
+    bars::StatusBarPlugin, markers::OrderMarkerPlugin, rings::SelectionRingPlugin,
+    waypoints::WaypointPlugin,
+};
+
+pub use self::{
+    bars::OverlaySettings,
+    markers::{MarkerKind, OrderMarker},
+};
+
+pub struct OverlayPlugin;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(SelectionRingPlugin)
+            .add_plugin(StatusBarPlugin)
+            .add_plugin(OrderMarkerPlugin)
+            .add_plugin(WaypointPlugin);
+    }
+}
+
+/// Removes the overlays of the last game when going back to the main menu
+fn despawn_all<T: Component>(mut commands: Commands, entities: Query<Entity, With<T>>) {
+    for entity in &entities {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn on_main_menu() -> OnEnter<GameState> {
+    OnEnter(GameState::MainMenu)
+}
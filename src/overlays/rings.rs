@@ -0,0 +1,232 @@
+use std::f32::consts::{FRAC_1_SQRT_2, SQRT_2};
+
+use bevy::{prelude::*, render::primitives::Aabb, utils::HashSet};
+
+use crate::{
+    cursor::HoveredEntity,
+    ground::Terrain,
+    navigation::Footprint,
+    player::{LocalPlayer, Owner},
+    selection::Selectable,
+    GameState,
+};
+
+use super::{despawn_all, on_main_menu};
+
+pub struct SelectionRingPlugin;
+
+/// Space between the corners of a model and its ring
+const RING_MARGIN: f32 = 0.1;
+/// The hover ring is drawn around the selection ring, so that both can be seen at once
+const HOVER_RING_SCALE: f32 = 1.15;
+/// Thickness of the ring line, relative to its radius
+const RING_THICKNESS: f32 = 0.06;
+/// Lifts the ring above the terrain so that it is not hidden by it
+const RING_HEIGHT: f32 = 0.03;
+
+const OWN_COLOR: Color = Color::rgb(0.2, 0.9, 0.3);
+const ENEMY_COLOR: Color = Color::rgb(0.9, 0.2, 0.2);
+const HOVER_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.6);
+
+/// Circle on the ground around a selected unit or building
+#[derive(Component)]
+struct SelectionRing(Entity);
+
+/// Circle around the hovered entity, separate from the selection
+#[derive(Component)]
+struct HoverRing;
+
+#[derive(Resource)]
+struct RingAssets {
+    mesh: Handle<Mesh>,
+    own: Handle<StandardMaterial>,
+    enemy: Handle<StandardMaterial>,
+    hover: Handle<StandardMaterial>,
+}
+
+impl FromWorld for RingAssets {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let mut ring_material = |color: Color| {
+            materials.add(StandardMaterial {
+                base_color: color,
+                unlit: true,
+                ..default()
+            })
+        };
+
+        Self {
+            mesh: meshes.add(
+                shape::Torus {
+                    radius: 1.0,
+                    ring_radius: RING_THICKNESS,
+                    subdivisions_segments: 32,
+                    subdivisions_sides: 4,
+                }
+                .into(),
+            ),
+            own: ring_material(OWN_COLOR),
+            enemy: ring_material(ENEMY_COLOR),
+            hover: materials.add(StandardMaterial {
+                base_color: HOVER_COLOR,
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+        }
+    }
+}
+
+impl Plugin for SelectionRingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RingAssets>()
+            .add_startup_system(spawn_hover_ring)
+            .add_system(despawn_all::<SelectionRing>.in_schedule(on_main_menu()))
+            .add_system(hide_hover_ring.in_schedule(on_main_menu()))
+            .add_systems(
+                (spawn_rings, follow_selection, follow_hovered)
+                    .chain()
+                    .distributive_run_if(resource_exists::<Terrain>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+/// Radius of a ring around a footprint, or around the model if it has none
+fn ring_radius(footprint: Option<&Footprint>, aabb: Option<&Aabb>) -> Option<f32> {
+    footprint
+        .map(|footprint| footprint.size().max_element() * FRAC_1_SQRT_2)
+        .or_else(|| {
+            aabb.map(|aabb| aabb.half_extents.x.max(aabb.half_extents.z) * SQRT_2 + RING_MARGIN)
+        })
+}
+
+/// Lays a ring flat on the terrain under `position`
+fn place_ring(transform: &mut Transform, position: Vec3, radius: f32, terrain: &Terrain) {
+    transform.translation = Vec3::new(
+        position.x,
+        terrain.height_at(position.x, position.z) + RING_HEIGHT,
+        position.z,
+    );
+    // Flattened, so that it lies on the ground like a decal
+    transform.scale = Vec3::new(radius, RING_THICKNESS, radius);
+}
+
+fn spawn_hover_ring(mut commands: Commands, assets: Res<RingAssets>) {
+    commands.spawn((
+        PbrBundle {
+            mesh: assets.mesh.clone(),
+            material: assets.hover.clone(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Name::from("Hover Ring"),
+        HoverRing,
+    ));
+}
+
+#[allow(clippy::type_complexity)]
+fn spawn_rings(
+    mut commands: Commands,
+    selectables: Query<(
+        Entity,
+        &Selectable,
+        &Owner,
+        Option<&Footprint>,
+        Option<&Aabb>,
+    )>,
+    rings: Query<&SelectionRing>,
+    assets: Res<RingAssets>,
+    local_player: Res<LocalPlayer>,
+) {
+    let ringed: HashSet<Entity> = rings.iter().map(|ring| ring.0).collect();
+    for (entity, selectable, owner, footprint, aabb) in &selectables {
+        if !selectable.is_selected || ringed.contains(&entity) {
+            continue;
+        }
+        // Models get their bounds once they are first rendered
+        let Some(radius) = ring_radius(footprint, aabb) else {
+            continue;
+        };
+        let material = if owner.0 == local_player.0 {
+            assets.own.clone()
+        } else {
+            assets.enemy.clone()
+        };
+
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material,
+                transform: Transform::from_scale(Vec3::new(radius, RING_THICKNESS, radius)),
+                // Placed by `follow_selection` before it is shown
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            Name::from("Selection Ring"),
+            SelectionRing(entity),
+        ));
+    }
+}
+
+/// Keeps rings under their unit and removes them once it is deselected or gone
+fn follow_selection(
+    mut commands: Commands,
+    mut rings: Query<(Entity, &SelectionRing, &mut Transform, &mut Visibility)>,
+    targets: Query<(&Transform, &Selectable, &Visibility), Without<SelectionRing>>,
+    terrain: Res<Terrain>,
+) {
+    for (entity, ring, mut transform, mut visibility) in &mut rings {
+        let Ok((target, selectable, target_visibility)) = targets.get(ring.0) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        if !selectable.is_selected {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let scale = transform.scale.x;
+        place_ring(&mut transform, target.translation, scale, &terrain);
+        *visibility = *target_visibility;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn follow_hovered(
+    mut ring: Query<(&mut Transform, &mut Visibility, With<HoverRing>)>,
+    targets: Query<(&Transform, Option<&Footprint>, Option<&Aabb>), Without<HoverRing>>,
+    hovered: Res<HoveredEntity>,
+    terrain: Res<Terrain>,
+) {
+    let Ok((mut transform, mut visibility, _)) = ring.get_single_mut() else {
+        return;
+    };
+
+    let target = hovered.0.and_then(|entity| targets.get(entity).ok());
+    let Some((target, Some(radius))) =
+        target.map(|(target, footprint, aabb)| (target, ring_radius(footprint, aabb)))
+    else {
+        if *visibility != Visibility::Hidden {
+            *visibility = Visibility::Hidden;
+        }
+        return;
+    };
+
+    place_ring(
+        &mut transform,
+        target.translation,
+        radius * HOVER_RING_SCALE,
+        &terrain,
+    );
+    *visibility = Visibility::Inherited;
+}
+
+fn hide_hover_ring(mut ring: Query<(&mut Visibility, With<HoverRing>)>) {
+    for (mut visibility, _) in &mut ring {
+        *visibility = Visibility::Hidden;
+    }
+}
@@ -0,0 +1,164 @@
+use bevy::prelude::*;
+
+use crate::{
+    abilities::AbilityTarget,
+    ground::Terrain,
+    order::{is_queueing, Order, Orders},
+    player::{LocalPlayer, Owner},
+    selection::Selectable,
+    units::Unit,
+    GameState,
+};
+
+pub struct WaypointPlugin;
+
+const LINE_WIDTH: f32 = 0.04;
+/// Lifts lines above the terrain so that they are not hidden by it
+const LINE_HEIGHT: f32 = 0.1;
+const LINE_COLOR: Color = Color::rgba(0.3, 0.9, 0.3, 0.7);
+
+/// Segment of the path through the queued orders of a selected unit, reused from frame to frame
+#[derive(Component)]
+struct WaypointLine;
+
+#[derive(Resource)]
+struct WaypointAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for WaypointAssets {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+
+        Self {
+            mesh: meshes.add(shape::Cube::new(1.0).into()),
+            material: materials.add(StandardMaterial {
+                base_color: LINE_COLOR,
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+        }
+    }
+}
+
+impl Plugin for WaypointPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WaypointAssets>().add_system(
+            draw_waypoints
+                .run_if(resource_exists::<Terrain>())
+                .in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
+
+/// Connects the selected units with their queued orders while Shift is held
+#[allow(clippy::too_many_arguments)]
+fn draw_waypoints(
+    mut commands: Commands,
+    mut lines: Query<(&mut Transform, &mut Visibility), With<WaypointLine>>,
+    units: Query<(&Transform, &Unit, &Orders, &Selectable, &Owner), Without<WaypointLine>>,
+    targets: Query<&Transform, Without<WaypointLine>>,
+    assets: Res<WaypointAssets>,
+    terrain: Res<Terrain>,
+    input: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+) {
+    let mut segments = Vec::new();
+    if is_queueing(&input) {
+        for (transform, unit, orders, selectable, owner) in &units {
+            if !selectable.is_selected || owner.0 != local_player.0 {
+                continue;
+            }
+
+            let waypoints: Vec<Vec3> = std::iter::once(transform.translation)
+                .chain(unit.state.destination())
+                .chain(
+                    orders
+                        .iter()
+                        .filter_map(|order| order_position(order, &targets)),
+                )
+                .map(|point| {
+                    Vec3::new(
+                        point.x,
+                        terrain.height_at(point.x, point.z) + LINE_HEIGHT,
+                        point.z,
+                    )
+                })
+                .collect();
+            segments.extend(waypoints.windows(2).map(|pair| (pair[0], pair[1])));
+        }
+    }
+
+    let mut segments = segments.into_iter();
+    for (mut transform, mut visibility) in &mut lines {
+        match segments.next() {
+            Some((start, end)) => {
+                *transform = segment_transform(start, end);
+                *visibility = Visibility::Inherited;
+            }
+            None if *visibility != Visibility::Hidden => *visibility = Visibility::Hidden,
+            None => {}
+        }
+    }
+
+    for (start, end) in segments {
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: assets.material.clone(),
+                transform: segment_transform(start, end),
+                ..default()
+            },
+            Name::from("Waypoint Line"),
+            WaypointLine,
+        ));
+    }
+}
+
+/// Where an order leads the unit, if it still exists
+fn order_position(
+    order: &Order,
+    targets: &Query<&Transform, Without<WaypointLine>>,
+) -> Option<Vec3> {
+    let target = match order {
+        Order::Move(position)
+        | Order::Cast {
+            target: AbilityTarget::Point(position),
+            ..
+        } => return Some(*position),
+        Order::Gather(target)
+        | Order::Build(target)
+        | Order::Attack(target)
+        | Order::Cast {
+            target: AbilityTarget::Unit(target),
+            ..
+        } => *target,
+        Order::Cast {
+            target: AbilityTarget::None,
+            ..
+        } => return None,
+    };
+
+    targets
+        .get(target)
+        .ok()
+        .map(|transform| transform.translation)
+}
+
+/// Stretches the unit cube into a thin bar from `start` to `end`
+fn segment_transform(start: Vec3, end: Vec3) -> Transform {
+    let direction = end - start;
+    Transform {
+        translation: (start + end) / 2.0,
+        rotation: direction
+            .try_normalize()
+            .map_or(Quat::IDENTITY, |direction| {
+                Quat::from_rotation_arc(Vec3::Z, direction)
+            }),
+        scale: Vec3::new(LINE_WIDTH, LINE_WIDTH, direction.length()),
+    }
+}
@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::snapshot::SnapshotApp;
+
+pub struct PlayerPlugin;
+
+/// Player that owns an entity
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Reflect)]
+#[reflect(Component)]
+pub struct Owner(pub u8);
+
+/// Player controlled by this client
+#[derive(Resource, Debug, Clone, Copy, Default, Reflect)]
+#[reflect(Resource)]
+pub struct LocalPlayer(pub u8);
+
+/// Colours players can pick from, the first ones are used by default
+pub const PLAYER_COLORS: [(&str, Color); 6] = [
+    ("Blue", Color::rgb(0.2, 0.4, 0.9)),
+    ("Red", Color::rgb(0.9, 0.2, 0.2)),
+    ("Green", Color::rgb(0.2, 0.75, 0.3)),
+    ("Yellow", Color::rgb(0.9, 0.75, 0.1)),
+    ("Purple", Color::rgb(0.6, 0.3, 0.85)),
+    ("Orange", Color::rgb(0.95, 0.5, 0.15)),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Controller {
+    #[default]
+    Human,
+    Ai,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub color: Color,
+    /// Players on the same team are allies
+    pub team: u8,
+    pub controller: Controller,
+}
+
+/// Players of the current game, indexed by player
+#[derive(Resource, Deref, DerefMut)]
+pub struct Players(pub Vec<PlayerInfo>);
+
+impl Default for Players {
+    fn default() -> Self {
+        Self::humans(2)
+    }
+}
+
+impl Players {
+    /// Human players, each on their own team and in the order of the colors
+    pub fn humans(count: usize) -> Self {
+        Self(
+            PLAYER_COLORS
+                .iter()
+                .take(count)
+                .enumerate()
+                .map(|(index, (_, color))| PlayerInfo {
+                    color: *color,
+                    team: index as u8,
+                    controller: Controller::Human,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn color(&self, owner: Owner) -> Color {
+        self.get(owner.0 as usize)
+            .map(|player| player.color)
+            .unwrap_or(Color::GRAY)
+    }
+
+    /// The first human player is the one playing on this machine
+    pub fn first_human(&self) -> Option<Owner> {
+        self.iter()
+            .position(|player| player.controller == Controller::Human)
+            .map(|index| Owner(index as u8))
+    }
+
+    /// Whether the entities of both players are on the same side, every player is allied with themselves
+    pub fn are_allies(&self, a: Owner, b: Owner) -> bool {
+        let team = |owner: Owner| self.get(owner.0 as usize).map(|player| player.team);
+        a == b || team(a).is_some_and(|team_a| team(b) == Some(team_a))
+    }
+}
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<Owner>()
+            .register_type::<LocalPlayer>()
+            .init_resource::<LocalPlayer>()
+            .init_resource::<Players>();
+    }
+}
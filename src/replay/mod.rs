@@ -0,0 +1,276 @@
+mod viewer;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checksum::state_hash,
+    command::{AppliedCommands, IssuedCommand, PlayerCommand},
+    economy::Stockpile,
+    game::GameSetup,
+    player::{LocalPlayer, PlayerInfo, Players},
+    save::LoadingSave,
+    simulation::{SimulationTime, TickEndSet},
+    GameState,
+};
+
+pub use self::viewer::{ReplayPlayback, WatchReplay};
+
+use self::viewer::ReplayViewerPlugin;
+
+/// Records every game and plays recorded games back
+pub struct ReplayPlugin;
+
+/// Version of the replay format, older replays are not read
+const REPLAY_VERSION: u32 = 1;
+/// Replays are written next to the game, not into the assets
+const REPLAY_FOLDER: &str = "replays";
+const REPLAY_EXTENSION: &str = "replay";
+/// Ticks between two state hashes in a replay
+const CHECKSUM_INTERVAL: u64 = 100;
+
+/// What a game needs to start the same way again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: u32,
+    /// Version of the game, the simulation has to be the same for the commands to play out the same
+    pub game_version: String,
+    pub map: String,
+    pub seed: u64,
+    pub starting_resources: Stockpile,
+    pub players: Vec<PlayerInfo>,
+    /// Player who recorded the game, whose vision playback starts with
+    pub recorded_by: u8,
+}
+
+/// Commands of all players in one tick, ticks without commands are left out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayTick {
+    pub tick: u64,
+    pub commands: Vec<(u8, PlayerCommand)>,
+}
+
+/// Recorded game, the header and the commands in the order they were applied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    /// Ticks the game ran for
+    pub length: u64,
+    pub ticks: Vec<ReplayTick>,
+    /// State hash at every `CHECKSUM_INTERVAL` ticks, which playback has to reach as well
+    pub checksums: Vec<(u64, u64)>,
+}
+
+/// Replay of the game being played
+#[derive(Resource)]
+struct Recording(Replay);
+
+impl ReplayHeader {
+    pub fn new(setup: &GameSetup, players: &Players, local_player: LocalPlayer) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            game_version: env!("CARGO_PKG_VERSION").to_string(),
+            map: setup.map.clone(),
+            seed: setup.seed,
+            starting_resources: setup.starting_resources,
+            players: players.0.clone(),
+            recorded_by: local_player.0,
+        }
+    }
+
+    pub fn setup(&self) -> GameSetup {
+        GameSetup {
+            map: self.map.clone(),
+            starting_resources: self.starting_resources,
+            seed: self.seed,
+        }
+    }
+
+    pub fn players(&self) -> Players {
+        Players(self.players.clone())
+    }
+}
+
+impl Replay {
+    pub fn new(header: ReplayHeader) -> Self {
+        Self {
+            header,
+            length: 0,
+            ticks: Vec::new(),
+            checksums: Vec::new(),
+        }
+    }
+
+    /// Adds the commands applied in a tick, `ticks` have been simulated after it
+    fn record(&mut self, ticks: u64, applied: &[IssuedCommand], checksum: Option<u64>) {
+        self.length = ticks;
+        if let Some(checksum) = checksum {
+            self.checksums.push((ticks, checksum));
+        }
+
+        let Some(first) = applied.first() else {
+            return;
+        };
+        self.ticks.push(ReplayTick {
+            tick: first.tick,
+            commands: applied
+                .iter()
+                .map(|command| (command.player, command.command.clone()))
+                .collect(),
+        });
+    }
+
+    /// Commands from the given tick on, as they are queued again for playback
+    pub fn commands_from(&self, tick: u64) -> impl Iterator<Item = IssuedCommand> + '_ {
+        self.ticks
+            .iter()
+            .filter(move |recorded| recorded.tick >= tick)
+            .flat_map(|recorded| {
+                recorded
+                    .commands
+                    .iter()
+                    .map(|(player, command)| IssuedCommand {
+                        player: *player,
+                        tick: recorded.tick,
+                        command: command.clone(),
+                    })
+            })
+    }
+
+    /// State hash the recording had after the given number of ticks
+    pub fn checksum(&self, ticks: u64) -> Option<u64> {
+        self.checksums
+            .iter()
+            .find(|(at, _)| *at == ticks)
+            .map(|(_, checksum)| *checksum)
+    }
+
+    /// Reads a replay, replays of another version of the format or the game are refused
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let value: serde_yaml::Value = serde_yaml::from_str(&text)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+
+        // Checked before the rest is read, which may not even parse in another version
+        let header = &value["header"];
+        let version = header["version"].as_u64();
+        if version != Some(REPLAY_VERSION as u64) {
+            return Err(format!(
+                "{} has replay format {}, this game reads format {}",
+                path.display(),
+                version.map_or("unknown".to_string(), |version| version.to_string()),
+                REPLAY_VERSION
+            ));
+        }
+        let game_version = header["game_version"].as_str().unwrap_or("unknown");
+        if game_version != env!("CARGO_PKG_VERSION") {
+            return Err(format!(
+                "{} was recorded with version {} of the game, this is version {}",
+                path.display(),
+                game_version,
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+
+        serde_yaml::from_value(value).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// Writes the replay into the replay folder, named by the time it is saved at
+    fn save(&self) -> Result<PathBuf, String> {
+        fs::create_dir_all(REPLAY_FOLDER)
+            .map_err(|error| format!("{}: {}", REPLAY_FOLDER, error))?;
+        let seconds = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let path = Path::new(REPLAY_FOLDER).join(format!("{}.{}", seconds, REPLAY_EXTENSION));
+
+        let text = serde_yaml::to_string(self).map_err(|error| error.to_string())?;
+        fs::write(&path, text).map_err(|error| format!("{}: {}", path.display(), error))?;
+        Ok(path)
+    }
+}
+
+/// Replay that was saved last, if there is any
+pub fn latest_replay() -> Option<PathBuf> {
+    fs::read_dir(REPLAY_FOLDER)
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path();
+            if path.extension()? != REPLAY_EXTENSION {
+                return None;
+            }
+            Some((entry.metadata().ok()?.modified().ok()?, path))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(ReplayViewerPlugin)
+            .add_system(
+                start_recording
+                    .run_if(resource_added::<GameSetup>())
+                    .run_if(not(resource_exists::<ReplayPlayback>()))
+                    // A replay starts from the map, not from the middle of a game
+                    .run_if(not(resource_exists::<LoadingSave>()))
+                    .in_schedule(OnEnter(GameState::InGame)),
+            )
+            .add_system(
+                record_tick
+                    .run_if(resource_exists::<Recording>())
+                    .in_set(TickEndSet)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(save_recording.in_schedule(OnEnter(GameState::MainMenu)))
+            .add_system(
+                save_recording
+                    .run_if(on_event::<AppExit>())
+                    .in_base_set(CoreSet::Last),
+            );
+    }
+}
+
+fn start_recording(
+    mut commands: Commands,
+    setup: Res<GameSetup>,
+    players: Res<Players>,
+    local_player: Res<LocalPlayer>,
+) {
+    let header = ReplayHeader::new(&setup, &players, *local_player);
+    commands.insert_resource(Recording(Replay::new(header)));
+}
+
+fn record_tick(world: &mut World) {
+    let ticks = world.resource::<SimulationTime>().tick();
+    let checksum = ticks
+        .is_multiple_of(CHECKSUM_INTERVAL)
+        .then(|| state_hash(world));
+    let applied = std::mem::take(&mut world.resource_mut::<AppliedCommands>().0);
+    world
+        .resource_mut::<Recording>()
+        .0
+        .record(ticks, &applied, checksum);
+}
+
+fn save_recording(mut commands: Commands, recording: Option<Res<Recording>>) {
+    let Some(recording) = recording else {
+        return;
+    };
+
+    if recording.0.length > 0 {
+        match recording.0.save() {
+            Ok(path) => info!("replay saved to {}", path.display()),
+            Err(error) => error!("replay could not be saved: {}", error),
+        }
+    }
+    commands.remove_resource::<Recording>();
+}
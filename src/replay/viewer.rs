@@ -0,0 +1,240 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::{
+    checksum::{dump_state, StateSnapshot},
+    command::CommandQueue,
+    player::{LocalPlayer, Players},
+    simulation::{SimulationTime, TickEndSet, TICKS_PER_SECOND},
+    snapshot::Snapshot,
+    GameState,
+};
+
+use super::Replay;
+
+pub struct ReplayViewerPlugin;
+
+/// Ticks between two snapshots that seeking goes back to
+const SNAPSHOT_INTERVAL: u64 = 10 * TICKS_PER_SECOND as u64;
+/// How far the arrow keys seek
+const SEEK_STEP: u64 = 10 * TICKS_PER_SECOND as u64;
+
+const PAUSE_KEY: KeyCode = KeyCode::Space;
+const VISION_KEY: KeyCode = KeyCode::V;
+
+/// Loads a replay and plays it back instead of a new game
+pub struct WatchReplay(pub PathBuf);
+
+/// Replay being played back. Its commands replace the input of the players,
+/// which only moves the camera and selects units to look at
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    /// Taken while playing, in the order of their ticks
+    snapshots: Vec<Snapshot>,
+    paused: bool,
+    /// Tick that playback jumps to before the next frame
+    seek: Option<u64>,
+    /// First tick whose state did not match the recording
+    desynced_at: Option<u64>,
+}
+
+impl ReplayPlayback {
+    fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            snapshots: Vec::new(),
+            paused: false,
+            seek: None,
+            desynced_at: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Ticks the recorded game ran for
+    pub fn length(&self) -> u64 {
+        self.replay.length
+    }
+
+    pub fn desynced_at(&self) -> Option<u64> {
+        self.desynced_at
+    }
+
+    /// Latest snapshot at or before the tick, or the first one if they all come after it
+    fn snapshot_before(&self, tick: u64) -> Option<&Snapshot> {
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.tick() <= tick)
+            .or(self.snapshots.first())
+    }
+}
+
+impl Plugin for ReplayViewerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WatchReplay>()
+            .add_system(start_playback.run_if(on_event::<WatchReplay>()))
+            .add_systems(
+                (control_playback, seek)
+                    .chain()
+                    .distributive_run_if(resource_exists::<ReplayPlayback>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            // After the pause menu unfreezes the game, before any tick runs
+            .add_system(
+                freeze_playback
+                    .run_if(resource_exists::<ReplayPlayback>())
+                    .run_if(in_state(GameState::InGame))
+                    .after(apply_state_transition::<GameState>)
+                    .in_base_set(CoreSet::StateTransitions),
+            )
+            .add_system(
+                follow_recording
+                    .run_if(resource_exists::<ReplayPlayback>())
+                    .in_set(TickEndSet)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(end_playback.in_schedule(OnEnter(GameState::MainMenu)));
+    }
+}
+
+fn start_playback(
+    mut commands: Commands,
+    mut reader: EventReader<WatchReplay>,
+    mut queue: ResMut<CommandQueue>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(WatchReplay(path)) = reader.iter().last() else {
+        return;
+    };
+
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(error) => {
+            error!("{}", error);
+            return;
+        }
+    };
+
+    info!("watching replay {}", path.display());
+    for command in replay.commands_from(0) {
+        queue.push(command);
+    }
+    commands.insert_resource(replay.header.setup());
+    commands.insert_resource(replay.header.players());
+    commands.insert_resource(LocalPlayer(replay.header.recorded_by));
+    commands.insert_resource(ReplayPlayback::new(replay));
+    next_state.set(GameState::InGame);
+}
+
+fn control_playback(
+    input: Res<Input<KeyCode>>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut local_player: ResMut<LocalPlayer>,
+    players: Res<Players>,
+    time: Res<SimulationTime>,
+) {
+    if input.just_pressed(PAUSE_KEY) {
+        playback.paused = !playback.paused;
+    }
+
+    let from = playback.seek.unwrap_or(time.tick());
+    if input.just_pressed(KeyCode::Left) {
+        playback.seek = Some(from.saturating_sub(SEEK_STEP));
+    }
+    if input.just_pressed(KeyCode::Right) {
+        playback.seek = Some((from + SEEK_STEP).min(playback.length()));
+    }
+
+    if input.just_pressed(VISION_KEY) && !players.is_empty() {
+        local_player.0 = (local_player.0 + 1) % players.len() as u8;
+    }
+}
+
+/// Jumps to another tick, going back to the snapshot before it and simulating from there
+fn seek(world: &mut World) {
+    let Some(target) = world.resource_mut::<ReplayPlayback>().seek.take() else {
+        return;
+    };
+
+    let tick = world.resource::<SimulationTime>().tick();
+    world.resource_scope(|world, playback: Mut<ReplayPlayback>| {
+        let Some(snapshot) = playback.snapshot_before(target) else {
+            return;
+        };
+        // Simulating on is quicker when the target is ahead and no snapshot is closer
+        if target >= tick && snapshot.tick() <= tick {
+            return;
+        }
+
+        snapshot.restore(world);
+        let mut queue = CommandQueue::default();
+        for command in playback.replay.commands_from(snapshot.tick()) {
+            queue.push(command);
+        }
+        world.insert_resource(queue);
+    });
+
+    while world.resource::<SimulationTime>().tick() < target {
+        world.run_schedule(CoreSchedule::FixedUpdate);
+    }
+}
+
+/// Takes snapshots as playback goes on and compares the state with the recording
+fn follow_recording(world: &mut World) {
+    let tick = world.resource::<SimulationTime>().tick();
+    let playback = world.resource::<ReplayPlayback>();
+    let expected = playback.replay.checksum(tick);
+    let needs_snapshot = playback
+        .snapshots
+        .last()
+        .is_none_or(|last| tick >= last.tick() + SNAPSHOT_INTERVAL);
+
+    let state = expected.map(|_| StateSnapshot::capture(world));
+    let actual = state.as_ref().map(StateSnapshot::hash);
+    let snapshot = needs_snapshot.then(|| Snapshot::capture(world));
+
+    let mut playback = world.resource_mut::<ReplayPlayback>();
+    if let Some(snapshot) = snapshot {
+        playback.snapshots.push(snapshot);
+    }
+    if expected != actual && playback.desynced_at.is_none() {
+        warn!("replay went out of sync at tick {}", tick);
+        playback.desynced_at = Some(tick);
+        if let Some(state) = state {
+            match dump_state(&state, &format!("replay-tick-{}", tick)) {
+                Ok(path) => warn!("state written to {}", path.display()),
+                Err(error) => warn!("state could not be written: {}", error),
+            }
+        }
+    }
+}
+
+/// Holds the game while playback is paused or has reached the end of the recording
+fn freeze_playback(
+    playback: Res<ReplayPlayback>,
+    simulation_time: Res<SimulationTime>,
+    mut time: ResMut<Time>,
+) {
+    let frozen = playback.paused || simulation_time.tick() >= playback.length();
+    if frozen && !time.is_paused() {
+        time.pause();
+    } else if !frozen && time.is_paused() {
+        time.unpause();
+    }
+}
+
+fn end_playback(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    mut time: ResMut<Time>,
+) {
+    if playback.is_some() {
+        commands.remove_resource::<ReplayPlayback>();
+        time.unpause();
+    }
+}
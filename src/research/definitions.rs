@@ -0,0 +1,89 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::{
+    buildings::BuildingKind, economy::Cost, hotkeys::letter_key_code, stats::Modifier,
+    units::UnitKind,
+};
+
+const RESEARCH_DEFINITIONS: &str = include_str!("../../assets/data/research.yaml");
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResearchDefinition {
+    pub name: String,
+    /// Kind of building where the research is done
+    pub researched_at: BuildingKind,
+    #[serde(default)]
+    pub cost: Cost,
+    /// Seconds the research takes
+    pub time: f32,
+    #[serde(default)]
+    pub requires: Prerequisites,
+    /// Letter that starts the research while a building where it is done is selected
+    pub hotkey: Option<char>,
+    pub effects: Vec<ResearchEffect>,
+}
+
+impl ResearchDefinition {
+    pub fn hotkey(&self) -> Option<KeyCode> {
+        self.hotkey.and_then(letter_key_code)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Prerequisites {
+    /// Finished buildings the player needs to own
+    pub buildings: Vec<BuildingKind>,
+    /// Research the player needs to have completed
+    pub research: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum ResearchEffect {
+    /// Permanently changes a stat of the player's units of the given kinds
+    Modify {
+        units: Vec<UnitKind>,
+        modifier: Modifier,
+    },
+    /// Lets the player's units of the given kinds use an ability
+    UnlockAbility {
+        units: Vec<UnitKind>,
+        ability: String,
+    },
+}
+
+/// Tech tree, read from `assets/data/research.yaml`
+#[derive(Resource, Debug)]
+pub struct ResearchDefinitions(HashMap<String, ResearchDefinition>);
+
+impl Default for ResearchDefinitions {
+    fn default() -> Self {
+        let definitions: HashMap<String, ResearchDefinition> =
+            serde_yaml::from_str(RESEARCH_DEFINITIONS).expect("invalid research definitions");
+        for (id, definition) in &definitions {
+            for required in &definition.requires.research {
+                assert!(
+                    definitions.contains_key(required),
+                    "research {} requires unknown research {}",
+                    id,
+                    required
+                );
+            }
+        }
+
+        Self(definitions)
+    }
+}
+
+impl ResearchDefinitions {
+    pub fn get(&self, id: &str) -> Option<&ResearchDefinition> {
+        self.0.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ResearchDefinition)> {
+        self.0.iter()
+    }
+}
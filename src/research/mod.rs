@@ -0,0 +1,366 @@
+mod definitions;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    buildings::{Building, Construction},
+    command::PlayerCommand,
+    economy::Stockpiles,
+    player::{LocalPlayer, Owner},
+    selection::Selectable,
+    simulation::{SimulationEventApp, SimulationTime, StableId, StepSet},
+    snapshot::SnapshotApp,
+    stats::{Modifier, ModifierSource, Stats},
+    units::UnitKind,
+    GameState,
+};
+
+pub use self::definitions::{ResearchDefinitions, ResearchEffect};
+
+pub struct ResearchPlugin;
+
+/// Starting and cancelling research from the keyboard
+pub struct ResearchInputPlugin;
+
+const CANCEL_KEY: KeyCode = KeyCode::Delete;
+
+/// Research a building is working on
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct Researching {
+    pub research: String,
+    /// Seconds spent on the research
+    pub progress: f32,
+}
+
+#[derive(Debug, Default, Clone, Reflect, FromReflect)]
+pub struct PlayerResearch {
+    completed: HashSet<String>,
+    /// Permanent modifiers of completed research by the unit kind they apply to
+    modifiers: HashMap<UnitKind, Vec<Modifier>>,
+}
+
+/// Research every player completed, indexed by player
+#[derive(Resource, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct ResearchState(Vec<PlayerResearch>);
+
+impl ResearchState {
+    pub fn is_completed(&self, owner: Owner, research: &str) -> bool {
+        self.0
+            .get(owner.0 as usize)
+            .map(|player| player.completed.contains(research))
+            .unwrap_or(false)
+    }
+
+    /// Research the owner completed, in alphabetical order
+    pub fn completed(&self, owner: Owner) -> Vec<&str> {
+        let mut completed: Vec<&str> = self
+            .0
+            .get(owner.0 as usize)
+            .map(|player| player.completed.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        completed.sort_unstable();
+        completed
+    }
+
+    /// Whether units of the kind may use the ability,
+    /// abilities that no research unlocks can always be used
+    pub fn is_ability_unlocked(
+        &self,
+        definitions: &ResearchDefinitions,
+        owner: Owner,
+        kind: UnitKind,
+        ability: &str,
+    ) -> bool {
+        let mut unlocked_by = definitions
+            .iter()
+            .filter(|(_, definition)| {
+                definition.effects.iter().any(|effect| match effect {
+                    ResearchEffect::UnlockAbility {
+                        units,
+                        ability: unlocked,
+                    } => unlocked == ability && units.contains(&kind),
+                    ResearchEffect::Modify { .. } => false,
+                })
+            })
+            .peekable();
+
+        unlocked_by.peek().is_none() || unlocked_by.any(|(id, _)| self.is_completed(owner, id))
+    }
+
+    /// Modifiers the owner's research applies to units of the kind
+    fn modifiers(&self, owner: Owner, kind: UnitKind) -> impl Iterator<Item = &Modifier> {
+        self.0
+            .get(owner.0 as usize)
+            .and_then(|player| player.modifiers.get(&kind))
+            .into_iter()
+            .flatten()
+    }
+
+    fn get_mut(&mut self, owner: Owner) -> &mut PlayerResearch {
+        let index = owner.0 as usize;
+        if self.0.len() <= index {
+            self.0.resize(index + 1, PlayerResearch::default());
+        }
+        &mut self.0[index]
+    }
+}
+
+/// Starts a research at the building if its owner meets the prerequisites and can pay for it
+pub struct StartResearch {
+    pub building: Entity,
+    pub research: String,
+}
+
+/// Stops the research of the building and refunds it
+pub struct CancelResearch {
+    pub building: Entity,
+}
+
+impl Plugin for ResearchPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<Researching>()
+            .add_snapshot_resource::<ResearchState>()
+            .register_type::<PlayerResearch>()
+            .register_type::<Vec<PlayerResearch>>()
+            .register_type::<HashMap<UnitKind, Vec<Modifier>>>()
+            .register_type::<Vec<Modifier>>()
+            // Sets are reflected as plain values, which are saved through serde
+            .register_type::<HashSet<String>>()
+            .register_type_data::<HashSet<String>, ReflectSerialize>()
+            .register_type_data::<HashSet<String>, ReflectDeserialize>()
+            .init_resource::<ResearchDefinitions>()
+            .init_resource::<ResearchState>()
+            .add_simulation_event::<StartResearch>()
+            .add_simulation_event::<CancelResearch>()
+            .add_systems(
+                (
+                    start_research,
+                    cancel_research,
+                    advance_research,
+                    apply_research_modifiers,
+                )
+                    .chain()
+                    .in_set(StepSet::Research)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+impl Plugin for ResearchInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (research_from_hotkeys, cancel_from_hotkeys).in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn research_from_hotkeys(
+    buildings: Query<(
+        &StableId,
+        &Building,
+        &Selectable,
+        &Owner,
+        Without<Construction>,
+        Without<Researching>,
+    )>,
+    definitions: Res<ResearchDefinitions>,
+    input: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<PlayerCommand>,
+) {
+    for (building_id, building, selectable, owner, _, _) in &buildings {
+        if !selectable.is_selected || owner.0 != local_player.0 {
+            continue;
+        }
+
+        for (id, definition) in definitions.iter() {
+            if definition.researched_at == building.kind
+                && definition
+                    .hotkey()
+                    .map(|key| input.just_pressed(key))
+                    .unwrap_or(false)
+            {
+                writer.send(PlayerCommand::Research {
+                    building: *building_id,
+                    research: id.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn cancel_from_hotkeys(
+    buildings: Query<(&StableId, &Selectable, &Owner, With<Researching>)>,
+    input: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+    mut writer: EventWriter<PlayerCommand>,
+) {
+    if !input.just_pressed(CANCEL_KEY) {
+        return;
+    }
+
+    for (id, selectable, owner, _) in &buildings {
+        if selectable.is_selected && owner.0 == local_player.0 {
+            writer.send(PlayerCommand::CancelResearch { building: *id });
+        }
+    }
+}
+
+fn start_research(
+    mut commands: Commands,
+    mut reader: EventReader<StartResearch>,
+    buildings: Query<(&Building, &Owner, Without<Construction>)>,
+    researching: Query<(&Researching, &Owner)>,
+    definitions: Res<ResearchDefinitions>,
+    state: Res<ResearchState>,
+    mut stockpiles: ResMut<Stockpiles>,
+) {
+    // Research started this tick, which is not in the query yet
+    let mut started: Vec<(Entity, Owner, &str)> = Vec::new();
+
+    for event in reader.iter() {
+        let (Ok((building, owner, _)), Some(definition)) = (
+            buildings.get(event.building),
+            definitions.get(&event.research),
+        ) else {
+            continue;
+        };
+
+        let is_busy = researching.contains(event.building)
+            || started
+                .iter()
+                .any(|(building, ..)| *building == event.building);
+        let is_in_progress = researching
+            .iter()
+            .map(|(other, other_owner)| (*other_owner, other.research.as_str()))
+            .chain(
+                started
+                    .iter()
+                    .map(|(_, owner, research)| (*owner, *research)),
+            )
+            .any(|(other_owner, research)| other_owner == *owner && research == event.research);
+        if definition.researched_at != building.kind
+            || is_busy
+            || is_in_progress
+            || state.is_completed(*owner, &event.research)
+        {
+            continue;
+        }
+
+        let has_buildings = definition.requires.buildings.iter().all(|required| {
+            buildings.iter().any(|(building, building_owner, _)| {
+                building.kind == *required && building_owner == owner
+            })
+        });
+        let has_research = definition
+            .requires
+            .research
+            .iter()
+            .all(|required| state.is_completed(*owner, required));
+        if !has_buildings || !has_research {
+            continue;
+        }
+
+        let stockpile = stockpiles.get_mut(*owner);
+        if !stockpile.can_afford(&definition.cost) {
+            continue;
+        }
+
+        stockpile.spend(&definition.cost);
+        started.push((event.building, *owner, event.research.as_str()));
+        commands.entity(event.building).insert(Researching {
+            research: event.research.clone(),
+            progress: 0.0,
+        });
+    }
+}
+
+fn cancel_research(
+    mut commands: Commands,
+    mut reader: EventReader<CancelResearch>,
+    buildings: Query<(&Researching, &Owner)>,
+    definitions: Res<ResearchDefinitions>,
+    mut stockpiles: ResMut<Stockpiles>,
+) {
+    for event in reader.iter() {
+        let Ok((researching, owner)) = buildings.get(event.building) else {
+            continue;
+        };
+
+        if let Some(definition) = definitions.get(&researching.research) {
+            stockpiles.get_mut(*owner).refund(&definition.cost, 1.0);
+        }
+        commands.entity(event.building).remove::<Researching>();
+    }
+}
+
+fn advance_research(
+    mut commands: Commands,
+    mut buildings: Query<(Entity, &StableId, &mut Researching, &Owner)>,
+    definitions: Res<ResearchDefinitions>,
+    mut state: ResMut<ResearchState>,
+    time: Res<SimulationTime>,
+    local_player: Res<LocalPlayer>,
+) {
+    // Modifiers pile up in the order research completes, by building id
+    let mut buildings: Vec<_> = buildings.iter_mut().collect();
+    buildings.sort_by_key(|(_, id, ..)| **id);
+
+    for (entity, _, mut researching, owner) in buildings {
+        let Some(definition) = definitions.get(&researching.research) else {
+            commands.entity(entity).remove::<Researching>();
+            continue;
+        };
+
+        researching.progress += time.delta_seconds();
+        if researching.progress < definition.time {
+            continue;
+        }
+
+        commands.entity(entity).remove::<Researching>();
+        let player = state.get_mut(*owner);
+        player.completed.insert(researching.research.clone());
+
+        for effect in &definition.effects {
+            match effect {
+                ResearchEffect::Modify { units, modifier } => {
+                    for kind in units {
+                        player.modifiers.entry(*kind).or_default().push(*modifier);
+                    }
+                }
+                ResearchEffect::UnlockAbility { units, ability } => {
+                    if owner.0 == local_player.0 {
+                        info!("{} unlocked for {:?}", ability, units);
+                    }
+                }
+            }
+        }
+
+        if owner.0 == local_player.0 {
+            info!("{} researched", definition.name);
+        }
+    }
+}
+
+/// Gives new units the modifiers of completed research, and all units the ones of new research
+fn apply_research_modifiers(
+    mut units: Query<(&mut Stats, &UnitKind, &Owner)>,
+    state: Res<ResearchState>,
+) {
+    for (mut stats, kind, owner) in &mut units {
+        if !state.is_changed() && !stats.is_added() {
+            continue;
+        }
+
+        stats.remove_modifiers(&ModifierSource::Research);
+        for modifier in state.modifiers(*owner, *kind) {
+            stats.add_modifier(*modifier, ModifierSource::Research, None);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    buildings::SpawnBuilding,
+    economy::SpawnResourceNode,
+    fog::VisibilityGrid,
+    game::GameSetup,
+    ground::Terrain,
+    map::CurrentMap,
+    player::{LocalPlayer, PlayerInfo, Players},
+    selection::ControlGroups,
+    simulation::StableId,
+    snapshot::{SerializedSnapshot, Snapshot},
+    units::SpawnUnit,
+    GameState,
+};
+
+/// Saves games to disk and continues them later
+pub struct SavePlugin;
+
+/// Version of the save format, older saves are brought up to it by `MIGRATIONS`
+const SAVE_VERSION: u32 = 1;
+/// Each entry upgrades the raw file of a save by one version, the first one from version 1 to 2.
+/// They run before the file is read, so renamed or reshaped types can be fixed up in place
+const MIGRATIONS: &[Migration] = &[];
+
+type Migration = fn(&mut serde_yaml::Value) -> Result<(), String>;
+
+const SAVE_FOLDER: &str = "saves";
+const SAVE_EXTENSION: &str = "save";
+
+/// Writes the running game to a new file in the save folder
+pub struct SaveGame;
+
+/// Ends the running game, if there is one, and continues the saved one
+pub struct LoadGame(pub PathBuf);
+
+/// Everything needed to continue a game, the simulation and what the player was looking at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub setup: GameSetup,
+    pub players: Vec<PlayerInfo>,
+    pub local_player: u8,
+    /// Translation and rotation of the camera, headless games have none
+    pub camera: Option<(Vec3, Quat)>,
+    /// Cells each player has explored, one character per cell
+    pub explored: Vec<String>,
+    /// Units and buildings in each control group of the local player
+    pub control_groups: Vec<Vec<StableId>>,
+    pub state: SerializedSnapshot,
+}
+
+/// Save that is being loaded, its state is put in place as soon as the map is ready
+#[derive(Resource)]
+pub struct LoadingSave(SaveFile);
+
+/// Camera of a loaded save, which only exists a few frames after the map
+#[derive(Resource)]
+struct RestoringView {
+    camera: Option<(Vec3, Quat)>,
+}
+
+impl SaveFile {
+    /// Saves the game that is running in the world
+    pub fn capture(world: &World) -> Result<Self, String> {
+        let setup = world
+            .get_resource::<GameSetup>()
+            .ok_or("there is no game to save")?
+            .clone();
+        let registry = world.resource::<AppTypeRegistry>().read();
+        let state = Snapshot::capture(world).serialize(&registry)?;
+
+        let camera = world
+            .iter_entities()
+            .find(|entity| entity.contains::<Camera3d>())
+            .and_then(|entity| entity.get::<Transform>())
+            .map(|transform| (transform.translation, transform.rotation));
+        let explored = world
+            .get_resource::<VisibilityGrid>()
+            .map_or_else(Vec::new, VisibilityGrid::explored);
+        let control_groups = world
+            .get_resource::<ControlGroups>()
+            .map_or_else(Vec::new, |groups| groups.0.clone());
+
+        Ok(Self {
+            version: SAVE_VERSION,
+            setup,
+            players: world.resource::<Players>().0.clone(),
+            local_player: world.resource::<LocalPlayer>().0,
+            camera,
+            explored,
+            control_groups,
+            state,
+        })
+    }
+
+    /// Reads a save, migrating it first if it was written by an older version
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut value: serde_yaml::Value = serde_yaml::from_str(&text)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+
+        let version = value["version"]
+            .as_u64()
+            .ok_or_else(|| format!("{} is not a save", path.display()))?;
+        if version == 0 || version > SAVE_VERSION as u64 {
+            return Err(format!(
+                "{} has save version {}, this game reads up to version {}",
+                path.display(),
+                version,
+                SAVE_VERSION
+            ));
+        }
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            migrate(&mut value).map_err(|error| format!("{}: {}", path.display(), error))?;
+        }
+        value["version"] = SAVE_VERSION.into();
+
+        serde_yaml::from_value(value).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let text = serde_yaml::to_string(self).map_err(|error| error.to_string())?;
+        fs::write(path, text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// Replaces the simulation with the saved one. The map has to be loaded already,
+    /// the entities it was about to spawn are dropped for the saved ones.
+    /// What the players explored is part of it, building sites are checked against it
+    pub fn restore_state(&self, world: &mut World) -> Result<(), String> {
+        let registry = world.resource::<AppTypeRegistry>().clone();
+        let snapshot = Snapshot::deserialize(&self.state, &registry.read())?;
+        let mut grid = world
+            .get_resource_mut::<VisibilityGrid>()
+            .ok_or("the map is not loaded yet")?;
+        for (player, cells) in self.explored.iter().enumerate() {
+            grid.set_explored(player as u8, cells);
+        }
+        snapshot.restore(world);
+
+        world.resource_mut::<Events<SpawnUnit>>().clear();
+        world.resource_mut::<Events<SpawnBuilding>>().clear();
+        world.resource_mut::<Events<SpawnResourceNode>>().clear();
+        world.insert_resource(LocalPlayer(self.local_player));
+        world.insert_resource(ControlGroups(self.control_groups.clone()));
+        Ok(())
+    }
+}
+
+/// Path for a new save in the save folder, named by the time it is saved at
+fn new_save_path() -> Result<PathBuf, String> {
+    fs::create_dir_all(SAVE_FOLDER).map_err(|error| format!("{}: {}", SAVE_FOLDER, error))?;
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    Ok(Path::new(SAVE_FOLDER).join(format!("{}.{}", seconds, SAVE_EXTENSION)))
+}
+
+/// Save that was written last, if there is any
+pub fn latest_save() -> Option<PathBuf> {
+    fs::read_dir(SAVE_FOLDER)
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path();
+            if path.extension()? != SAVE_EXTENSION {
+                return None;
+            }
+            Some((entry.metadata().ok()?.modified().ok()?, path))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveGame>()
+            .add_event::<LoadGame>()
+            .add_system(save_game.run_if(on_event::<SaveGame>()))
+            .add_system(start_loading.in_set(OnUpdate(GameState::MainMenu)))
+            // Before the first tick could run on the map
+            .add_system(
+                restore_saved_state
+                    .run_if(resource_exists::<LoadingSave>())
+                    .run_if(resource_exists::<CurrentMap>())
+                    .run_if(resource_exists::<Terrain>())
+                    .in_base_set(CoreSet::PreUpdate),
+            )
+            .add_system(restore_saved_view.run_if(resource_exists::<RestoringView>()))
+            .add_system(cancel_loading.in_schedule(OnEnter(GameState::MainMenu)));
+    }
+}
+
+fn save_game(world: &mut World) {
+    world.resource_mut::<Events<SaveGame>>().clear();
+
+    let saved = SaveFile::capture(world).and_then(|save| {
+        let path = new_save_path()?;
+        save.write(&path)?;
+        Ok(path)
+    });
+    match saved {
+        Ok(path) => info!("game saved to {}", path.display()),
+        Err(error) => error!("game could not be saved: {}", error),
+    }
+}
+
+/// Starts the saved game like a new one, its state replaces the map's once that has loaded
+fn start_loading(
+    mut commands: Commands,
+    mut reader: EventReader<LoadGame>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(LoadGame(path)) = reader.iter().last() else {
+        return;
+    };
+
+    let save = match SaveFile::read(path) {
+        Ok(save) => save,
+        Err(error) => {
+            error!("{}", error);
+            return;
+        }
+    };
+
+    info!("loading {}", path.display());
+    commands.insert_resource(save.setup.clone());
+    commands.insert_resource(Players(save.players.clone()));
+    commands.insert_resource(LocalPlayer(save.local_player));
+    commands.insert_resource(LoadingSave(save));
+    next_state.set(GameState::InGame);
+}
+
+fn restore_saved_state(world: &mut World) {
+    let Some(LoadingSave(save)) = world.remove_resource::<LoadingSave>() else {
+        return;
+    };
+
+    if let Err(error) = save.restore_state(world) {
+        error!("save could not be loaded: {}", error);
+        world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::MainMenu);
+        return;
+    }
+    world.insert_resource(RestoringView {
+        camera: save.camera,
+    });
+}
+
+fn restore_saved_view(
+    mut commands: Commands,
+    view: Res<RestoringView>,
+    mut camera: Query<(&mut Transform, With<Camera3d>)>,
+) {
+    if let (Some((translation, rotation)), Ok((mut transform, _))) =
+        (view.camera, camera.get_single_mut())
+    {
+        transform.translation = translation;
+        transform.rotation = rotation;
+    }
+    commands.remove_resource::<RestoringView>();
+}
+
+/// A save that is left over when the game ends is not loaded into the next one
+fn cancel_loading(mut commands: Commands) {
+    commands.remove_resource::<LoadingSave>();
+    commands.remove_resource::<RestoringView>();
+}
@@ -1,10 +1,39 @@
-use bevy::{prelude::*, sprite::Anchor, window::PrimaryWindow};
+use bevy::{prelude::*, sprite::Anchor, ui::RelativeCursorPosition, window::PrimaryWindow};
 use bevy_rapier3d::prelude::{Collider, Sensor};
 
-use crate::{units::Unit, GameState};
+use crate::{
+    abilities::AbilityTargeting,
+    buildings::{Building, PlacingBuilding},
+    command::PlayerCommand,
+    cursor::{update_hovered_entity, HoveredEntity},
+    hud::{is_cursor_over_hud, HudNode},
+    player::{LocalPlayer, Owner},
+    simulation::StableId,
+    units::Unit,
+    GameState,
+};
 
 pub struct SelectionPlugin;
 
+/// Keys of the control groups, in the order of `ControlGroups`
+const CONTROL_GROUP_KEYS: [KeyCode; 10] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Key0,
+];
+
+/// Units and buildings the local player put into each control group. They are saved with
+/// the game, but only the local player has them, so they are not part of the simulation
+#[derive(Resource, Debug, Default, Clone)]
+pub struct ControlGroups(pub Vec<Vec<StableId>>);
+
 #[derive(Debug)]
 /// Vec2 values are in screen position
 enum SelectionEvent {
@@ -20,6 +49,7 @@ struct Selection {
 }
 
 #[derive(Component, Default, Debug, Reflect)]
+#[reflect(Component)]
 pub struct Selectable {
     pub is_selected: bool,
 }
@@ -27,67 +57,66 @@ pub struct Selectable {
 impl Plugin for SelectionPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SelectionEvent>()
-            .register_type::<Selectable>()
+            .init_resource::<ControlGroups>()
+            .add_system(clear_control_groups.in_schedule(OnEnter(GameState::MainMenu)))
             .add_systems(
                 (
-                    create_selection_events,
+                    create_selection_events
+                        .run_if(not(resource_exists::<PlacingBuilding>()))
+                        .run_if(not(resource_exists::<AbilityTargeting>())),
                     start_drawing_selection,
                     draw_selection.run_if(any_with_component::<Selection>()),
                     set_selection_size.run_if(any_with_component::<Selection>()),
                     despawn_selection,
-                    select_unit,
-                    deselect_unit,
+                    select_unit
+                        .after(update_hovered_entity)
+                        .run_if(not(is_cursor_over_hud))
+                        .run_if(not(resource_exists::<PlacingBuilding>()))
+                        .run_if(not(resource_exists::<AbilityTargeting>())),
+                    deselect_unit
+                        .after(update_hovered_entity)
+                        .run_if(not(is_cursor_over_hud))
+                        .run_if(not(resource_exists::<PlacingBuilding>()))
+                        .run_if(not(resource_exists::<AbilityTargeting>())),
+                    use_control_groups.before(send_selection),
+                    send_selection,
                 )
                     .in_set(OnUpdate(GameState::InGame)),
             );
     }
 }
 
+/// Selects the unit or building that a click lands on, the one the cursor shows as hovered.
+/// Enemies in the fog are hidden and cannot be selected
+#[allow(clippy::type_complexity)]
 fn select_unit(
-    window: Query<(&Window, With<PrimaryWindow>)>,
-    camera: Query<(&Camera, &GlobalTransform, With<Camera3d>)>,
-    mut units: Query<(&Collider, &mut Selectable, With<Unit>)>,
+    mut units: Query<(&mut Selectable, &Visibility), Or<(With<Unit>, With<Building>)>>,
+    hovered: Res<HoveredEntity>,
     input: Res<Input<MouseButton>>,
 ) {
-    let (window, _) = window.single();
-    let (camera, camera_transform, _) = camera.single();
-
-    for (unit_collider, mut selectable, _) in &mut units {
-        if is_single_unit_selectable(camera, camera_transform, window, unit_collider)
-            && input.just_pressed(MouseButton::Left)
-        {
-            selectable.is_selected = true
-        }
+    if !input.just_pressed(MouseButton::Left) {
+        return;
     }
-}
-
-fn is_single_unit_selectable(
-    camera: &Camera,
-    camera_transform: &GlobalTransform,
-    window: &Window,
-    unit_collider: &Collider,
-) -> bool {
-    if let Some(cursor_position) = window.cursor_position() {
-        if let Some(ray) = camera.viewport_to_world(camera_transform, cursor_position) {
-            return unit_collider.intersects_local_ray(ray.origin, ray.direction, 100.0);
-        }
+    let Some(Ok((mut selectable, visibility))) = hovered.0.map(|entity| units.get_mut(entity))
+    else {
+        return;
+    };
+    if *visibility != Visibility::Hidden {
+        selectable.is_selected = true;
     }
-
-    false
 }
 
+#[allow(clippy::type_complexity)]
 fn deselect_unit(
-    window: Query<(&Window, With<PrimaryWindow>)>,
-    camera: Query<(&Camera, &GlobalTransform, With<Camera3d>)>,
-    mut units: Query<(&Collider, &mut Selectable, With<Unit>)>,
+    mut units: Query<(Entity, &mut Selectable), Or<(With<Unit>, With<Building>)>>,
+    hovered: Res<HoveredEntity>,
     input: Res<Input<MouseButton>>,
 ) {
-    let (window, _) = window.single();
-    let (camera, camera_transform, _) = camera.single();
-    for (unit_collider, mut selectable, _) in &mut units {
-        if !is_single_unit_selectable(camera, camera_transform, window, unit_collider)
-            && input.just_released(MouseButton::Left)
-        {
+    if !input.just_released(MouseButton::Left) {
+        return;
+    }
+    for (entity, mut selectable) in &mut units {
+        if hovered.0 != Some(entity) && selectable.is_selected {
             selectable.is_selected = false;
         }
     }
@@ -96,11 +125,12 @@ fn deselect_unit(
 fn create_selection_events(
     input: Res<Input<MouseButton>>,
     window: Query<(&Window, With<PrimaryWindow>)>,
+    hud_nodes: Query<(&RelativeCursorPosition, With<HudNode>)>,
     mut writer: EventWriter<SelectionEvent>,
 ) {
     let (window, _) = window.single();
     if let Some(cursor_position) = window.cursor_position() {
-        if input.just_pressed(MouseButton::Left) {
+        if input.just_pressed(MouseButton::Left) && !is_cursor_over_hud(hud_nodes) {
             writer.send(SelectionEvent::Start(Vec2::new(
                 cursor_position.x,
                 cursor_position.y,
@@ -157,8 +187,9 @@ fn despawn_selection(
 ) {
     for event in reader.iter() {
         if let SelectionEvent::End = event {
-            let (selection, _) = selection_query.single();
-            commands.entity(selection).despawn_recursive()
+            if let Ok((selection, _)) = selection_query.get_single() {
+                commands.entity(selection).despawn_recursive()
+            }
         }
     }
 }
@@ -196,3 +227,68 @@ fn set_selection_size(
         }
     }
 }
+
+/// Tells the simulation what the local player has selected whenever it changes
+/// Ctrl and a number key puts what the local player has selected into that control group,
+/// the key alone selects the group again
+fn use_control_groups(
+    mut selectables: Query<(&StableId, &mut Selectable, &Owner)>,
+    mut groups: ResMut<ControlGroups>,
+    input: Res<Input<KeyCode>>,
+    local_player: Res<LocalPlayer>,
+) {
+    let Some(group) = CONTROL_GROUP_KEYS
+        .iter()
+        .position(|key| input.just_pressed(*key))
+    else {
+        return;
+    };
+    if groups.0.len() < CONTROL_GROUP_KEYS.len() {
+        groups.0.resize(CONTROL_GROUP_KEYS.len(), Vec::new());
+    }
+
+    if input.any_pressed([KeyCode::LControl, KeyCode::RControl]) {
+        let mut units: Vec<StableId> = selectables
+            .iter()
+            .filter(|(_, selectable, owner)| selectable.is_selected && owner.0 == local_player.0)
+            .map(|(id, ..)| *id)
+            .collect();
+        units.sort_unstable();
+        groups.0[group] = units;
+    } else if !groups.0[group].is_empty() {
+        for (id, mut selectable, _) in &mut selectables {
+            let selected = groups.0[group].contains(id);
+            if selectable.is_selected != selected {
+                selectable.is_selected = selected;
+            }
+        }
+    }
+}
+
+fn clear_control_groups(mut commands: Commands) {
+    commands.insert_resource(ControlGroups::default());
+}
+
+fn send_selection(
+    changed: Query<(), Changed<Selectable>>,
+    selectables: Query<(&StableId, &Selectable)>,
+    mut sent: Local<Vec<StableId>>,
+    mut writer: EventWriter<PlayerCommand>,
+) {
+    if changed.is_empty() {
+        return;
+    }
+
+    let mut selected: Vec<StableId> = selectables
+        .iter()
+        .filter(|(_, selectable)| selectable.is_selected)
+        .map(|(id, _)| *id)
+        .collect();
+    selected.sort_unstable();
+    if *sent != selected {
+        writer.send(PlayerCommand::Select {
+            units: selected.clone(),
+        });
+        *sent = selected;
+    }
+}
@@ -0,0 +1,430 @@
+use std::time::Duration;
+
+use bevy::{
+    app::PluginGroupBuilder,
+    ecs::{event::Event, schedule::ExecutorKind},
+    prelude::*,
+    time::Stopwatch,
+    transform::TransformSystem,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abilities::AbilityPlugin,
+    buildings::BuildingPlugin,
+    combat::CombatPlugin,
+    command::CommandPlugin,
+    economy::EconomyPlugin,
+    fog::VisionPlugin,
+    game::GamePlugin,
+    ground::GroundPlugin,
+    ground::Terrain,
+    health::HealthPlugin,
+    map::CurrentMap,
+    map::MapPlugin,
+    navigation::NavigationPlugin,
+    network::{Lockstep, LockstepPlugin},
+    order::OrderPlugin,
+    player::PlayerPlugin,
+    research::ResearchPlugin,
+    snapshot::SnapshotApp,
+    stats::StatsPlugin,
+    supply::SupplyPlugin,
+    units::UnitPlugin,
+    GameState,
+};
+
+pub struct SimulationPlugin;
+
+/// Ticks of the simulation per second of game time
+pub const TICKS_PER_SECOND: u32 = 20;
+/// Game time that passes during one tick, whatever the game speed
+const TICK: Duration = Duration::from_nanos(1_000_000_000 / TICKS_PER_SECOND as u64);
+
+/// Speeds the game can run at, as multiples of real time
+const GAME_SPEEDS: [f32; 5] = [0.5, 1.0, 1.5, 2.0, 4.0];
+const NORMAL_SPEED: usize = 1;
+
+/// Game logic, which needs no window, camera or input and runs the same under `MinimalPlugins`
+pub struct SimulationPlugins;
+
+impl PluginGroup for SimulationPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(SimulationPlugin)
+            .add(PlayerPlugin)
+            .add(GamePlugin)
+            .add(CommandPlugin)
+            .add(GroundPlugin)
+            .add(MapPlugin)
+            .add(VisionPlugin)
+            .add(UnitPlugin)
+            .add(BuildingPlugin)
+            .add(EconomyPlugin)
+            .add(OrderPlugin)
+            .add(NavigationPlugin)
+            .add(HealthPlugin)
+            .add(SupplyPlugin)
+            .add(ResearchPlugin)
+            .add(StatsPlugin)
+            .add(CombatPlugin)
+            .add(AbilityPlugin)
+            .add(LockstepPlugin)
+    }
+}
+
+/// Game logic that advances on the fixed tick in `CoreSchedule::FixedUpdate`,
+/// systems go into one of its `StepSet`s
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimulationSet;
+
+/// The steps of a tick inside `SimulationSet`, one after the other in this order.
+/// Systems that share data must be in different steps or ordered within theirs, otherwise
+/// the order between them changes from run to run and so does the outcome of the tick
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StepSet {
+    /// Entities spawned in the last tick are put down on the terrain
+    Placement,
+    /// What the players see as the tick starts, its commands are checked against it
+    Vision,
+    /// Commands of the players for the tick
+    Commands,
+    Orders,
+    Research,
+    Production,
+    Abilities,
+    Movement,
+    Construction,
+    Economy,
+    Combat,
+    /// Whatever died in the tick is removed
+    Cleanup,
+}
+
+/// Runs first in every tick, even in those that `is_simulating` holds back.
+/// Lockstep games decide here whether the inputs for the tick have arrived
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TickStartSet;
+
+/// Spawning from the events of the tick. It runs after `SimulationSet`, so every simulation
+/// event is read in the tick it was sent in and nothing is left over between two ticks.
+/// The kinds spawn one after the other, in this order, so stable ids are handed out the same way
+/// in every run
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpawnSet {
+    Buildings,
+    Units,
+    ResourceNodes,
+}
+
+/// Systems that look at the whole state once a tick is complete and counted
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TickEndSet;
+
+/// Time inside the simulation, use it instead of `Time` in `SimulationSet` systems
+#[derive(Resource, Default, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct SimulationTime {
+    tick: u64,
+}
+
+impl SimulationTime {
+    /// Ticks simulated since the game started
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Game time since the game started
+    pub fn elapsed(&self) -> Duration {
+        TICK * self.tick as u32
+    }
+
+    pub fn delta(&self) -> Duration {
+        TICK
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        TICK.as_secs_f32()
+    }
+}
+
+/// Source of randomness for game logic, seeded by the game setup so that a game can be repeated
+#[derive(Resource, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct SimulationRng(u64);
+
+impl SimulationRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// SplitMix64, which gives the same sequence on every platform
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = self.0;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        value ^ (value >> 31)
+    }
+
+    /// Value in `0.0..1.0`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Identifies an entity of the simulation in every run of a game, unlike its `Entity`
+#[derive(
+    Component,
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Reflect,
+    FromReflect,
+    Serialize,
+    Deserialize,
+)]
+#[reflect(Component)]
+pub struct StableId(pub u32);
+
+/// Hands out stable ids in the order entities are spawned
+#[derive(Resource, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct StableIds {
+    next: u32,
+}
+
+impl StableIds {
+    pub fn next(&mut self) -> StableId {
+        self.next += 1;
+        StableId(self.next)
+    }
+}
+
+/// Game speed, which changes how often the simulation ticks but not what happens in a tick
+#[derive(Resource, Clone, Copy, PartialEq, Eq)]
+pub struct GameSpeed(usize);
+
+impl Default for GameSpeed {
+    fn default() -> Self {
+        Self(NORMAL_SPEED)
+    }
+}
+
+impl GameSpeed {
+    pub fn multiplier(self) -> f32 {
+        GAME_SPEEDS[self.0]
+    }
+
+    pub fn is_normal(self) -> bool {
+        self.0 == NORMAL_SPEED
+    }
+
+    pub fn name(self) -> String {
+        format!("{}x", self.multiplier())
+    }
+
+    pub fn faster(self) -> Self {
+        Self((self.0 + 1).min(GAME_SPEEDS.len() - 1))
+    }
+
+    pub fn slower(self) -> Self {
+        Self(self.0.saturating_sub(1))
+    }
+
+    /// Next faster speed, wrapping around to the slowest
+    pub fn next(self) -> Self {
+        Self((self.0 + 1) % GAME_SPEEDS.len())
+    }
+
+    /// Real time between two ticks
+    fn period(self) -> Duration {
+        TICK.div_f32(self.multiplier())
+    }
+}
+
+/// Transform of a moving entity at the last two ticks, it is drawn blended between them
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Interpolated {
+    previous: Transform,
+    current: Transform,
+}
+
+impl Interpolated {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            previous: transform,
+            current: transform,
+        }
+    }
+
+    /// Transform at the last tick, unlike `Transform` which is blended for drawing
+    pub fn simulated(&self) -> Transform {
+        self.current
+    }
+}
+
+/// Events of the simulation are kept for two ticks instead of two frames,
+/// so that none are lost in frames that run no tick
+pub trait SimulationEventApp {
+    fn add_simulation_event<T: Event>(&mut self) -> &mut Self;
+}
+
+impl SimulationEventApp for App {
+    fn add_simulation_event<T: Event>(&mut self) -> &mut Self {
+        self.init_resource::<Events<T>>().add_system(
+            Events::<T>::update_system
+                .run_if(is_simulating)
+                .after(TickStartSet)
+                .before(SimulationSet)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+    }
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<StableId>()
+            .add_snapshot_component::<Transform>()
+            .add_snapshot_component::<Interpolated>()
+            .add_snapshot_component::<Name>()
+            .add_snapshot_resource::<SimulationTime>()
+            .add_snapshot_resource::<SimulationRng>()
+            .add_snapshot_resource::<StableIds>()
+            // Timers are part of the state, and headless games run without the `TimePlugin`
+            .register_type::<Timer>()
+            .register_type::<TimerMode>()
+            .register_type::<Stopwatch>()
+            .init_resource::<SimulationTime>()
+            .init_resource::<SimulationRng>()
+            .init_resource::<StableIds>()
+            .init_resource::<GameSpeed>()
+            .insert_resource(FixedTime::new(TICK))
+            .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+                // Ticks are short, systems run one at a time in the order the sets give them
+                schedule
+                    .set_executor_kind(ExecutorKind::SingleThreaded)
+                    .configure_set(TickStartSet.before(SimulationSet))
+                    .configure_set(SimulationSet.run_if(is_simulating))
+                    .configure_sets(
+                        (
+                            StepSet::Placement,
+                            StepSet::Vision,
+                            StepSet::Commands,
+                            StepSet::Orders,
+                            StepSet::Research,
+                            StepSet::Production,
+                            StepSet::Abilities,
+                            StepSet::Movement,
+                            StepSet::Construction,
+                            StepSet::Economy,
+                            StepSet::Combat,
+                            StepSet::Cleanup,
+                        )
+                            .chain()
+                            .in_set(SimulationSet),
+                    )
+                    .configure_sets(
+                        (
+                            SpawnSet::Buildings,
+                            SpawnSet::Units,
+                            SpawnSet::ResourceNodes,
+                        )
+                            .chain()
+                            .after(SimulationSet),
+                    )
+                    .configure_set(SpawnSet::Buildings.run_if(is_simulating))
+                    .configure_set(SpawnSet::Units.run_if(is_simulating))
+                    .configure_set(SpawnSet::ResourceNodes.run_if(is_simulating))
+                    .configure_set(
+                        TickEndSet
+                            .after(record_transforms)
+                            .after(SpawnSet::ResourceNodes)
+                            .run_if(is_simulating),
+                    );
+            })
+            .add_systems(
+                (
+                    restore_transforms.after(TickStartSet).before(SimulationSet),
+                    record_transforms.after(SimulationSet),
+                )
+                    .distributive_run_if(is_simulating)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                interpolate_transforms
+                    .in_base_set(CoreSet::PostUpdate)
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_system(apply_game_speed.run_if(resource_changed::<GameSpeed>()))
+            .add_system(reset_simulation.in_schedule(OnEnter(GameState::MainMenu)));
+    }
+}
+
+/// The simulation runs while a game is played on a loaded map,
+/// and in lockstep games only once every player's inputs for the tick are there
+pub fn is_simulating(
+    state: Res<State<GameState>>,
+    map: Option<Res<CurrentMap>>,
+    terrain: Option<Res<Terrain>>,
+    lockstep: Option<Res<Lockstep>>,
+) -> bool {
+    state.0 == GameState::InGame
+        && map.is_some()
+        && terrain.is_some()
+        && lockstep.is_none_or(|lockstep| !lockstep.is_waiting())
+}
+
+/// Puts back the simulated transforms, so that the tick does not start from a blended one
+fn restore_transforms(mut entities: Query<(&mut Transform, &mut Interpolated)>) {
+    for (mut transform, mut interpolated) in &mut entities {
+        interpolated.previous = interpolated.current;
+        *transform = interpolated.current;
+    }
+}
+
+fn record_transforms(
+    mut entities: Query<(&Transform, &mut Interpolated)>,
+    mut time: ResMut<SimulationTime>,
+) {
+    for (transform, mut interpolated) in &mut entities {
+        interpolated.current = *transform;
+    }
+    time.tick += 1;
+}
+
+/// Blends between the last two ticks by how far the next one has come
+fn interpolate_transforms(
+    mut entities: Query<(&mut Transform, &Interpolated)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let progress =
+        (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).min(1.0);
+    for (mut transform, interpolated) in &mut entities {
+        let (previous, current) = (interpolated.previous, interpolated.current);
+        *transform = Transform {
+            translation: previous.translation.lerp(current.translation, progress),
+            rotation: previous.rotation.slerp(current.rotation, progress),
+            scale: previous.scale.lerp(current.scale, progress),
+        };
+    }
+}
+
+fn apply_game_speed(speed: Res<GameSpeed>, mut fixed_time: ResMut<FixedTime>) {
+    fixed_time.period = speed.period();
+}
+
+/// Starts the next game at tick zero and normal speed
+fn reset_simulation(mut commands: Commands) {
+    commands.insert_resource(SimulationTime::default());
+    commands.insert_resource(StableIds::default());
+    commands.insert_resource(GameSpeed::default());
+    commands.insert_resource(FixedTime::new(TICK));
+}
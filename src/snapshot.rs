@@ -0,0 +1,334 @@
+use std::any::TypeId;
+
+use bevy::{
+    ecs::reflect::{ReflectComponent, ReflectResource},
+    hierarchy::despawn_with_children_recursive,
+    prelude::*,
+    reflect::{
+        serde::{ReflectSerializer, UntypedReflectDeserializer},
+        GetTypeRegistration, ReflectMut, TypeRegistryInternal,
+    },
+    utils::HashMap,
+};
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
+
+use crate::simulation::{SimulationTime, StableId};
+
+/// Components and resources that hold the state of the simulation.
+/// Everything else is either fixed for the whole game or added again from these,
+/// like colliders, models and overlays
+#[derive(Resource, Debug, Default)]
+pub struct SnapshotTypes {
+    components: Vec<TypeId>,
+    resources: Vec<TypeId>,
+}
+
+/// Registers the state of the simulation, from the plugin that owns it.
+/// Types used inside of it need `register_type` as well, or saves can not be read back
+pub trait SnapshotApp {
+    /// The component needs `#[reflect(Component)]`
+    fn add_snapshot_component<T: Component + GetTypeRegistration>(&mut self) -> &mut Self;
+    /// The resource needs `#[reflect(Resource)]`
+    fn add_snapshot_resource<T: Resource + GetTypeRegistration>(&mut self) -> &mut Self;
+}
+
+impl SnapshotApp for App {
+    fn add_snapshot_component<T: Component + GetTypeRegistration>(&mut self) -> &mut Self {
+        self.register_type::<T>();
+        assert!(
+            self.world
+                .resource::<AppTypeRegistry>()
+                .read()
+                .get_type_data::<ReflectComponent>(TypeId::of::<T>())
+                .is_some(),
+            "{} needs #[reflect(Component)] to be part of snapshots",
+            std::any::type_name::<T>()
+        );
+
+        self.world
+            .get_resource_or_insert_with(SnapshotTypes::default)
+            .components
+            .push(TypeId::of::<T>());
+        self
+    }
+
+    fn add_snapshot_resource<T: Resource + GetTypeRegistration>(&mut self) -> &mut Self {
+        self.register_type::<T>();
+        assert!(
+            self.world
+                .resource::<AppTypeRegistry>()
+                .read()
+                .get_type_data::<ReflectResource>(TypeId::of::<T>())
+                .is_some(),
+            "{} needs #[reflect(Resource)] to be part of snapshots",
+            std::any::type_name::<T>()
+        );
+
+        self.world
+            .get_resource_or_insert_with(SnapshotTypes::default)
+            .resources
+            .push(TypeId::of::<T>());
+        self
+    }
+}
+
+/// Copy of the simulation between two ticks that it can be put back to
+pub struct Snapshot {
+    tick: u64,
+    resources: Vec<Box<dyn Reflect>>,
+    entities: Vec<SnapshotEntity>,
+}
+
+struct SnapshotEntity {
+    /// Entity at the time of the snapshot, which other components may refer to
+    entity: Entity,
+    components: Vec<Box<dyn Reflect>>,
+}
+
+/// Snapshot that can be written to a file. Every value is serialized through reflection,
+/// keyed by its type name, so only types in the registry can be read back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerializedSnapshot {
+    tick: u64,
+    resources: Vec<serde_yaml::Value>,
+    entities: Vec<SerializedEntity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SerializedEntity {
+    /// Bits of the entity at the time of the snapshot, the references in components use them
+    entity: u64,
+    components: Vec<serde_yaml::Value>,
+}
+
+impl Snapshot {
+    /// Copies the entities with a `StableId`, in the order of their ids
+    pub fn capture(world: &World) -> Self {
+        let types = world.resource::<SnapshotTypes>();
+        let registry = world.resource::<AppTypeRegistry>().read();
+
+        let resources = types
+            .resources
+            .iter()
+            .filter_map(|type_id| {
+                let reflect = registry.get_type_data::<ReflectResource>(*type_id)?;
+                Some(reflect.reflect(world)?.clone_value())
+            })
+            .collect();
+
+        let mut entities: Vec<_> = world
+            .iter_entities()
+            .filter_map(|entity| Some((*entity.get::<StableId>()?, entity)))
+            .collect();
+        entities.sort_by_key(|(id, _)| *id);
+        let entities = entities
+            .into_iter()
+            .map(|(_, entity)| SnapshotEntity {
+                entity: entity.id(),
+                components: types
+                    .components
+                    .iter()
+                    .filter_map(|type_id| {
+                        let reflect = registry.get_type_data::<ReflectComponent>(*type_id)?;
+                        Some(reflect.reflect(entity)?.clone_value())
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            tick: world
+                .get_resource::<SimulationTime>()
+                .map_or(0, SimulationTime::tick),
+            resources,
+            entities,
+        }
+    }
+
+    pub fn serialize(&self, registry: &TypeRegistryInternal) -> Result<SerializedSnapshot, String> {
+        let serialize = |value: &dyn Reflect| {
+            serde_yaml::to_value(ReflectSerializer::new(value, registry))
+                .map_err(|error| format!("{}: {}", value.type_name(), error))
+        };
+
+        Ok(SerializedSnapshot {
+            tick: self.tick,
+            resources: self
+                .resources
+                .iter()
+                .map(|resource| serialize(&**resource))
+                .collect::<Result<_, _>>()?,
+            entities: self
+                .entities
+                .iter()
+                .map(|entity| {
+                    Ok(SerializedEntity {
+                        entity: entity.entity.to_bits(),
+                        components: entity
+                            .components
+                            .iter()
+                            .map(|component| serialize(&**component))
+                            .collect::<Result<_, String>>()?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        })
+    }
+
+    /// Reads the values back as dynamic ones, which `restore` applies like any other
+    pub fn deserialize(
+        serialized: &SerializedSnapshot,
+        registry: &TypeRegistryInternal,
+    ) -> Result<Self, String> {
+        let deserialize = |value: &serde_yaml::Value| {
+            UntypedReflectDeserializer::new(registry)
+                .deserialize(value.clone())
+                .map_err(|error| error.to_string())
+        };
+
+        Ok(Self {
+            tick: serialized.tick,
+            resources: serialized
+                .resources
+                .iter()
+                .map(deserialize)
+                .collect::<Result<_, _>>()?,
+            entities: serialized
+                .entities
+                .iter()
+                .map(|entity| {
+                    Ok(SnapshotEntity {
+                        entity: Entity::from_bits(entity.entity),
+                        components: entity
+                            .components
+                            .iter()
+                            .map(deserialize)
+                            .collect::<Result<_, String>>()?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        })
+    }
+
+    /// Tick that runs next after the snapshot is restored
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Replaces the entities with a `StableId` by the ones of the snapshot.
+    /// They are spawned again, so references between them are moved over to the new entities
+    pub fn restore(&self, world: &mut World) {
+        let current: Vec<Entity> = world
+            .iter_entities()
+            .filter(|entity| entity.contains::<StableId>())
+            .map(|entity| entity.id())
+            .collect();
+        for entity in current {
+            despawn_with_children_recursive(world, entity);
+        }
+
+        let spawned: HashMap<Entity, Entity> = self
+            .entities
+            .iter()
+            .map(|entity| (entity.entity, world.spawn(SpatialBundle::default()).id()))
+            .collect();
+
+        let registry = world.resource::<AppTypeRegistry>().clone();
+        let registry = registry.read();
+        for entity in &self.entities {
+            for component in &entity.components {
+                let Some(reflect) = registry
+                    .get_with_name(component.type_name())
+                    .and_then(|registration| registration.data::<ReflectComponent>())
+                else {
+                    continue;
+                };
+
+                let mut component = component.clone_value();
+                map_entities(component.as_mut(), &spawned);
+                reflect.insert(&mut world.entity_mut(spawned[&entity.entity]), &*component);
+            }
+        }
+
+        for resource in &self.resources {
+            let Some(reflect) = registry
+                .get_with_name(resource.type_name())
+                .and_then(|registration| registration.data::<ReflectResource>())
+            else {
+                continue;
+            };
+
+            let mut resource = resource.clone_value();
+            map_entities(resource.as_mut(), &spawned);
+            reflect.insert(world, &*resource);
+        }
+    }
+}
+
+/// Points the entities inside a reflected value to the entities they were spawned again as,
+/// entities that were not part of the snapshot are left alone
+fn map_entities(value: &mut dyn Reflect, spawned: &HashMap<Entity, Entity>) {
+    if let Some(entity) = value.downcast_mut::<Entity>() {
+        if let Some(new) = spawned.get(entity) {
+            *entity = *new;
+        }
+        return;
+    }
+
+    match value.reflect_mut() {
+        ReflectMut::Struct(value) => {
+            for index in 0..value.field_len() {
+                if let Some(field) = value.field_at_mut(index) {
+                    map_entities(field, spawned);
+                }
+            }
+        }
+        ReflectMut::TupleStruct(value) => {
+            for index in 0..value.field_len() {
+                if let Some(field) = value.field_mut(index) {
+                    map_entities(field, spawned);
+                }
+            }
+        }
+        ReflectMut::Tuple(value) => {
+            for index in 0..value.field_len() {
+                if let Some(field) = value.field_mut(index) {
+                    map_entities(field, spawned);
+                }
+            }
+        }
+        ReflectMut::List(value) => {
+            for index in 0..value.len() {
+                if let Some(item) = value.get_mut(index) {
+                    map_entities(item, spawned);
+                }
+            }
+        }
+        ReflectMut::Array(value) => {
+            for index in 0..value.len() {
+                if let Some(item) = value.get_mut(index) {
+                    map_entities(item, spawned);
+                }
+            }
+        }
+        ReflectMut::Map(value) => {
+            // Keys can not be changed in place, no state is keyed by entity
+            let keys: Vec<Box<dyn Reflect>> = (0..value.len())
+                .filter_map(|index| Some(value.get_at(index)?.0.clone_value()))
+                .collect();
+            for key in keys {
+                if let Some(item) = value.get_mut(key.as_ref()) {
+                    map_entities(item, spawned);
+                }
+            }
+        }
+        ReflectMut::Enum(value) => {
+            for index in 0..value.field_len() {
+                if let Some(field) = value.field_at_mut(index) {
+                    map_entities(field, spawned);
+                }
+            }
+        }
+        ReflectMut::Value(_) => {}
+    }
+}
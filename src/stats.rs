@@ -0,0 +1,173 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::{
+    simulation::{SimulationTime, StepSet},
+    snapshot::SnapshotApp,
+};
+
+pub struct StatsPlugin;
+
+/// Value of a unit that upgrades and effects can change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, FromReflect, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stat {
+    Speed,
+    Damage,
+    Armor,
+    /// Distance from which the weapon can hit
+    Range,
+}
+
+/// Change to a stat, the final value is `(base + sum of add) * product of multiply`
+#[derive(Debug, Clone, Copy, Reflect, FromReflect, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Modifier {
+    pub stat: Stat,
+    #[serde(default)]
+    pub add: f32,
+    #[serde(default = "no_multiplier")]
+    pub multiply: f32,
+}
+
+fn no_multiplier() -> f32 {
+    1.0
+}
+
+/// What added a modifier, so that it can be taken away again
+#[derive(Debug, Clone, PartialEq, Eq, Reflect, FromReflect)]
+pub enum ModifierSource {
+    Research,
+    Ability,
+}
+
+#[derive(Debug, Clone, Reflect, FromReflect)]
+pub struct StatModifier {
+    pub modifier: Modifier,
+    pub source: ModifierSource,
+    /// Seconds left until a timed modifier runs out, permanent ones have none
+    pub remaining: Option<f32>,
+}
+
+/// Base values of an entity and the modifiers applied to them.
+/// The final values are cached and only calculated again when something changes.
+#[derive(Component, Debug, Default, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Stats {
+    base: HashMap<Stat, f32>,
+    modifiers: Vec<StatModifier>,
+    values: HashMap<Stat, f32>,
+}
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_component::<Stats>()
+            .register_type::<Stat>()
+            .register_type::<Modifier>()
+            .register_type::<ModifierSource>()
+            .register_type::<Option<f32>>()
+            .register_type::<StatModifier>()
+            .register_type::<Vec<StatModifier>>()
+            .register_type::<HashMap<Stat, f32>>()
+            .add_system(
+                expire_modifiers
+                    .in_set(StepSet::Orders)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+impl Stats {
+    pub fn new(base: impl IntoIterator<Item = (Stat, f32)>) -> Self {
+        let base: HashMap<Stat, f32> = base.into_iter().collect();
+        Self {
+            values: base.clone(),
+            base,
+            modifiers: Vec::new(),
+        }
+    }
+
+    /// Final value of the stat, zero if the entity does not have it
+    pub fn get(&self, stat: Stat) -> f32 {
+        self.values.get(&stat).copied().unwrap_or_default()
+    }
+
+    /// Adds a modifier that lasts for the given number of seconds, or forever without one
+    pub fn add_modifier(
+        &mut self,
+        modifier: Modifier,
+        source: ModifierSource,
+        duration: Option<f32>,
+    ) {
+        self.modifiers.push(StatModifier {
+            modifier,
+            source,
+            remaining: duration,
+        });
+        self.update_value(modifier.stat);
+    }
+
+    pub fn remove_modifiers(&mut self, source: &ModifierSource) {
+        self.modifiers.retain(|modifier| modifier.source != *source);
+        self.update_values();
+    }
+
+    fn update_value(&mut self, stat: Stat) {
+        let Some(base) = self.base.get(&stat) else {
+            return;
+        };
+
+        let modifiers = self.modifiers.iter().map(|modifier| &modifier.modifier);
+        let value = apply_modifiers(*base, stat, modifiers);
+        self.values.insert(stat, value);
+    }
+
+    fn update_values(&mut self) {
+        let stats: Vec<Stat> = self.base.keys().copied().collect();
+        for stat in stats {
+            self.update_value(stat);
+        }
+    }
+}
+
+/// Applies the modifiers of the given stat to its base value
+fn apply_modifiers<'a>(
+    base: f32,
+    stat: Stat,
+    modifiers: impl Iterator<Item = &'a Modifier>,
+) -> f32 {
+    let (add, multiply) = modifiers
+        .filter(|modifier| modifier.stat == stat)
+        .fold((0.0, 1.0), |(add, multiply), modifier| {
+            (add + modifier.add, multiply * modifier.multiply)
+        });
+
+    (base + add) * multiply
+}
+
+fn expire_modifiers(mut stats: Query<&mut Stats>, time: Res<SimulationTime>) {
+    for mut stats in &mut stats {
+        if stats
+            .modifiers
+            .iter()
+            .all(|modifier| modifier.remaining.is_none())
+        {
+            continue;
+        }
+
+        let delta = time.delta_seconds();
+        for modifier in &mut stats.modifiers {
+            if let Some(remaining) = &mut modifier.remaining {
+                *remaining -= delta;
+            }
+        }
+
+        let count = stats.modifiers.len();
+        stats
+            .modifiers
+            .retain(|modifier| modifier.remaining.is_none_or(|remaining| remaining > 0.0));
+        if stats.modifiers.len() != count {
+            stats.update_values();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::quick::ResourceInspectorPlugin;
+
+use crate::{
+    buildings::{Building, BuildingDefinitions, Construction, ProductionQueue},
+    player::{LocalPlayer, Owner},
+    simulation::StepSet,
+    snapshot::SnapshotApp,
+    units::{UnitDefinitions, UnitKind},
+    GameState,
+};
+
+pub struct SupplyPlugin;
+
+/// Supply inspector and messages for the local player
+pub struct SupplyViewPlugin;
+
+/// Highest population a player can have, no matter how many buildings provide supply
+const MAX_SUPPLY: u32 = 200;
+
+#[derive(Debug, Default, Clone, Copy, Reflect, FromReflect)]
+pub struct Supply {
+    /// Taken by living units and units in production queues
+    pub used: u32,
+    /// Provided by finished buildings
+    pub max: u32,
+}
+
+impl Supply {
+    pub fn has_room_for(&self, supply: u32) -> bool {
+        self.used + supply <= self.max
+    }
+}
+
+/// Population of every player, indexed by player
+#[derive(Resource, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct Supplies(Vec<Supply>);
+
+impl Supplies {
+    pub fn get(&self, owner: Owner) -> Supply {
+        self.0.get(owner.0 as usize).copied().unwrap_or_default()
+    }
+
+    pub fn get_mut(&mut self, owner: Owner) -> &mut Supply {
+        let index = owner.0 as usize;
+        if self.0.len() <= index {
+            self.0.resize(index + 1, Supply::default());
+        }
+        &mut self.0[index]
+    }
+}
+
+/// A unit could not be queued because its owner has no supply left
+pub struct SupplyCapped {
+    pub owner: Owner,
+}
+
+impl Plugin for SupplyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_snapshot_resource::<Supplies>()
+            .register_type::<Supply>()
+            .register_type::<Vec<Supply>>()
+            .init_resource::<Supplies>()
+            .add_event::<SupplyCapped>()
+            .add_system(
+                count_supply
+                    .in_set(StepSet::Orders)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+impl Plugin for SupplyViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(ResourceInspectorPlugin::<Supplies>::default())
+            .add_system(notify_supply_capped.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// Counts the supply again every frame, so that dead units, cancelled production
+/// and finished buildings are always accounted for
+fn count_supply(
+    units: Query<(&UnitKind, &Owner)>,
+    queues: Query<(&ProductionQueue, &Owner)>,
+    buildings: Query<(&Building, &Owner, Without<Construction>)>,
+    unit_definitions: Res<UnitDefinitions>,
+    building_definitions: Res<BuildingDefinitions>,
+    mut supplies: ResMut<Supplies>,
+) {
+    let mut counted = Supplies::default();
+
+    let queued = queues
+        .iter()
+        .flat_map(|(production, owner)| production.queue.iter().map(move |kind| (kind, owner)));
+    for (kind, owner) in units.iter().chain(queued) {
+        counted.get_mut(*owner).used += unit_definitions.get(*kind).supply;
+    }
+
+    for (building, owner, _) in &buildings {
+        let supply = counted.get_mut(*owner);
+        supply.max =
+            (supply.max + building_definitions.get(building.kind).provides_supply).min(MAX_SUPPLY);
+    }
+
+    *supplies = counted;
+}
+
+fn notify_supply_capped(mut reader: EventReader<SupplyCapped>, local_player: Res<LocalPlayer>) {
+    for event in reader.iter() {
+        if event.owner.0 == local_player.0 {
+            info!("not enough supply, build more houses");
+        }
+    }
+}
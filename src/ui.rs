@@ -0,0 +1,21 @@
+use bevy::prelude::*;
+
+pub struct UiPlugin;
+
+const FONT_PATH: &str = "fonts/DejaVuSans.ttf";
+
+/// Font of every text in the interface
+#[derive(Resource)]
+pub struct UiFont(pub Handle<Font>);
+
+impl FromWorld for UiFont {
+    fn from_world(world: &mut World) -> Self {
+        Self(world.resource::<AssetServer>().load(FONT_PATH))
+    }
+}
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<UiFont>();
+    }
+}
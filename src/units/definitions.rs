@@ -0,0 +1,81 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::{economy::Cost, hotkeys::letter_key_code};
+
+use super::UnitKind;
+
+const UNIT_DEFINITIONS: &str = include_str!("../../assets/data/units.yaml");
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnitDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub cost: Cost,
+    /// Seconds a building needs to train the unit
+    pub train_time: f32,
+    /// Population the unit takes up while it is alive or being trained
+    pub supply: u32,
+    pub health: f32,
+    pub sight_range: f32,
+    /// Distance the unit walks per second
+    pub speed: f32,
+    /// Subtracted from the damage of every hit the unit takes
+    #[serde(default)]
+    pub armor: f32,
+    /// Units without a weapon can not attack
+    pub weapon: Option<WeaponDefinition>,
+    /// Most energy the unit can store for its abilities
+    pub energy: Option<f32>,
+    /// Ids of abilities in `assets/data/abilities.yaml`, in command card order
+    #[serde(default)]
+    pub abilities: Vec<String>,
+    /// Letter that queues the unit while a building that trains it is selected
+    pub hotkey: Option<char>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeaponDefinition {
+    pub damage: f32,
+    pub range: f32,
+    /// Seconds between two hits
+    pub cooldown: f32,
+}
+
+impl UnitDefinition {
+    pub fn hotkey(&self) -> Option<KeyCode> {
+        self.hotkey.and_then(letter_key_code)
+    }
+}
+
+/// Stats of every unit kind, read from `assets/data/units.yaml`
+#[derive(Resource, Debug)]
+pub struct UnitDefinitions(HashMap<UnitKind, UnitDefinition>);
+
+impl Default for UnitDefinitions {
+    fn default() -> Self {
+        let definitions: HashMap<UnitKind, UnitDefinition> =
+            serde_yaml::from_str(UNIT_DEFINITIONS).expect("invalid unit definitions");
+        for kind in UnitKind::ALL {
+            assert!(
+                definitions.contains_key(&kind),
+                "unit {:?} has no definition",
+                kind
+            );
+        }
+
+        Self(definitions)
+    }
+}
+
+impl UnitDefinitions {
+    pub fn get(&self, kind: UnitKind) -> &UnitDefinition {
+        &self.0[&kind]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (UnitKind, &UnitDefinition)> {
+        self.0.iter().map(|(kind, definition)| (*kind, definition))
+    }
+}
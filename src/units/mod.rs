@@ -1,15 +1,35 @@
+mod definitions;
 mod movement;
 mod setup;
 
 use bevy::prelude::*;
 
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abilities::AbilityTarget, fog::SightRange, player::Owner, selection::Selectable,
+    simulation::SimulationEventApp, snapshot::SnapshotApp,
+};
+
 use self::{movement::UnitMovementPlugin, setup::UnitSetupPlugin};
 
+pub use self::{
+    definitions::UnitDefinitions,
+    movement::{approach_point, has_arrived},
+    setup::{UnitViewPlugin, UNIT_SIZE},
+};
+
 pub struct UnitPlugin;
 
 impl Plugin for UnitPlugin {
     fn build(&self, app: &mut App) {
-        app.register_type::<Unit>()
+        app.add_snapshot_component::<Unit>()
+            .add_snapshot_component::<UnitKind>()
+            .add_snapshot_component::<Selectable>()
+            .add_snapshot_component::<SightRange>()
+            .register_type::<UnitState>()
+            .add_simulation_event::<SpawnUnit>()
+            .init_resource::<UnitDefinitions>()
             .add_plugin(UnitMovementPlugin)
             .add_plugin(UnitSetupPlugin);
     }
@@ -18,11 +38,102 @@ impl Plugin for UnitPlugin {
 #[derive(Default, Reflect)]
 pub enum UnitState {
     Moving(Vec3),
+    /// Walking to `position` next to a resource node, then harvesting it
+    Gathering {
+        node: Entity,
+        position: Vec3,
+    },
+    /// Carrying resources to `position` next to a drop-off
+    ReturningCargo {
+        dropoff: Entity,
+        position: Vec3,
+    },
+    /// Walking to `position` next to a building site, then constructing it
+    Constructing {
+        site: Entity,
+        position: Vec3,
+    },
+    /// Walking to `position` until the target is in weapon range, then attacking it
+    Attacking {
+        target: Entity,
+        position: Vec3,
+    },
+    /// Walking to `position` until the target is in range of the ability, then using it
+    Casting {
+        ability: String,
+        target: AbilityTarget,
+        position: Vec3,
+    },
     #[default]
     Idle,
 }
 
+impl UnitState {
+    /// Point the unit is walking towards
+    pub fn destination(&self) -> Option<Vec3> {
+        match self {
+            UnitState::Moving(destination)
+            | UnitState::Gathering {
+                position: destination,
+                ..
+            }
+            | UnitState::ReturningCargo {
+                position: destination,
+                ..
+            }
+            | UnitState::Constructing {
+                position: destination,
+                ..
+            }
+            | UnitState::Attacking {
+                position: destination,
+                ..
+            }
+            | UnitState::Casting {
+                position: destination,
+                ..
+            } => Some(*destination),
+            UnitState::Idle => None,
+        }
+    }
+}
+
+#[derive(
+    Component,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Reflect,
+    FromReflect,
+    Serialize,
+    Deserialize,
+)]
+#[reflect(Component)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitKind {
+    #[default]
+    Worker,
+    Soldier,
+}
+
+impl UnitKind {
+    pub const ALL: [UnitKind; 2] = [UnitKind::Worker, UnitKind::Soldier];
+}
+
 #[derive(Component, Default, Reflect)]
+#[reflect(Component)]
 pub struct Unit {
     pub state: UnitState,
 }
+
+pub struct SpawnUnit {
+    pub kind: UnitKind,
+    pub owner: Owner,
+    pub position: Vec3,
+    /// Where the unit walks to after spawning
+    pub rally_point: Option<Vec3>,
+}
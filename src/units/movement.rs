@@ -1,27 +1,79 @@
 use bevy::prelude::*;
 
-use crate::GameState;
+use crate::{
+    ground::Terrain,
+    simulation::{SimulationTime, StepSet},
+    stats::{Stat, Stats},
+};
 
-use super::{Unit, UnitState};
+use super::{setup::UNIT_SIZE, Unit, UnitState};
 
-const UNIT_SPEED: f32 = 5.0;
+/// Distance at which a unit counts as standing at its destination
+const ARRIVAL_DISTANCE: f32 = 0.1;
 
 pub struct UnitMovementPlugin;
 
 impl Plugin for UnitMovementPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(move_units.in_set(OnUpdate(GameState::InGame)));
+        app.add_systems(
+            (
+                move_units,
+                follow_terrain.run_if(resource_exists::<Terrain>()),
+            )
+                .chain()
+                .in_set(StepSet::Movement)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
     }
 }
 
-pub fn move_units(mut units: Query<(&Unit, &mut Transform)>, time: Res<Time>) {
-    for (unit, mut transform) in &mut units {
-        if let UnitState::Moving(destination) = unit.state {
-            let up = transform.up();
-            transform.look_at(destination, up);
-            let direction = transform.forward();
-            let direction = Vec3::new(direction.x, 0.0, direction.z);
-            transform.translation += direction * time.delta_seconds() * UNIT_SPEED;
+pub fn move_units(
+    mut units: Query<(&mut Unit, &mut Transform, &Stats)>,
+    time: Res<SimulationTime>,
+) {
+    for (mut unit, mut transform, stats) in &mut units {
+        let Some(destination) = unit.state.destination() else {
+            continue;
+        };
+
+        // Units stand on uneven terrain, so only move and turn in the horizontal plane
+        let target = Vec3::new(destination.x, transform.translation.y, destination.z);
+        let distance = transform.translation.distance(target);
+        if distance <= ARRIVAL_DISTANCE {
+            if let UnitState::Moving(_) = unit.state {
+                unit.state = UnitState::Idle;
+            }
+            continue;
+        }
+
+        transform.look_at(target, Vec3::Y);
+        let direction = (target - transform.translation) / distance;
+        let speed = stats.get(Stat::Speed);
+        transform.translation += direction * (time.delta_seconds() * speed).min(distance);
+    }
+}
+
+fn follow_terrain(mut units: Query<(&mut Transform, With<Unit>)>, terrain: Res<Terrain>) {
+    for (mut transform, _) in &mut units {
+        let height = terrain.height_at(transform.translation.x, transform.translation.z);
+        let y = height + UNIT_SIZE / 2.0;
+        if (transform.translation.y - y).abs() > f32::EPSILON {
+            transform.translation.y = y
         }
     }
 }
+
+/// Point next to a square of the given size, on the side facing `from`
+pub fn approach_point(target: Vec3, size: f32, from: Vec3) -> Vec3 {
+    let offset = Vec3::new(from.x - target.x, 0.0, from.z - target.z);
+    let direction = offset.try_normalize().unwrap_or(Vec3::X);
+    // Half of the diagonal keeps the point clear of the corners
+    let distance = size * std::f32::consts::FRAC_1_SQRT_2 + UNIT_SIZE;
+    target + direction * distance
+}
+
+/// Whether the unit stands close enough to a point it was walking to
+pub fn has_arrived(transform: &Transform, position: Vec3) -> bool {
+    let offset = transform.translation - position;
+    Vec2::new(offset.x, offset.z).length() <= ARRIVAL_DISTANCE * 2.0
+}
@@ -1,56 +1,145 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
 use bevy_rapier3d::prelude::{Collider, RigidBody};
 
-use crate::{order::Orders, selection::Selectable, GameState};
+use crate::{
+    abilities::{Abilities, Energy},
+    combat::Weapon,
+    economy::Worker,
+    fog::SightRange,
+    health::Health,
+    order::{Order, Orders},
+    player::{Owner, Players},
+    selection::Selectable,
+    simulation::{Interpolated, SpawnSet, StableIds},
+    stats::{Stat, Stats},
+};
 
-use super::Unit;
+use super::{SpawnUnit, Unit, UnitDefinitions, UnitKind};
 
-const UNIT_SIZE: f32 = 0.5;
-
-const NORMAL_COLOR: Color = Color::rgba(0.9, 0.6, 0.1, 1.0);
-const HIGHLIHT_COLOR: Color = Color::rgba(0.9, 0.8, 0.5, 0.9);
+pub const UNIT_SIZE: f32 = 0.5;
 
 pub struct UnitSetupPlugin;
 
+/// Gives units a model in the colour of their owner
+pub struct UnitViewPlugin;
+
+#[derive(Resource)]
+struct UnitMesh(Handle<Mesh>);
+
+impl FromWorld for UnitMesh {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        Self(meshes.add(shape::Cube::new(UNIT_SIZE).into()))
+    }
+}
+
+/// One material per player, shared by all of their units
+#[derive(Resource, Default)]
+struct UnitMaterials(HashMap<Owner, Handle<StandardMaterial>>);
+
 impl Plugin for UnitSetupPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_unit)
-            .add_systems((handle_highlight,).in_set(OnUpdate(GameState::InGame)));
+        app.add_system(
+            spawn_units
+                .in_set(SpawnSet::Units)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(add_unit_bodies);
     }
 }
 
-fn spawn_unit(
+impl Plugin for UnitViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<UnitMesh>()
+            .init_resource::<UnitMaterials>()
+            .add_system(add_unit_models);
+    }
+}
+
+fn spawn_units(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut reader: EventReader<SpawnUnit>,
+    definitions: Res<UnitDefinitions>,
+    mut ids: ResMut<StableIds>,
 ) {
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(shape::Cube::new(UNIT_SIZE).into()),
-            material: materials.add(NORMAL_COLOR.into()),
-            transform: Transform::from_xyz(0.0, UNIT_SIZE / 2.0, 0.0),
-            ..default()
-        },
-        Collider::cuboid(UNIT_SIZE / 2.0, UNIT_SIZE / 2.0, UNIT_SIZE / 2.0),
-        RigidBody::KinematicPositionBased,
-        Name::from("Unit"),
-        Unit::default(),
-        Selectable::default(),
-        Orders::default(),
-    ));
+    for event in reader.iter() {
+        let definition = definitions.get(event.kind);
+        let mut orders = Orders::default();
+        if let Some(rally_point) = event.rally_point {
+            orders.queue(Order::Move(rally_point));
+        }
+
+        let transform = Transform::from_xyz(
+            event.position.x,
+            event.position.y + UNIT_SIZE / 2.0,
+            event.position.z,
+        );
+        let mut unit = commands.spawn((
+            SpatialBundle::from_transform(transform),
+            Interpolated::new(transform),
+            ids.next(),
+            Name::from(definition.name.as_str()),
+            Unit::default(),
+            Selectable::default(),
+            orders,
+            Health::new(definition.health),
+            SightRange(definition.sight_range),
+            event.kind,
+            event.owner,
+        ));
+
+        let mut stats = vec![
+            (Stat::Speed, definition.speed),
+            (Stat::Armor, definition.armor),
+        ];
+        if let Some(weapon) = &definition.weapon {
+            stats.extend([(Stat::Damage, weapon.damage), (Stat::Range, weapon.range)]);
+            unit.insert(Weapon::new(weapon.cooldown));
+        }
+        unit.insert(Stats::new(stats));
+
+        if let Some(energy) = definition.energy {
+            unit.insert(Energy::new(energy));
+        }
+        if !definition.abilities.is_empty() {
+            unit.insert(Abilities::new(definition.abilities.clone()));
+        }
+
+        if event.kind == UnitKind::Worker {
+            unit.insert(Worker::default());
+        }
+    }
 }
 
-fn handle_highlight(
-    units: Query<(With<Unit>, &Handle<StandardMaterial>, &Selectable)>,
+/// Added to every new unit instead of at spawning, so that units restored from a snapshot get one too
+fn add_unit_bodies(mut commands: Commands, units: Query<Entity, Added<Unit>>) {
+    for entity in &units {
+        commands.entity(entity).insert((
+            Collider::cuboid(UNIT_SIZE / 2.0, UNIT_SIZE / 2.0, UNIT_SIZE / 2.0),
+            RigidBody::KinematicPositionBased,
+        ));
+    }
+}
+
+fn add_unit_models(
+    mut commands: Commands,
+    units: Query<(Entity, &Owner), Added<Unit>>,
+    mesh: Res<UnitMesh>,
+    mut unit_materials: ResMut<UnitMaterials>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    players: Res<Players>,
 ) {
-    for (_, handle, selectable) in &units {
-        if let Some(material) = materials.get_mut(handle) {
-            if selectable.is_selected {
-                material.base_color = HIGHLIHT_COLOR;
-            } else {
-                material.base_color = NORMAL_COLOR;
-            }
-        }
+    // Colours are picked again for every game
+    if players.is_changed() {
+        unit_materials.0.clear();
+    }
+
+    for (entity, owner) in &units {
+        let material = unit_materials
+            .0
+            .entry(*owner)
+            .or_insert_with(|| materials.add(players.color(*owner).into()))
+            .clone();
+        commands.entity(entity).insert((mesh.0.clone(), material));
     }
 }